@@ -4,36 +4,232 @@ use iced::widget::canvas::{
 };
 use iced::widget::canvas::{LineCap, LineDash, LineJoin, Stroke};
 use iced::{Theme, Font};
+use iced::widget::image::Handle;
 use iced::{Color, Point, Rectangle, Renderer, Size, Vector};
-use std::cell::RefCell;
+use iced_graphics::Primitive;
+use crate::transform::Transform;
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+/// Lato in pixel della maniglia per ridimensionare un'immagine selezionata
+const RESIZE_HANDLE: f32 = 10.0;
+/// Lato minimo di un'immagine ridimensionata, in pixel dell'immagine
+const MIN_IMAGE_SIZE: f32 = 8.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum FillStyle {
     Fill,
     Stroke(f32),
 }
 
-#[derive(Debug, Clone)]
+/// Strumento con cui vengono disegnate le nuove annotazioni. In JSON i
+/// colori sono componenti rgba tra 0 e 1 e il font è descritto da
+/// [`TextFont`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum Tool {
     Rectangle {
+        #[serde(with = "serde_color")]
+        color: Color,
+        fill_style: FillStyle,
+    },
+    Ellipse {
+        #[serde(with = "serde_color")]
         color: Color,
         fill_style: FillStyle,
     },
     Arrow {
+        #[serde(with = "serde_color")]
         color: Color,
         stroke_width: f32,
     },
     Text {
+        #[serde(with = "serde_color")]
         color: Color,
         content: String,
         size: f32,
+        #[serde(with = "serde_font", default)]
         font: Font
     },
     FreeHand {
+        #[serde(with = "serde_color")]
         color: Color,
         stroke_width: f32,
     },
+    /// Timbro centrato nel punto del click, grande scale volte l'immagine
+    Image {
+        #[serde(with = "serde_png")]
+        image: Arc<RgbaImage>,
+        scale: f32,
+        opacity: f32,
+    },
+}
+
+/// Peso del font di un testo, con gli stessi valori di iced::font::Weight
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FontWeight {
+    Thin,
+    ExtraLight,
+    Light,
+    #[default]
+    Normal,
+    Medium,
+    Semibold,
+    Bold,
+    ExtraBold,
+    Black,
+}
+
+impl FontWeight {
+    /// Valore numerico del peso, come in CSS e SVG
+    pub fn css_weight(self) -> u16 {
+        match self {
+            FontWeight::Thin => 100,
+            FontWeight::ExtraLight => 200,
+            FontWeight::Light => 300,
+            FontWeight::Normal => 400,
+            FontWeight::Medium => 500,
+            FontWeight::Semibold => 600,
+            FontWeight::Bold => 700,
+            FontWeight::ExtraBold => 800,
+            FontWeight::Black => 900,
+        }
+    }
+}
+
+impl From<iced::font::Weight> for FontWeight {
+    fn from(weight: iced::font::Weight) -> Self {
+        use iced::font::Weight;
+        match weight {
+            Weight::Thin => FontWeight::Thin,
+            Weight::ExtraLight => FontWeight::ExtraLight,
+            Weight::Light => FontWeight::Light,
+            Weight::Normal => FontWeight::Normal,
+            Weight::Medium => FontWeight::Medium,
+            Weight::Semibold => FontWeight::Semibold,
+            Weight::Bold => FontWeight::Bold,
+            Weight::ExtraBold => FontWeight::ExtraBold,
+            Weight::Black => FontWeight::Black,
+        }
+    }
+}
+
+impl From<FontWeight> for iced::font::Weight {
+    fn from(weight: FontWeight) -> Self {
+        use iced::font::Weight;
+        match weight {
+            FontWeight::Thin => Weight::Thin,
+            FontWeight::ExtraLight => Weight::ExtraLight,
+            FontWeight::Light => Weight::Light,
+            FontWeight::Normal => Weight::Normal,
+            FontWeight::Medium => Weight::Medium,
+            FontWeight::Semibold => Weight::Semibold,
+            FontWeight::Bold => Weight::Bold,
+            FontWeight::ExtraBold => Weight::ExtraBold,
+            FontWeight::Black => Weight::Black,
+        }
+    }
+}
+
+/// Font di un testo in forma serializzabile
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextFont {
+    /// Nome della famiglia del font, None per il font di default
+    #[serde(default)]
+    pub family: Option<String>,
+    #[serde(default)]
+    pub weight: FontWeight,
+}
+
+impl From<Font> for TextFont {
+    fn from(font: Font) -> Self {
+        Self {
+            family: match font.family {
+                iced::font::Family::Name(name) => Some(name.to_string()),
+                _ => None,
+            },
+            weight: font.weight.into(),
+        }
+    }
+}
+
+impl From<&TextFont> for Font {
+    fn from(font: &TextFont) -> Self {
+        let base = font
+            .family
+            .as_deref()
+            .and_then(font_name)
+            .map_or(Font::default(), Font::with_name);
+        Font {
+            weight: font.weight.into(),
+            ..base
+        }
+    }
+}
+
+/// Serializza un Color come [r, g, b, a]
+mod serde_color {
+    use iced::Color;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, s: S) -> Result<S::Ok, S::Error> {
+        super::rgba(color).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Color, D::Error> {
+        let [r, g, b, a] = <[f32; 4]>::deserialize(d)?;
+        Ok(Color::from_rgba(r, g, b, a))
+    }
+}
+
+/// Serializza un Font come [`super::TextFont`]
+mod serde_font {
+    use super::TextFont;
+    use iced::Font;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(font: &Font, s: S) -> Result<S::Ok, S::Error> {
+        TextFont::from(*font).serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Font, D::Error> {
+        Ok(Font::from(&TextFont::deserialize(d)?))
+    }
+}
+
+/// Serializza un'immagine come Png codificato in base64
+mod serde_png {
+    use base64::Engine;
+    use image::RgbaImage;
+    use serde::de::Error as _;
+    use serde::ser::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    pub fn serialize<S: Serializer>(image: &Arc<RgbaImage>, s: S) -> Result<S::Ok, S::Error> {
+        let mut png = Cursor::new(Vec::new());
+        image
+            .write_to(&mut png, image::ImageFormat::Png)
+            .map_err(S::Error::custom)?;
+        base64::engine::general_purpose::STANDARD
+            .encode(png.get_ref())
+            .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Arc<RgbaImage>, D::Error> {
+        let png = base64::engine::general_purpose::STANDARD
+            .decode(String::deserialize(d)?)
+            .map_err(D::Error::custom)?;
+        let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+            .map_err(D::Error::custom)?;
+        Ok(Arc::new(image.to_rgba8()))
+    }
 }
 
 #[derive(Clone)]
@@ -43,6 +239,12 @@ enum PrivTool {
         fill_style: FillStyle,
         rec: Rectangle,
     },
+    /// Ellisse inscritta nel rettangolo rec
+    Ellipse {
+        color: Color,
+        fill_style: FillStyle,
+        rec: Rectangle,
+    },
     Arrow {
         color: Color,
         stroke_width: f32,
@@ -55,6 +257,15 @@ enum PrivTool {
         stroke_width: f32,
         points: Vec<Point>,
     },
+    /// Immagine incollata o timbro, disegnata nel rettangolo rec. handle
+    /// contiene i pixel di image con l'opacità già applicata, pronti per il
+    /// renderer.
+    Image {
+        image: Arc<RgbaImage>,
+        handle: Handle,
+        rec: Rectangle,
+        opacity: f32,
+    },
 }
 
 impl From<Tool> for PrivTool {
@@ -65,6 +276,11 @@ impl From<Tool> for PrivTool {
                 fill_style,
                 rec: Rectangle::default(),
             },
+            Tool::Ellipse { color, fill_style } => PrivTool::Ellipse {
+                color,
+                fill_style,
+                rec: Rectangle::default(),
+            },
             Tool::Arrow {
                 color,
                 stroke_width,
@@ -96,14 +312,613 @@ impl From<Tool> for PrivTool {
                 stroke_width,
                 points: Vec::with_capacity(300),
             },
+            Tool::Image {
+                image,
+                scale,
+                opacity,
+            } => {
+                let size = Size::new(image.width() as f32 * scale, image.height() as f32 * scale);
+                PrivTool::image(image, Rectangle::with_size(size), opacity)
+            }
+        }
+    }
+}
+
+/// Annotazione già disegnata, con la geometria in coordinate dell'immagine
+/// originale e i colori come componenti rgba tra 0 e 1. È la forma con cui
+/// le annotazioni vengono salvate nei progetti.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Annotation {
+    Rectangle {
+        color: [f32; 4],
+        fill_style: FillStyle,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    Ellipse {
+        color: [f32; 4],
+        fill_style: FillStyle,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    Arrow {
+        color: [f32; 4],
+        stroke_width: f32,
+        begin: [f32; 2],
+        end: [f32; 2],
+    },
+    /// Testo centrato in (x, y)
+    Text {
+        color: [f32; 4],
+        content: String,
+        size: f32,
+        x: f32,
+        y: f32,
+        #[serde(default)]
+        font: TextFont,
+    },
+    FreeHand {
+        color: [f32; 4],
+        stroke_width: f32,
+        points: Vec<[f32; 2]>,
+    },
+    /// Immagine disegnata nel rettangolo indicato, in JSON come Png in base64
+    Image {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        #[serde(with = "serde_png")]
+        image: Arc<RgbaImage>,
+        #[serde(default = "opaque")]
+        opacity: f32,
+    },
+}
+
+impl Annotation {
+    /// Annotazione per un'immagine scalata di sx in orizzontale e sy in
+    /// verticale. Spessori e dimensione del testo seguono la media geometrica
+    /// delle due scale.
+    pub fn scaled(&self, sx: f32, sy: f32) -> Annotation {
+        let s = (sx * sy).sqrt();
+        let point = |[x, y]: [f32; 2]| [x * sx, y * sy];
+        let fill = |fill_style: &FillStyle| match fill_style {
+            FillStyle::Fill => FillStyle::Fill,
+            FillStyle::Stroke(w) => FillStyle::Stroke(w * s),
+        };
+        match self.clone() {
+            Annotation::Rectangle {
+                color,
+                fill_style,
+                x,
+                y,
+                width,
+                height,
+            } => Annotation::Rectangle {
+                color,
+                fill_style: fill(&fill_style),
+                x: x * sx,
+                y: y * sy,
+                width: width * sx,
+                height: height * sy,
+            },
+            Annotation::Ellipse {
+                color,
+                fill_style,
+                x,
+                y,
+                width,
+                height,
+            } => Annotation::Ellipse {
+                color,
+                fill_style: fill(&fill_style),
+                x: x * sx,
+                y: y * sy,
+                width: width * sx,
+                height: height * sy,
+            },
+            Annotation::Arrow {
+                color,
+                stroke_width,
+                begin,
+                end,
+            } => Annotation::Arrow {
+                color,
+                stroke_width: stroke_width * s,
+                begin: point(begin),
+                end: point(end),
+            },
+            Annotation::Text {
+                color,
+                content,
+                size,
+                x,
+                y,
+                font,
+            } => Annotation::Text {
+                color,
+                content,
+                size: size * s,
+                x: x * sx,
+                y: y * sy,
+                font,
+            },
+            Annotation::FreeHand {
+                color,
+                stroke_width,
+                points,
+            } => Annotation::FreeHand {
+                color,
+                stroke_width: stroke_width * s,
+                points: points.into_iter().map(point).collect(),
+            },
+            Annotation::Image {
+                x,
+                y,
+                width,
+                height,
+                image,
+                opacity,
+            } => Annotation::Image {
+                x: x * sx,
+                y: y * sy,
+                width: width * sx,
+                height: height * sy,
+                image,
+                opacity,
+            },
+        }
+    }
+}
+
+impl Annotation {
+    /// Annotazione per un'immagine di width x height trasformata con
+    /// transform. I timbri vengono trasformati insieme allo screenshot, i
+    /// testi restano orizzontali e viene spostato solo il loro centro.
+    pub fn transformed(&self, transform: Transform, width: f32, height: f32) -> Annotation {
+        let point = |p: [f32; 2]| transform.point(p, width, height);
+        let rect = |x, y, w, h| transform.rect([x, y, w, h], width, height);
+        match self.clone() {
+            Annotation::Rectangle {
+                color,
+                fill_style,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let [x, y, width, height] = rect(x, y, width, height);
+                Annotation::Rectangle {
+                    color,
+                    fill_style,
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+            }
+            Annotation::Ellipse {
+                color,
+                fill_style,
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let [x, y, width, height] = rect(x, y, width, height);
+                Annotation::Ellipse {
+                    color,
+                    fill_style,
+                    x,
+                    y,
+                    width,
+                    height,
+                }
+            }
+            Annotation::Arrow {
+                color,
+                stroke_width,
+                begin,
+                end,
+            } => Annotation::Arrow {
+                color,
+                stroke_width,
+                begin: point(begin),
+                end: point(end),
+            },
+            Annotation::Text {
+                color,
+                content,
+                size,
+                x,
+                y,
+                font,
+            } => {
+                let [x, y] = point([x, y]);
+                Annotation::Text {
+                    color,
+                    content,
+                    size,
+                    x,
+                    y,
+                    font,
+                }
+            }
+            Annotation::FreeHand {
+                color,
+                stroke_width,
+                points,
+            } => Annotation::FreeHand {
+                color,
+                stroke_width,
+                points: points.into_iter().map(point).collect(),
+            },
+            Annotation::Image {
+                x,
+                y,
+                width,
+                height,
+                image,
+                opacity,
+            } => {
+                let [x, y, width, height] = rect(x, y, width, height);
+                Annotation::Image {
+                    x,
+                    y,
+                    width,
+                    height,
+                    image: Arc::new(transform.apply(&image)),
+                    opacity,
+                }
+            }
         }
     }
 }
 
+impl From<&PrivTool> for Annotation {
+    fn from(tool: &PrivTool) -> Self {
+        match tool {
+            PrivTool::Rectangle {
+                color,
+                fill_style,
+                rec,
+            } => Annotation::Rectangle {
+                color: rgba(color),
+                fill_style: fill_style.clone(),
+                x: rec.x,
+                y: rec.y,
+                width: rec.width,
+                height: rec.height,
+            },
+            PrivTool::Ellipse {
+                color,
+                fill_style,
+                rec,
+            } => Annotation::Ellipse {
+                color: rgba(color),
+                fill_style: fill_style.clone(),
+                x: rec.x,
+                y: rec.y,
+                width: rec.width,
+                height: rec.height,
+            },
+            PrivTool::Arrow {
+                color,
+                stroke_width,
+                begin,
+                end,
+            } => Annotation::Arrow {
+                color: rgba(color),
+                stroke_width: *stroke_width,
+                begin: [begin.x, begin.y],
+                end: [end.x, end.y],
+            },
+            PrivTool::Text(txt) => Annotation::Text {
+                color: rgba(&txt.color),
+                content: txt.content.clone(),
+                size: txt.size,
+                x: txt.position.x,
+                y: txt.position.y,
+                font: txt.font.into(),
+            },
+            PrivTool::FreeHand {
+                color,
+                stroke_width,
+                points,
+            } => Annotation::FreeHand {
+                color: rgba(color),
+                stroke_width: *stroke_width,
+                points: points.iter().map(|p| [p.x, p.y]).collect(),
+            },
+            PrivTool::Image {
+                image,
+                rec,
+                opacity,
+                ..
+            } => {
+                let rec = normalize(*rec);
+                Annotation::Image {
+                    x: rec.x,
+                    y: rec.y,
+                    width: rec.width,
+                    height: rec.height,
+                    image: image.clone(),
+                    opacity: *opacity,
+                }
+            }
+        }
+    }
+}
+
+impl From<Annotation> for PrivTool {
+    fn from(annotation: Annotation) -> Self {
+        let color = |[r, g, b, a]: [f32; 4]| Color::from_rgba(r, g, b, a);
+        match annotation {
+            Annotation::Rectangle {
+                color: c,
+                fill_style,
+                x,
+                y,
+                width,
+                height,
+            } => PrivTool::Rectangle {
+                color: color(c),
+                fill_style,
+                rec: Rectangle::new(Point::new(x, y), Size::new(width, height)),
+            },
+            Annotation::Ellipse {
+                color: c,
+                fill_style,
+                x,
+                y,
+                width,
+                height,
+            } => PrivTool::Ellipse {
+                color: color(c),
+                fill_style,
+                rec: Rectangle::new(Point::new(x, y), Size::new(width, height)),
+            },
+            Annotation::Arrow {
+                color: c,
+                stroke_width,
+                begin,
+                end,
+            } => PrivTool::Arrow {
+                color: color(c),
+                stroke_width,
+                begin: Point::new(begin[0], begin[1]),
+                end: Point::new(end[0], end[1]),
+            },
+            Annotation::Text {
+                color: c,
+                content,
+                size,
+                x,
+                y,
+                font,
+            } => PrivTool::Text(Text {
+                content,
+                position: Point::new(x, y),
+                color: color(c),
+                size,
+                horizontal_alignment: iced::alignment::Horizontal::Center,
+                vertical_alignment: iced::alignment::Vertical::Center,
+                font: Font::from(&font),
+                ..Text::default()
+            }),
+            Annotation::FreeHand {
+                color: c,
+                stroke_width,
+                points,
+            } => PrivTool::FreeHand {
+                color: color(c),
+                stroke_width,
+                points: points.into_iter().map(|[x, y]| Point::new(x, y)).collect(),
+            },
+            Annotation::Image {
+                x,
+                y,
+                width,
+                height,
+                image,
+                opacity,
+            } => PrivTool::image(
+                image,
+                Rectangle::new(Point::new(x, y), Size::new(width, height)),
+                opacity,
+            ),
+        }
+    }
+}
+
+/// Versione del formato scritta da [`Annotations::to_json`]
+pub const ANNOTATIONS_VERSION: u32 = 1;
+
+/// Insieme di annotazioni esportato in JSON, con la dimensione dell'immagine
+/// su cui sono state disegnate
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnnotationSet {
+    pub version: u32,
+    pub width: f32,
+    pub height: f32,
+    #[serde(default)]
+    pub annotations: Vec<Annotation>,
+}
+
+fn opaque() -> f32 {
+    1.0
+}
+
+fn rgba(color: &Color) -> [f32; 4] {
+    [color.r, color.g, color.b, color.a]
+}
+
+/// Famiglie di font che un'annotazione può usare oltre a quello di default
+const FONT_FAMILIES: [&str; 1] = ["Tektur"];
+
+/// I font di iced sono identificati da un nome &'static str: i nomi letti
+/// da JSON vengono cercati tra quelli noti, gli altri usano il font di default
+fn font_name(name: &str) -> Option<&'static str> {
+    FONT_FAMILIES.iter().find(|f| **f == name).copied()
+}
+
+impl PrivTool {
+    fn image(image: Arc<RgbaImage>, rec: Rectangle, opacity: f32) -> Self {
+        let opacity = opacity.clamp(0.0, 1.0);
+        let mut pixels = image.as_raw().clone();
+        if opacity < 1.0 {
+            for p in pixels.chunks_exact_mut(4) {
+                p[3] = (p[3] as f32 * opacity).round() as u8;
+            }
+        }
+        let handle = Handle::from_pixels(image.width(), image.height(), pixels);
+        PrivTool::Image {
+            image,
+            handle,
+            rec,
+            opacity,
+        }
+    }
+
+    /// Controlla se il punto p, in coordinate dell'immagine, cade
+    /// sull'annotazione. tolerance allarga l'area selezionabile dei tratti.
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool {
+        // Distanza del punto p dal segmento a-b
+        let segment_distance = |a: Point, b: Point| {
+            let ab = b - a;
+            let len2 = ab.x * ab.x + ab.y * ab.y;
+            if len2 == 0.0 {
+                return p.distance(a);
+            }
+            let t = (((p.x - a.x) * ab.x + (p.y - a.y) * ab.y) / len2).clamp(0.0, 1.0);
+            p.distance(a + ab * t)
+        };
+
+        match self {
+            PrivTool::Rectangle { rec, fill_style, .. } => {
+                let rec = normalize(*rec);
+                match fill_style {
+                    FillStyle::Fill => expand(rec, tolerance).contains(p),
+                    //Di una forma vuota conta solo il bordo, non l'interno
+                    FillStyle::Stroke(w) => {
+                        let half = w * 25.0 / 2.0 + tolerance;
+                        expand(rec, half).contains(p) && !expand(rec, -half).contains(p)
+                    }
+                }
+            }
+            PrivTool::Ellipse { rec, fill_style, .. } => {
+                let rec = normalize(*rec);
+                let (rx, ry) = (rec.width / 2.0, rec.height / 2.0);
+                let center = Point::new(rec.x + rx, rec.y + ry);
+                //Posizione di p rispetto a un'ellisse con i semiassi allargati
+                //di amount: minore o uguale a 1 se p è all'interno
+                let inside = |amount: f32| {
+                    let (ax, ay) = (rx + amount, ry + amount);
+                    if ax <= 0.0 || ay <= 0.0 {
+                        return false;
+                    }
+                    let (dx, dy) = ((p.x - center.x) / ax, (p.y - center.y) / ay);
+                    dx * dx + dy * dy <= 1.0
+                };
+                match fill_style {
+                    FillStyle::Fill => inside(tolerance),
+                    FillStyle::Stroke(w) => {
+                        let half = w * 25.0 / 2.0 + tolerance;
+                        inside(half) && !inside(-half)
+                    }
+                }
+            }
+            PrivTool::Arrow {
+                begin,
+                end,
+                stroke_width,
+                ..
+            } => segment_distance(*begin, *end) <= stroke_width * 25.0 / 2.0 + tolerance,
+            PrivTool::FreeHand {
+                points,
+                stroke_width,
+                ..
+            } => {
+                let max = stroke_width * 25.0 / 2.0 + tolerance;
+                match points.as_slice() {
+                    [] => false,
+                    [single] => p.distance(*single) <= max,
+                    _ => points.windows(2).any(|w| segment_distance(w[0], w[1]) <= max),
+                }
+            }
+            PrivTool::Text(txt) => text_bounds(txt).contains(p),
+            PrivTool::Image { rec, .. } => expand(normalize(*rec), tolerance).contains(p),
+        }
+    }
+
+    /// Rettangolo che contiene l'annotazione, in coordinate dell'immagine
+    fn bounds(&self) -> Rectangle {
+        match self {
+            PrivTool::Rectangle { rec, .. }
+            | PrivTool::Ellipse { rec, .. }
+            | PrivTool::Image { rec, .. } => normalize(*rec),
+            PrivTool::Arrow { begin, end, .. } => Rectangle::new(
+                Point::new(begin.x.min(end.x), begin.y.min(end.y)),
+                Size::new((begin.x - end.x).abs(), (begin.y - end.y).abs()),
+            ),
+            PrivTool::FreeHand { points, .. } => {
+                let Some(first) = points.first() else {
+                    return Rectangle::default();
+                };
+                let (min, max) = points.iter().fold((*first, *first), |(min, max), p| {
+                    (
+                        Point::new(min.x.min(p.x), min.y.min(p.y)),
+                        Point::new(max.x.max(p.x), max.y.max(p.y)),
+                    )
+                });
+                Rectangle::new(min, Size::new(max.x - min.x, max.y - min.y))
+            }
+            PrivTool::Text(txt) => text_bounds(txt),
+        }
+    }
+}
+
+/// Restituisce il rettangolo con larghezza e altezza positive, perché
+/// disegnando verso sinistra o verso l'alto risultano negative
+fn normalize(rec: Rectangle) -> Rectangle {
+    Rectangle::new(
+        Point::new(rec.x.min(rec.x + rec.width), rec.y.min(rec.y + rec.height)),
+        Size::new(rec.width.abs(), rec.height.abs()),
+    )
+}
+
+/// Allarga il rettangolo di amount su ogni lato
+fn expand(rec: Rectangle, amount: f32) -> Rectangle {
+    Rectangle::new(
+        Point::new(rec.x - amount, rec.y - amount),
+        Size::new(rec.width + 2.0 * amount, rec.height + 2.0 * amount),
+    )
+}
+
+/// Stima lo spazio occupato da un testo centrato in position. La larghezza
+/// dei caratteri non è nota, quindi si usa una larghezza media.
+fn text_bounds(txt: &Text) -> Rectangle {
+    let height = txt.size * 25.0;
+    let width = txt.content.chars().count() as f32 * height * 0.6;
+    Rectangle::new(
+        Point::new(txt.position.x - width / 2.0, txt.position.y - height / 2.0),
+        Size::new(width, height),
+    )
+}
+
 #[derive(Clone)]
 pub struct Annotations<Message: Clone> {
     tool: Rc<RefCell<Option<PrivTool>>>,
     baked_geometry: Rc<RefCell<Vec<PrivTool>>>,
+    /// Indice in baked_geometry dell'annotazione selezionata
+    selected: Rc<Cell<Option<usize>>>,
+    /// Annotazioni eliminate, con la loro posizione, per poterle ripristinare
+    deleted: Rc<RefCell<Vec<(usize, PrivTool)>>>,
     image_size: Size<f32>,
     crop_area: Rectangle<f32>,
     new_annotation_msg: Message,
@@ -112,14 +927,26 @@ pub struct Annotations<Message: Clone> {
 #[derive(Debug, Default)]
 pub struct State {
     mouse_pressed: bool,
+    /// Trascinamento in corso dell'immagine selezionata
+    drag: Option<Drag>,
     cache: iced::widget::canvas::Cache,
 }
 
+#[derive(Debug, Clone, Copy)]
+enum Drag {
+    /// Spostamento, con la distanza dall'angolo in alto a sinistra al cursore
+    Move(Vector),
+    /// Ridimensionamento dall'angolo in basso a destra
+    Resize,
+}
+
 impl<Message: Clone> Annotations<Message> {
     pub fn new(image_size: Size<f32>, new_annotation_msg: Message) -> Self {
         Self {
             tool: Rc::new(None.into()),
             baked_geometry: Default::default(),
+            selected: Default::default(),
+            deleted: Default::default(),
             image_size,
             crop_area: Rectangle::with_size(image_size),
             new_annotation_msg,
@@ -128,15 +955,54 @@ impl<Message: Clone> Annotations<Message> {
 
     pub fn undo_annotation(&mut self) {
         self.baked_geometry.borrow_mut().pop();
+        self.selected.set(None);
+    }
+
+    /// Seleziona l'annotazione con l'indice indicato, o nessuna se None.
+    /// Restituisce la selezione precedente.
+    pub fn select(&mut self, index: Option<usize>) -> Option<usize> {
+        self.selected.replace(index)
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected.get()
+    }
+
+    /// Elimina l'annotazione selezionata. Restituisce true se c'era
+    /// un'annotazione da eliminare.
+    pub fn delete_selected(&mut self) -> bool {
+        let Some(index) = self.selected.take() else {
+            return false;
+        };
+        let mut baked = self.baked_geometry.borrow_mut();
+        if index >= baked.len() {
+            return false;
+        }
+        let tool = baked.remove(index);
+        self.deleted.borrow_mut().push((index, tool));
+        true
+    }
+
+    /// Ripristina l'ultima annotazione eliminata nella sua posizione originale
+    pub fn restore_deleted(&mut self) {
+        if let Some((index, tool)) = self.deleted.borrow_mut().pop() {
+            let mut baked = self.baked_geometry.borrow_mut();
+            let index = index.min(baked.len());
+            baked.insert(index, tool);
+            self.selected.set(Some(index));
+        }
     }
 
     pub fn clear_annotations(&mut self) {
         self.baked_geometry.borrow_mut().clear();
+        self.deleted.borrow_mut().clear();
+        self.selected.set(None);
         *self.tool.borrow_mut() = None;
         self.crop_area = Rectangle::with_size(self.image_size);
     }
 
     pub fn set_tool(&mut self, tool: Option<Tool>) {
+        self.selected.set(None);
         if let Some(t) = tool {
             *self.tool.borrow_mut() = Some(t.into());
         } else {
@@ -144,6 +1010,86 @@ impl<Message: Clone> Annotations<Message> {
         }
     }
 
+    /// Annotazioni disegnate, dalla più vecchia alla più recente
+    pub fn annotations(&self) -> Vec<Annotation> {
+        self.baked_geometry.borrow().iter().map(Into::into).collect()
+    }
+
+    /// Annotazioni eliminate che restore_deleted può ripristinare, con la
+    /// posizione che avevano
+    pub fn deleted(&self) -> Vec<(usize, Annotation)> {
+        self.deleted
+            .borrow()
+            .iter()
+            .map(|(i, t)| (*i, t.into()))
+            .collect()
+    }
+
+    /// Sostituisce tutte le annotazioni, ad esempio aprendo un progetto
+    pub fn set_annotations(&mut self, annotations: Vec<Annotation>, deleted: Vec<(usize, Annotation)>) {
+        *self.baked_geometry.borrow_mut() = annotations.into_iter().map(Into::into).collect();
+        *self.deleted.borrow_mut() = deleted.into_iter().map(|(i, a)| (i, a.into())).collect();
+        self.selected.set(None);
+    }
+
+    /// Esporta le annotazioni disegnate, senza quelle eliminate
+    pub fn export_annotations(&self) -> AnnotationSet {
+        AnnotationSet {
+            version: ANNOTATIONS_VERSION,
+            width: self.image_size.width,
+            height: self.image_size.height,
+            annotations: self.annotations(),
+        }
+    }
+
+    /// Sostituisce le annotazioni con quelle importate. La geometria è in
+    /// coordinate dell'immagine e non viene adattata se la dimensione è diversa.
+    pub fn import_annotations(&mut self, set: AnnotationSet) -> crate::Result<()> {
+        if set.version > ANNOTATIONS_VERSION {
+            return Err(crate::Error::Annotations(format!(
+                "version {} was written by a newer release",
+                set.version
+            )));
+        }
+        self.set_annotations(set.annotations, Vec::new());
+        Ok(())
+    }
+
+    /// Esporta le annotazioni come JSON, vedi [`AnnotationSet`]
+    pub fn to_json(&self) -> crate::Result<String> {
+        serde_json::to_string_pretty(&self.export_annotations())
+            .map_err(|e| crate::Error::Annotations(e.to_string()))
+    }
+
+    /// Importa le annotazioni scritte da [`Annotations::to_json`]
+    pub fn load_json(&mut self, json: &str) -> crate::Result<()> {
+        let set = serde_json::from_str(json).map_err(|e| crate::Error::Annotations(e.to_string()))?;
+        self.import_annotations(set)
+    }
+
+    /// Aggiunge image come annotazione, al centro dell'area visibile e
+    /// rimpicciolita se non ci sta. La nuova annotazione viene selezionata.
+    pub fn insert_image(&mut self, image: RgbaImage) {
+        let area = self.crop_area;
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let fit = (area.width * 0.8 / width)
+            .min(area.height * 0.8 / height)
+            .min(1.0);
+        let size = Size::new(width * fit, height * fit);
+        let top_left = Point::new(
+            area.x + (area.width - size.width) / 2.0,
+            area.y + (area.height - size.height) / 2.0,
+        );
+
+        let mut baked = self.baked_geometry.borrow_mut();
+        baked.push(PrivTool::image(
+            Arc::new(image),
+            Rectangle::new(top_left, size),
+            1.0,
+        ));
+        self.selected.set(Some(baked.len() - 1));
+    }
+
     pub fn set_crop(&mut self, rec: Rectangle<f32>) -> Rectangle<f32> {
         let ret = self.crop_area;
         self.crop_area = rec;
@@ -155,6 +1101,47 @@ impl<Message: Clone> Annotations<Message> {
         self.crop_area = Rectangle::with_size(size);
     }
 
+    /// Adatta tutte le annotazioni, anche quelle eliminate, a un'immagine
+    /// ridimensionata a size. Come set_image_size, annulla il crop.
+    pub fn resize(&mut self, size: Size<f32>) {
+        let sx = size.width / self.image_size.width;
+        let sy = size.height / self.image_size.height;
+        let annotations = self
+            .annotations()
+            .iter()
+            .map(|a| a.scaled(sx, sy))
+            .collect();
+        let deleted = self
+            .deleted()
+            .iter()
+            .map(|(i, a)| (*i, a.scaled(sx, sy)))
+            .collect();
+        self.set_annotations(annotations, deleted);
+        self.set_image_size(size);
+    }
+
+    /// Trasforma tutte le annotazioni, anche quelle eliminate, insieme
+    /// all'immagine. Come set_image_size, annulla il crop.
+    pub fn transform(&mut self, transform: Transform) {
+        let Size { width, height } = self.image_size;
+        let annotations = self
+            .annotations()
+            .iter()
+            .map(|a| a.transformed(transform, width, height))
+            .collect();
+        let deleted = self
+            .deleted()
+            .iter()
+            .map(|(i, a)| (*i, a.transformed(transform, width, height)))
+            .collect();
+        self.set_annotations(annotations, deleted);
+        self.set_image_size(if transform.swaps_axes() {
+            Size::new(height, width)
+        } else {
+            Size::new(width, height)
+        });
+    }
+
     fn paint(
         &self,
         frame: &mut Frame,
@@ -209,6 +1196,30 @@ impl<Message: Clone> Annotations<Message> {
                     }
                 }
             }
+            PrivTool::Ellipse {
+                color,
+                rec,
+                fill_style,
+            } => {
+                let ellipse_path = ellipse(*rec).transform(&t);
+
+                match fill_style {
+                    FillStyle::Fill => frame.fill(&ellipse_path, *color),
+                    FillStyle::Stroke(width) => {
+                        let stroke = Stroke {
+                            style: Style::Solid(*color),
+                            width: *width * scale * 25.0,
+                            line_cap: LineCap::Round,
+                            line_join: LineJoin::Round,
+                            line_dash: LineDash::default(),
+                        };
+
+                        frame.with_clip(Rectangle::with_size(frame.size()), |f| {
+                            f.stroke(&ellipse_path, stroke);
+                        });
+                    }
+                }
+            }
             PrivTool::Arrow {
                 color,
                 stroke_width,
@@ -220,19 +1231,21 @@ impl<Message: Clone> Annotations<Message> {
                 let end_point = Point::new(end.x, end.y);
 
                 let arrow_angle = (end_point.y - start_point.y).atan2(end_point.x - start_point.x);
+                let head =
+                    crate::svg::arrow_head_length(self.image_size.width, self.image_size.height);
 
                 let upper_angle =
                     arrow_angle + std::f32::consts::FRAC_PI_2 + std::f32::consts::FRAC_PI_3;
                 let upper_stroke_point = Point::new(
-                    end_point.x + upper_angle.cos() * 0.05 * self.image_size.height,
-                    end_point.y + upper_angle.sin() * 0.05 * self.image_size.height,
+                    end_point.x + upper_angle.cos() * head,
+                    end_point.y + upper_angle.sin() * head,
                 );
 
                 let lower_angle =
                     arrow_angle - std::f32::consts::FRAC_PI_2 - std::f32::consts::FRAC_PI_3;
                 let lower_stroke_point = Point::new(
-                    end_point.x + lower_angle.cos() * 0.05 * self.image_size.height,
-                    end_point.y + lower_angle.sin() * 0.05 * self.image_size.height,
+                    end_point.x + lower_angle.cos() * head,
+                    end_point.y + lower_angle.sin() * head,
                 );
 
                 let stroke = Stroke {
@@ -288,8 +1301,198 @@ impl<Message: Clone> Annotations<Message> {
                     });
                 }
             }
+            //Il Frame non disegna immagini, vedi image_geometry
+            PrivTool::Image { .. } => {}
+        };
+    }
+
+    /// Disegna un'immagine incollata come primitiva a sé, ritagliata sulla
+    /// dimensione del canvas
+    fn image_geometry(
+        &self,
+        handle: &Handle,
+        rec: Rectangle,
+        scale: f32,
+        translation_vector: &Vector<f32>,
+        size: Size,
+    ) -> Geometry {
+        let rec = normalize(rec);
+        let bounds = Rectangle::new(
+            Point::new(
+                (rec.x + translation_vector.x) * scale,
+                (rec.y + translation_vector.y) * scale,
+            ),
+            Size::new(rec.width * scale, rec.height * scale),
+        );
+        Geometry::from_primitive(Primitive::Clip {
+            bounds: Rectangle::with_size(size),
+            content: Box::new(Primitive::Image {
+                handle: handle.clone(),
+                bounds,
+            }),
+        })
+    }
+
+    /// Disegna l'annotazione in corso e il contorno di quella selezionata
+    fn paint_overlay(
+        &self,
+        frame: &mut Frame,
+        scale: f32,
+        translation_vector: &Vector<f32>,
+        cursor_over: bool,
+    ) {
+        if cursor_over {
+            if let Some(t) = self.tool.borrow().as_ref() {
+                self.paint(frame, t, scale, translation_vector);
+            }
+        }
+
+        //Contorno tratteggiato intorno all'annotazione selezionata
+        let baked = self.baked_geometry.borrow();
+        let Some(t) = self.selected.get().and_then(|i| baked.get(i)) else {
+            return;
         };
+        let color = Color::from_rgb(0.0, 0.47, 0.84);
+        let rec = expand(t.bounds(), 0.01 * self.crop_area.height);
+        let top_left = Point::new(
+            (rec.x + translation_vector.x) * scale,
+            (rec.y + translation_vector.y) * scale,
+        );
+        let size = Size::new(rec.width * scale, rec.height * scale);
+        frame.stroke(
+            &Path::rectangle(top_left, size),
+            Stroke {
+                style: Style::Solid(color),
+                width: 2.0,
+                line_dash: LineDash {
+                    segments: &[6.0, 4.0],
+                    offset: 0,
+                },
+                ..Stroke::default()
+            },
+        );
+
+        //Le immagini si ridimensionano trascinando l'angolo in basso a destra
+        if let PrivTool::Image { rec, .. } = t {
+            let rec = normalize(*rec);
+            let corner = Point::new(
+                (rec.x + rec.width + translation_vector.x) * scale - RESIZE_HANDLE / 2.0,
+                (rec.y + rec.height + translation_vector.y) * scale - RESIZE_HANDLE / 2.0,
+            );
+            frame.fill_rectangle(corner, Size::new(RESIZE_HANDLE, RESIZE_HANDLE), color);
+        }
     }
+
+    /// Gestisce gli eventi quando non c'è uno strumento: un click seleziona
+    /// l'annotazione più in alto sotto al cursore, e le immagini selezionate
+    /// possono essere spostate o ridimensionate trascinandole
+    fn update_selection(
+        &self,
+        state: &mut State,
+        event: Event,
+        cursor_position: Point,
+        scale: f32,
+    ) -> event::Status {
+        match event {
+            Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
+                let baked = self.baked_geometry.borrow();
+                let selected = self.selected.get().and_then(|i| baked.get(i));
+                if let Some(PrivTool::Image { rec, .. }) = selected {
+                    let rec = normalize(*rec);
+                    let corner = Point::new(rec.x + rec.width, rec.y + rec.height);
+                    if cursor_position.distance(corner) <= RESIZE_HANDLE / scale {
+                        state.drag = Some(Drag::Resize);
+                        return event::Status::Captured;
+                    }
+                }
+
+                let tolerance = 0.005 * self.crop_area.height;
+                let hit = baked
+                    .iter()
+                    .rposition(|t| t.hit_test(cursor_position, tolerance));
+                self.selected.set(hit);
+                state.drag = match hit.and_then(|i| baked.get(i)) {
+                    Some(PrivTool::Image { rec, .. }) => {
+                        let rec = normalize(*rec);
+                        Some(Drag::Move(cursor_position - Point::new(rec.x, rec.y)))
+                    }
+                    _ => None,
+                };
+                //Un click nel vuoto toglie la selezione ma resta disponibile
+                //agli altri widget
+                match hit {
+                    Some(_) => event::Status::Captured,
+                    None => event::Status::Ignored,
+                }
+            }
+            Event::Mouse(mouse::Event::CursorMoved { .. }) => {
+                let Some(drag) = state.drag else {
+                    return event::Status::Ignored;
+                };
+                let mut baked = self.baked_geometry.borrow_mut();
+                let selected = self.selected.get().and_then(|i| baked.get_mut(i));
+                let Some(PrivTool::Image { image, rec, .. }) = selected else {
+                    state.drag = None;
+                    return event::Status::Ignored;
+                };
+                *rec = normalize(*rec);
+                match drag {
+                    Drag::Move(offset) => {
+                        rec.x = cursor_position.x - offset.x;
+                        rec.y = cursor_position.y - offset.y;
+                    }
+                    //Il ridimensionamento mantiene le proporzioni dell'immagine
+                    Drag::Resize => {
+                        let ratio = image.height() as f32 / image.width() as f32;
+                        let width = (cursor_position.x - rec.x)
+                            .max((cursor_position.y - rec.y) / ratio)
+                            .max(MIN_IMAGE_SIZE);
+                        rec.width = width;
+                        rec.height = width * ratio;
+                    }
+                }
+                event::Status::Captured
+            }
+            _ => event::Status::Ignored,
+        }
+    }
+}
+
+/// Costruisce un'ellisse inscritta in rec approssimandola con quattro curve
+/// di Bézier cubiche, una per quadrante
+fn ellipse(rec: Rectangle) -> Path {
+    // Distanza dei punti di controllo dagli estremi, per un arco di 90°
+    const KAPPA: f32 = 0.552_284_8;
+
+    let rec = normalize(rec);
+    let (rx, ry) = (rec.width / 2.0, rec.height / 2.0);
+    let (cx, cy) = (rec.x + rx, rec.y + ry);
+    let (ox, oy) = (rx * KAPPA, ry * KAPPA);
+
+    let mut builder = path::Builder::new();
+    builder.move_to(Point::new(cx + rx, cy));
+    builder.bezier_curve_to(
+        Point::new(cx + rx, cy + oy),
+        Point::new(cx + ox, cy + ry),
+        Point::new(cx, cy + ry),
+    );
+    builder.bezier_curve_to(
+        Point::new(cx - ox, cy + ry),
+        Point::new(cx - rx, cy + oy),
+        Point::new(cx - rx, cy),
+    );
+    builder.bezier_curve_to(
+        Point::new(cx - rx, cy - oy),
+        Point::new(cx - ox, cy - ry),
+        Point::new(cx, cy - ry),
+    );
+    builder.bezier_curve_to(
+        Point::new(cx + ox, cy - ry),
+        Point::new(cx + rx, cy - oy),
+        Point::new(cx + rx, cy),
+    );
+    builder.close();
+    builder.build()
 }
 
 //Specifica come le cose vanno disegnate dentro il canvas
@@ -305,6 +1508,13 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
     ) -> (event::Status, Option<Message>) {
         state.cache.clear();
 
+        //Il trascinamento termina anche se il tasto viene rilasciato fuori dal canvas
+        if let Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left)) = event {
+            if state.drag.take().is_some() {
+                return (event::Status::Captured, None);
+            }
+        }
+
         let cursor_position = if let Some(cp) = cursor.position_in(&bounds) {
             Point::new(
                 cp.x / bounds.width * self.crop_area.width + self.crop_area.x,
@@ -318,7 +1528,11 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
         let tool: &mut PrivTool = if let Some(t) = opt_tool.as_mut() {
             t
         } else {
-            return (event::Status::Ignored, None);
+            let scale = bounds.height / self.crop_area.height;
+            return (
+                self.update_selection(state, event, cursor_position, scale),
+                None,
+            );
         };
 
         match tool {
@@ -329,6 +1543,11 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
                 };
                 *tool = PrivTool::Text(text);
             }
+            //Come il testo, il timbro segue il cursore e viene fissato al rilascio
+            PrivTool::Image { ref mut rec, .. } => {
+                rec.x = cursor_position.x - rec.width / 2.0;
+                rec.y = cursor_position.y - rec.height / 2.0;
+            }
             _ => (),
         };
 
@@ -336,7 +1555,8 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
             Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)) => {
                 state.mouse_pressed = true;
                 match tool {
-                    PrivTool::Rectangle { ref mut rec, .. } => {
+                    PrivTool::Rectangle { ref mut rec, .. }
+                    | PrivTool::Ellipse { ref mut rec, .. } => {
                         rec.x = cursor_position.x;
                         rec.y = cursor_position.y;
                     }
@@ -348,7 +1568,7 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
                         *begin = cursor_position;
                         *end = cursor_position;
                     }
-                    PrivTool::Text(_) => {}
+                    PrivTool::Text(_) | PrivTool::Image { .. } => {}
                     PrivTool::FreeHand { ref mut points, .. } => points.push(cursor_position),
                 }
                 return (event::Status::Captured, None);
@@ -360,7 +1580,8 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
 
                 // Reset tools to avoid showing glitchy annotations
                 match tool {
-                    PrivTool::Rectangle { ref mut rec, .. } => *rec = Rectangle::default(),
+                    PrivTool::Rectangle { ref mut rec, .. }
+                    | PrivTool::Ellipse { ref mut rec, .. } => *rec = Rectangle::default(),
                     PrivTool::Arrow {
                         ref mut begin,
                         ref mut end,
@@ -373,6 +1594,7 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
                         txt.position = Point::ORIGIN;
                     }
                     PrivTool::FreeHand { ref mut points, .. } => points.clear(),
+                    PrivTool::Image { .. } => {}
                 }
                 return (
                     event::Status::Captured,
@@ -382,8 +1604,9 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
             Event::Mouse(mouse::Event::CursorMoved { .. }) => {
                 if state.mouse_pressed {
                     match tool {
-                        PrivTool::Text(_) => {}
-                        PrivTool::Rectangle { ref mut rec, .. } => {
+                        PrivTool::Text(_) | PrivTool::Image { .. } => {}
+                        PrivTool::Rectangle { ref mut rec, .. }
+                        | PrivTool::Ellipse { ref mut rec, .. } => {
                             rec.width = cursor_position.x - rec.x;
                             rec.height = cursor_position.y - rec.y;
                         }
@@ -419,28 +1642,67 @@ impl<Message: Clone> Program<Message, Renderer> for Annotations<Message> {
             self.image_size.height * bounds.height / self.crop_area.height / self.image_size.height;
         let translation_vector = Vector::new(-self.crop_area.x, -self.crop_area.y);
 
-        let g = state.cache.draw(renderer, bounds.size(), |f| {
-            for t in self.baked_geometry.borrow().iter() {
-                self.paint(f, t, scale, &translation_vector);
-            }
-
-            if cursor.is_over(&bounds) {
-                if let Some(t) = self.tool.borrow().as_ref() {
+        let cursor_over = cursor.is_over(&bounds);
+        let baked = self.baked_geometry.borrow();
+        let tool = self.tool.borrow();
+        //Anteprima del timbro che verrà appoggiato
+        let stamp = match tool.as_ref() {
+            Some(PrivTool::Image { handle, rec, .. }) if cursor_over => Some((handle, *rec)),
+            _ => None,
+        };
+        if stamp.is_none() && !baked.iter().any(|t| matches!(t, PrivTool::Image { .. })) {
+            let g = state.cache.draw(renderer, bounds.size(), |f| {
+                for t in baked.iter() {
                     self.paint(f, t, scale, &translation_vector);
                 }
-            }
-        });
+                self.paint_overlay(f, scale, &translation_vector, cursor_over);
+            });
+            return vec![g];
+        }
 
-        vec![g]
+        //Le immagini sono primitive separate: le annotazioni che le precedono e
+        //quelle che le seguono vanno in livelli diversi per rispettare l'ordine
+        let mut layers = Vec::new();
+        let mut frame = Frame::new(renderer, bounds.size());
+        for t in baked.iter() {
+            if let PrivTool::Image { handle, rec, .. } = t {
+                let below = std::mem::replace(&mut frame, Frame::new(renderer, bounds.size()));
+                layers.push(below.into_geometry());
+                layers.push(self.image_geometry(
+                    handle,
+                    *rec,
+                    scale,
+                    &translation_vector,
+                    bounds.size(),
+                ));
+            } else {
+                self.paint(&mut frame, t, scale, &translation_vector);
+            }
+        }
+        drop(baked);
+        self.paint_overlay(&mut frame, scale, &translation_vector, cursor_over);
+        layers.push(frame.into_geometry());
+        if let Some((handle, rec)) = stamp {
+            layers.push(self.image_geometry(
+                handle,
+                rec,
+                scale,
+                &translation_vector,
+                bounds.size(),
+            ));
+        }
+        layers
     }
 
     fn mouse_interaction(
         &self,
-        _state: &Self::State,
+        state: &Self::State,
         bounds: Rectangle,
         cursor: Cursor,
     ) -> mouse::Interaction {
-        if cursor.is_over(&bounds) && self.tool.borrow().is_some() {
+        if state.drag.is_some() {
+            mouse::Interaction::Grabbing
+        } else if cursor.is_over(&bounds) && self.tool.borrow().is_some() {
             mouse::Interaction::Crosshair
         } else {
             mouse::Interaction::default()
@@ -730,6 +1992,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn annotations_select_and_delete() {
+        let image = Reader::open("tests/screenshot.png")
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_rgba8();
+        let control_image = Reader::open("tests/annotated_screenshot_rectangle.png")
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_rgba8();
+
+        let image_size = Size::new(image.width() as f32, image.height() as f32);
+        let bounds = Rectangle::with_size(image_size);
+        let mut state = State::default();
+        let mut annotations = Annotations::new(image_size, Message::Dummy);
+
+        annotations.set_tool(Some(Tool::Rectangle {
+            color: Color::new(0.0, 0.5, 0.1, 1.0),
+            fill_style: FillStyle::Stroke(1.0),
+        }));
+        let end = Point::new(1000.0, 700.0);
+        for (event, position) in [
+            (mouse::Event::ButtonPressed(mouse::Button::Left), Point::new(500.0, 500.0)),
+            (mouse::Event::CursorMoved { position: end }, end),
+            (mouse::Event::ButtonReleased(mouse::Button::Left), end),
+        ] {
+            annotations.update(
+                &mut state,
+                Event::Mouse(event),
+                bounds,
+                Cursor::Available(position),
+            );
+        }
+
+        // Senza strumento un click sul bordo seleziona il rettangolo, uno nel
+        // vuoto o al centro del rettangolo vuoto no, e lascia passare l'evento
+        annotations.set_tool(None);
+        let click = |annotations: &Annotations<Message>, state: &mut State, position| {
+            annotations
+                .update(
+                    state,
+                    Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left)),
+                    bounds,
+                    Cursor::Available(position),
+                )
+                .0
+        };
+        let status = click(&annotations, &mut state, Point::new(100.0, 100.0));
+        assert_eq!(status, event::Status::Ignored);
+        assert_eq!(annotations.selected(), None);
+        let status = click(&annotations, &mut state, Point::new(750.0, 600.0));
+        assert_eq!(status, event::Status::Ignored);
+        assert_eq!(annotations.selected(), None);
+        let status = click(&annotations, &mut state, Point::new(500.0, 600.0));
+        assert_eq!(status, event::Status::Captured);
+        assert_eq!(annotations.selected(), Some(0));
+
+        assert!(annotations.delete_selected());
+        assert!(!annotations.delete_selected());
+        let result =
+            draw_on_buffer::<Annotations<Message>, Message>(&annotations, &image, None).unwrap();
+        assert!(result == image, "The deleted annotation is still drawn");
+
+        annotations.restore_deleted();
+        annotations.select(None);
+        let result =
+            draw_on_buffer::<Annotations<Message>, Message>(&annotations, &image, None).unwrap();
+        assert!(
+            result == control_image,
+            "The restored annotation is different from the original one"
+        );
+    }
+
     #[test]
     fn annotations_combined_cropped() {
         let dyn_image = Reader::open("tests/screenshot.png")
@@ -835,4 +2172,283 @@ mod tests {
             "The generated image is different from the control one"
         );
     }
+
+    #[test]
+    fn annotations_json_round_trip() {
+        let image = Reader::open("tests/screenshot.png")
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_rgba8();
+        let image_size = Size::new(image.width() as f32, image.height() as f32);
+
+        let mut annotations = Annotations::new(image_size, Message::Dummy);
+        annotations.set_annotations(vec![
+            Annotation::Rectangle {
+                color: [0.0, 0.5, 0.1, 1.0],
+                fill_style: FillStyle::Stroke(3.0),
+                x: 100.0,
+                y: 120.0,
+                width: 250.5,
+                height: -80.25
+            },
+            Annotation::Ellipse {
+                color: [0.2, 0.3, 0.9, 0.6],
+                fill_style: FillStyle::Fill,
+                x: 400.0,
+                y: 300.0,
+                width: 120.0,
+                height: 90.0
+            },
+            Annotation::Arrow {
+                color: [1.0, 0.0, 0.0, 1.0],
+                stroke_width: 2.5,
+                begin: [600.0, 600.0],
+                end: [450.3, 520.7]
+            },
+            Annotation::Text {
+                color: [1.0, 1.0, 0.0, 1.0],
+                content: String::from("Round trip"),
+                size: 18.0,
+                x: 700.0,
+                y: 200.0,
+                font: TextFont {
+                    family: Some(String::from("Tektur")),
+                    weight: FontWeight::Bold
+                }
+            },
+            Annotation::FreeHand {
+                color: [1.0, 0.5, 0.5, 0.4],
+                stroke_width: 12.0,
+                points: vec![[50.0, 50.0], [60.5, 70.25], [90.0, 65.0], [120.0, 110.0]]
+            },
+        ], Vec::new());
+        let expected =
+            draw_on_buffer::<Annotations<Message>, Message>(&annotations, &image, None).unwrap();
+
+        let json = annotations.to_json().unwrap();
+        let mut imported = Annotations::new(image_size, Message::Dummy);
+        imported.load_json(&json).unwrap();
+        assert_eq!(imported.annotations(), annotations.annotations());
+
+        let result =
+            draw_on_buffer::<Annotations<Message>, Message>(&imported, &image, None).unwrap();
+        assert!(
+            result == expected,
+            "The imported annotations are rendered differently"
+        );
+
+        // Anche gli strumenti sopravvivono alla serializzazione
+        let tool = Tool::Text {
+            color: Color::new(0.1, 0.2, 0.3, 0.4),
+            content: String::from("Test"),
+            size: 15.0,
+            font: iced::Font::with_name("Tektur")
+        };
+        let json = serde_json::to_string(&tool).unwrap();
+        assert_eq!(serde_json::from_str::<Tool>(&json).unwrap(), tool);
+
+        // Le versioni più recenti vengono rifiutate
+        let newer = AnnotationSet {
+            version: ANNOTATIONS_VERSION + 1,
+            ..annotations.export_annotations()
+        };
+        let newer = serde_json::to_string(&newer).unwrap();
+        assert!(matches!(imported.load_json(&newer), Err(crate::Error::Annotations(_))));
+    }
+
+    #[test]
+    fn annotations_pasted_image() {
+        let image = Reader::open("tests/screenshot.png")
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_rgba8();
+        let image_size = Size::new(image.width() as f32, image.height() as f32);
+        let red = image::Rgba([255, 0, 0, 255]);
+
+        let mut annotations = Annotations::new(image_size, Message::Dummy);
+        annotations.insert_image(RgbaImage::from_pixel(40, 20, red));
+        assert_eq!(annotations.selected(), Some(0));
+        let Annotation::Image { x, y, width, height, .. } = annotations.annotations()[0] else {
+            panic!("the pasted image is not an image annotation");
+        };
+        assert_eq!((width, height), (40.0, 20.0));
+        assert_eq!(x, (image_size.width - 40.0) / 2.0);
+        assert_eq!(y, (image_size.height - 20.0) / 2.0);
+
+        // I pixel dell'immagine non devono avere rosso e blu invertiti
+        annotations.select(None);
+        let result =
+            draw_on_buffer::<Annotations<Message>, Message>(&annotations, &image, None).unwrap();
+        let center = (image.width() / 2, image.height() / 2);
+        assert_eq!(result.get_pixel(center.0, center.1), &red);
+
+        let json = annotations.to_json().unwrap();
+        let mut imported = Annotations::new(image_size, Message::Dummy);
+        imported.load_json(&json).unwrap();
+        assert_eq!(imported.annotations(), annotations.annotations());
+    }
+
+    #[test]
+    fn annotations_stamp() {
+        let image = Reader::open("tests/screenshot.png")
+            .unwrap()
+            .decode()
+            .unwrap()
+            .to_rgba8();
+        let image_size = Size::new(image.width() as f32, image.height() as f32);
+        let bounds = Rectangle::with_size(image_size);
+        let mut state = State::default();
+        let mut annotations = Annotations::new(image_size, Message::Dummy);
+
+        let stamp = RgbaImage::from_pixel(10, 10, image::Rgba([0, 0, 255, 255]));
+        annotations.set_tool(Some(Tool::Image {
+            image: Arc::new(stamp),
+            scale: 2.0,
+            opacity: 0.5,
+        }));
+        let click = |annotations: &Annotations<Message>, state: &mut State, event, p: Point| {
+            annotations.update(state, Event::Mouse(event), bounds, Cursor::Available(p))
+        };
+        let p = Point::new(500.0, 500.0);
+        click(&annotations, &mut state, mouse::Event::ButtonPressed(mouse::Button::Left), p);
+        click(&annotations, &mut state, mouse::Event::ButtonReleased(mouse::Button::Left), p);
+
+        let Annotation::Image { x, y, width, height, opacity, .. } = annotations.annotations()[0]
+        else {
+            panic!("the stamp is not an image annotation");
+        };
+        assert_eq!((x, y, width, height, opacity), (490.0, 490.0, 20.0, 20.0, 0.5));
+
+        // Il timbro è trasparente per metà
+        let result =
+            draw_on_buffer::<Annotations<Message>, Message>(&annotations, &image, None).unwrap();
+        let (before, after) = (image.get_pixel(500, 500), result.get_pixel(500, 500));
+        let blend = |stamp: u8, screen: u8| (stamp as i32 + screen as i32) / 2;
+        assert!((after[0] as i32 - blend(0, before[0])).abs() <= 2);
+        assert!((after[2] as i32 - blend(255, before[2])).abs() <= 2);
+
+        // Senza strumento il timbro si sposta trascinandolo
+        annotations.set_tool(None);
+        click(&annotations, &mut state, mouse::Event::ButtonPressed(mouse::Button::Left), p);
+        let q = Point::new(600.0, 450.0);
+        click(&annotations, &mut state, mouse::Event::CursorMoved { position: q }, q);
+        click(&annotations, &mut state, mouse::Event::ButtonReleased(mouse::Button::Left), q);
+        let Annotation::Image { x, y, .. } = annotations.annotations()[0] else {
+            panic!("the stamp is not an image annotation");
+        };
+        assert_eq!((x, y), (590.0, 440.0));
+    }
+
+    #[test]
+    fn annotations_resize() {
+        let color = [1.0, 0.0, 0.0, 1.0];
+        let mut annotations = Annotations::new(Size::new(200.0, 100.0), Message::Dummy);
+        annotations.set_annotations(
+            vec![
+                Annotation::Rectangle {
+                    color,
+                    fill_style: FillStyle::Stroke(2.0),
+                    x: 10.0,
+                    y: 20.0,
+                    width: 40.0,
+                    height: 30.0,
+                },
+                Annotation::FreeHand {
+                    color,
+                    stroke_width: 1.0,
+                    points: vec![[0.0, 0.0], [100.0, 50.0]],
+                },
+            ],
+            vec![(
+                0,
+                Annotation::Arrow {
+                    color,
+                    stroke_width: 1.0,
+                    begin: [20.0, 10.0],
+                    end: [60.0, 90.0],
+                },
+            )],
+        );
+        annotations.set_crop(Rectangle::new(Point::new(10.0, 10.0), Size::new(50.0, 50.0)));
+
+        annotations.resize(Size::new(100.0, 50.0));
+        assert_eq!(
+            annotations.annotations(),
+            vec![
+                Annotation::Rectangle {
+                    color,
+                    fill_style: FillStyle::Stroke(1.0),
+                    x: 5.0,
+                    y: 10.0,
+                    width: 20.0,
+                    height: 15.0,
+                },
+                Annotation::FreeHand {
+                    color,
+                    stroke_width: 0.5,
+                    points: vec![[0.0, 0.0], [50.0, 25.0]],
+                },
+            ]
+        );
+        let Annotation::Arrow { begin, end, .. } = annotations.deleted()[0].1 else {
+            panic!("the deleted annotation is not an arrow");
+        };
+        assert_eq!((begin, end), ([10.0, 5.0], [30.0, 45.0]));
+        assert_eq!(annotations.crop_area, Rectangle::with_size(Size::new(100.0, 50.0)));
+    }
+
+    #[test]
+    fn annotations_transform() {
+        let mut stamp = RgbaImage::new(2, 1);
+        stamp.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        let stamp = Arc::new(stamp);
+        let rectangle = Annotation::Rectangle {
+            color: [1.0, 0.0, 0.0, 1.0],
+            fill_style: FillStyle::Fill,
+            x: 10.0,
+            y: 20.0,
+            width: 40.0,
+            height: 30.0,
+        };
+        let mut annotations = Annotations::new(Size::new(200.0, 100.0), Message::Dummy);
+        annotations.set_annotations(
+            vec![
+                rectangle.clone(),
+                Annotation::Image {
+                    x: 0.0,
+                    y: 0.0,
+                    width: 20.0,
+                    height: 10.0,
+                    image: stamp.clone(),
+                    opacity: 1.0,
+                },
+            ],
+            Vec::new(),
+        );
+
+        annotations.transform(Transform::Rotate90);
+        assert_eq!(annotations.image_size, Size::new(100.0, 200.0));
+        let rotated = annotations.annotations();
+        let Annotation::Rectangle { x, y, width, height, .. } = rotated[0] else {
+            panic!("the rectangle changed kind");
+        };
+        assert_eq!((x, y, width, height), (50.0, 10.0, 30.0, 40.0));
+        let Annotation::Image { x, y, width, height, ref image, .. } = rotated[1] else {
+            panic!("the image changed kind");
+        };
+        assert_eq!((x, y, width, height), (90.0, 0.0, 10.0, 20.0));
+        assert_eq!(image.dimensions(), (1, 2));
+
+        // La rotazione inversa riporta tutto com'era
+        annotations.transform(Transform::Rotate270);
+        assert_eq!(annotations.image_size, Size::new(200.0, 100.0));
+        let restored = annotations.annotations();
+        assert_eq!(restored[0], rectangle);
+        let Annotation::Image { ref image, .. } = restored[1] else {
+            panic!("the image changed kind");
+        };
+        assert_eq!(image.as_ref(), stamp.as_ref());
+    }
 }
@@ -0,0 +1,280 @@
+//! Elaborazione in batch: la stessa ricetta di annotazioni e ritaglio viene
+//! applicata a tutte le immagini di una cartella, usando più thread.
+//!
+//! La ricetta è un [`AnnotationSet`] esportato dall'editor, con in più un
+//! ritaglio opzionale. Le annotazioni sono in pixel e vengono disegnate nella
+//! stessa posizione su ogni immagine, qualunque sia la sua dimensione.
+
+use crate::annotations::{AnnotationSet, Annotations};
+use crate::canvas_to_pixels::{draw_primitives, rasterize};
+use crate::encoding::{self, EncoderOptions};
+use crate::metadata::{read_metadata_from_file, Crop};
+use crate::naming::FileName;
+use crate::{Error, ImageFormat, Result};
+use iced::{Rectangle, Size};
+use iced_graphics::Primitive;
+use image::RgbaImage;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Template usato se non ne viene indicato uno: il nome dell'immagine di
+/// partenza
+pub const DEFAULT_TEMPLATE: &str = "{name}";
+
+/// Annotazioni e ritaglio da applicare a ogni immagine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    #[serde(flatten)]
+    pub annotations: AnnotationSet,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crop: Option<Crop>,
+}
+
+impl Recipe {
+    pub fn from_json(json: &str) -> Result<Self> {
+        let recipe: Self =
+            serde_json::from_str(json).map_err(|e| Error::Annotations(e.to_string()))?;
+        let (width, height) = (recipe.annotations.width, recipe.annotations.height);
+        if !(width > 0.0 && height > 0.0) {
+            return Err(Error::Annotations(format!(
+                "invalid image size {}x{}",
+                width, height
+            )));
+        }
+        Ok(recipe)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        Self::from_json(&json)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(|e| Error::Annotations(e.to_string()))
+    }
+
+    /// Genera le primitive delle annotazioni. Vanno generate una volta sola
+    /// sul thread chiamante, perché il canvas non è Send, e poi applicate a
+    /// ogni immagine con [`apply`].
+    pub fn primitives(&self) -> Result<Vec<Primitive>> {
+        let size = Size::new(self.annotations.width, self.annotations.height);
+        let mut annotations = Annotations::new(size, ());
+        annotations.import_annotations(self.annotations.clone())?;
+        Ok(draw_primitives::<Annotations<()>, ()>(
+            &annotations,
+            Size::new(size.width.ceil() as u32, size.height.ceil() as u32),
+        ))
+    }
+
+    /// Applica la ricetta a una sola immagine
+    pub fn apply(&self, image: &RgbaImage) -> Result<RgbaImage> {
+        apply(&self.primitives()?, self.crop, image)
+    }
+}
+
+/// Disegna le primitive sull'immagine e la ritaglia. A differenza di
+/// [`rasterize`] un ritaglio che esce dall'immagine è un errore.
+pub fn apply(primitives: &[Primitive], crop: Option<Crop>, image: &RgbaImage) -> Result<RgbaImage> {
+    if let Some(c) = crop.filter(|c| !c.fits_in(image.width(), image.height())) {
+        return Err(Error::Render(format!(
+            "the crop {} is outside the {}x{} image",
+            c,
+            image.width(),
+            image.height()
+        )));
+    }
+    let crop_rec = crop.map(|c| Rectangle {
+        x: c.x,
+        y: c.y,
+        width: c.width,
+        height: c.height,
+    });
+    rasterize(primitives, image, crop_rec)
+        .ok_or_else(|| Error::Render(String::from("unable to draw the annotations")))
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// Template dei nomi dei file scritti, vedi [`crate::naming`]
+    pub template: String,
+    /// Formato dei file scritti, None per mantenere quello di ogni immagine
+    pub format: Option<ImageFormat>,
+    /// Numero di thread, 0 per usarne uno per core
+    pub jobs: usize,
+    pub encoder: EncoderOptions,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            template: String::from(DEFAULT_TEMPLATE),
+            format: None,
+            jobs: 0,
+            encoder: EncoderOptions::default(),
+        }
+    }
+}
+
+/// Immagini in un formato supportato contenute nella cartella, in ordine
+/// alfabetico. Le sottocartelle non vengono visitate.
+pub fn list_images(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = std::fs::read_dir(dir).map_err(|e| Error::io(dir, e))?;
+    let mut images = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| Error::io(dir, e))?.path();
+        if path.is_file() && input_format(&path).is_some() {
+            images.push(path);
+        }
+    }
+    images.sort();
+    Ok(images)
+}
+
+fn input_format(path: &Path) -> Option<ImageFormat> {
+    path.extension()
+        .and_then(|e| ImageFormat::from_extension(&e.to_string_lossy()))
+}
+
+/// Applica la ricetta a ogni immagine e scrive i risultati in output_dir.
+/// Restituisce per ogni immagine, nello stesso ordine, il file scritto o
+/// l'errore: un'immagine non valida non interrompe le altre.
+pub fn run(
+    recipe: &Recipe,
+    inputs: &[PathBuf],
+    output_dir: &Path,
+    options: &BatchOptions,
+) -> Result<Vec<(PathBuf, Result<PathBuf>)>> {
+    let primitives = recipe.primitives()?;
+    std::fs::create_dir_all(output_dir).map_err(|e| Error::io(output_dir, e))?;
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs)
+        .build()
+        .map_err(|e| Error::Render(e.to_string()))?;
+
+    Ok(pool.install(|| {
+        inputs
+            .par_iter()
+            .map(|input| {
+                let result = process(&primitives, recipe.crop, input, output_dir, options);
+                (input.clone(), result)
+            })
+            .collect()
+    }))
+}
+
+fn process(
+    primitives: &[Primitive],
+    crop: Option<Crop>,
+    input: &Path,
+    output_dir: &Path,
+    options: &BatchOptions,
+) -> Result<PathBuf> {
+    let image = image::open(input)?.to_rgba8();
+    let result = apply(primitives, crop, &image)?;
+
+    //I metadati della cattura originale vengono conservati
+    let mut metadata = read_metadata_from_file(input).unwrap_or_default();
+    if crop.is_some() {
+        metadata.crop = crop;
+    }
+
+    let format = options
+        .format
+        .or_else(|| input_format(input))
+        .unwrap_or(ImageFormat::Png);
+    let name = FileName {
+        name: input.file_stem().map(|s| s.to_string_lossy().into_owned()),
+        ..FileName::new(&options.template, format)
+    };
+    let (path, file) = name.create(output_dir, result.width(), result.height())?;
+    encoding::write_to_file(
+        &result,
+        file,
+        &path,
+        format,
+        &options.encoder,
+        Some(&metadata),
+    )?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::{Annotation, FillStyle, ANNOTATIONS_VERSION};
+    use crate::test_utils::temp_dir;
+    use image::Rgba;
+
+    const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+
+    #[test]
+    fn batch_directory() {
+        let dir = temp_dir("batch");
+        let input_dir = dir.join("in");
+        let output_dir = dir.join("out");
+        std::fs::create_dir_all(&input_dir).unwrap();
+        for (name, width) in [("a.png", 40), ("b.bmp", 60), ("small.png", 10)] {
+            RgbaImage::from_pixel(width, 30, WHITE)
+                .save(input_dir.join(name))
+                .unwrap();
+        }
+        std::fs::write(input_dir.join("notes.txt"), "not an image").unwrap();
+
+        let recipe = Recipe::from_json(
+            &Recipe {
+                annotations: AnnotationSet {
+                    version: ANNOTATIONS_VERSION,
+                    width: 40.0,
+                    height: 30.0,
+                    annotations: vec![Annotation::Rectangle {
+                        color: [1.0, 0.0, 0.0, 1.0],
+                        fill_style: FillStyle::Fill,
+                        x: 5.0,
+                        y: 5.0,
+                        width: 10.0,
+                        height: 10.0,
+                    }],
+                },
+                crop: Some(Crop {
+                    x: 0,
+                    y: 0,
+                    width: 20,
+                    height: 20,
+                }),
+            }
+            .to_json()
+            .unwrap(),
+        )
+        .unwrap();
+
+        let inputs = list_images(&input_dir).unwrap();
+        assert_eq!(inputs.len(), 3);
+        let options = BatchOptions {
+            template: String::from("{name}-annotated"),
+            jobs: 2,
+            ..BatchOptions::default()
+        };
+        let results = run(&recipe, &inputs, &output_dir, &options).unwrap();
+
+        assert_eq!(
+            results[0].1.as_ref().unwrap(),
+            &output_dir.join("a-annotated.png")
+        );
+        assert_eq!(
+            results[1].1.as_ref().unwrap(),
+            &output_dir.join("b-annotated.bmp")
+        );
+        for (_, output) in &results[..2] {
+            let image = image::open(output.as_ref().unwrap()).unwrap().to_rgba8();
+            assert_eq!(image.dimensions(), (20, 20));
+            assert_eq!(image.get_pixel(10, 10), &RED);
+            assert_eq!(image.get_pixel(2, 2), &WHITE);
+        }
+        //Il ritaglio esce dall'immagine più piccola
+        assert!(results[2].1.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,399 @@
+//! Esportazione "di presentazione": lo screenshot renderizzato viene messo su
+//! uno sfondo a tinta unita o sfumato, con margine, angoli arrotondati e ombra.
+//!
+//! Le impostazioni sono raccolte in preset con un nome, salvati nella
+//! configurazione. L'elaborazione lavora solo sui pixel, quindi può girare in
+//! background ed essere usata anche per l'anteprima in scala ridotta.
+
+use image::{imageops, GrayImage, Luma, Rgba, RgbaImage};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+/// Colore opaco, scritto nella configurazione come "#rrggbb"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+impl Rgb {
+    fn lerp(self, other: Rgb, t: f32) -> Rgba<u8> {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Rgba([
+            mix(self.0, other.0),
+            mix(self.1, other.1),
+            mix(self.2, other.2),
+            255,
+        ])
+    }
+}
+
+impl std::fmt::Display for Rgb {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0, self.1, self.2)
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(format!("invalid color \"{}\", expected #rrggbb", s));
+        }
+        let channel = |i: usize| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .map_err(|_| format!("invalid color \"{}\", expected #rrggbb", s))
+        };
+        Ok(Rgb(channel(0)?, channel(2)?, channel(4)?))
+    }
+}
+
+impl Serialize for Rgb {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rgb {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Direzione della sfumatura dello sfondo
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GradientDirection {
+    /// Dall'alto verso il basso
+    Vertical,
+    /// Da sinistra verso destra
+    Horizontal,
+    /// Dall'angolo in alto a sinistra a quello in basso a destra
+    #[default]
+    Diagonal,
+}
+
+impl GradientDirection {
+    pub const ALL: [GradientDirection; 3] = [
+        GradientDirection::Vertical,
+        GradientDirection::Horizontal,
+        GradientDirection::Diagonal,
+    ];
+}
+
+impl std::fmt::Display for GradientDirection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            GradientDirection::Vertical => "Vertical",
+            GradientDirection::Horizontal => "Horizontal",
+            GradientDirection::Diagonal => "Diagonal",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Sfondo su cui viene appoggiato lo screenshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Background {
+    Solid {
+        color: Rgb,
+    },
+    Gradient {
+        from: Rgb,
+        to: Rgb,
+        direction: GradientDirection,
+    },
+}
+
+impl Background {
+    /// Colore dello sfondo nel punto (x, y) di un'immagine width x height
+    fn color_at(&self, x: u32, y: u32, width: u32, height: u32) -> Rgba<u8> {
+        match *self {
+            Background::Solid { color } => Rgba([color.0, color.1, color.2, 255]),
+            Background::Gradient {
+                from,
+                to,
+                direction,
+            } => {
+                let fx = x as f32 / (width.max(2) - 1) as f32;
+                let fy = y as f32 / (height.max(2) - 1) as f32;
+                let t = match direction {
+                    GradientDirection::Vertical => fy,
+                    GradientDirection::Horizontal => fx,
+                    GradientDirection::Diagonal => (fx + fy) / 2.0,
+                };
+                from.lerp(to, t)
+            }
+        }
+    }
+}
+
+/// Impostazioni di presentazione salvate con un nome
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BeautifyPreset {
+    pub name: String,
+    pub background: Background,
+    /// Margine tra lo screenshot e il bordo dell'immagine, in pixel
+    pub padding: u32,
+    /// Raggio degli angoli dello screenshot, in pixel
+    pub corner_radius: u32,
+    /// Raggio della sfocatura dell'ombra, in pixel; 0 per un'ombra netta
+    pub shadow_blur: f32,
+    /// Spostamento dell'ombra rispetto allo screenshot, in pixel
+    pub shadow_offset: (i32, i32),
+    /// Opacità dell'ombra, tra 0 e 1; 0 la disattiva
+    pub shadow_opacity: f32,
+}
+
+impl std::fmt::Display for BeautifyPreset {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl BeautifyPreset {
+    /// Preset con tutte le misure moltiplicate per factor, usato per
+    /// l'anteprima di uno screenshot rimpicciolito
+    pub fn scaled(&self, factor: f32) -> Self {
+        let scale = |v: u32| (v as f32 * factor).round() as u32;
+        Self {
+            padding: scale(self.padding),
+            corner_radius: scale(self.corner_radius),
+            shadow_blur: self.shadow_blur * factor,
+            shadow_offset: (
+                (self.shadow_offset.0 as f32 * factor).round() as i32,
+                (self.shadow_offset.1 as f32 * factor).round() as i32,
+            ),
+            ..self.clone()
+        }
+    }
+
+    /// Preset proposti alla prima esecuzione
+    pub fn defaults() -> Vec<BeautifyPreset> {
+        vec![
+            BeautifyPreset {
+                name: String::from("Clean"),
+                background: Background::Solid {
+                    color: Rgb(0xf2, 0xf2, 0xf2),
+                },
+                padding: 64,
+                corner_radius: 12,
+                shadow_blur: 24.0,
+                shadow_offset: (0, 12),
+                shadow_opacity: 0.35,
+            },
+            BeautifyPreset {
+                name: String::from("Ocean"),
+                background: Background::Gradient {
+                    from: Rgb(0x4f, 0xac, 0xfe),
+                    to: Rgb(0x00, 0xf2, 0xfe),
+                    direction: GradientDirection::Diagonal,
+                },
+                padding: 96,
+                corner_radius: 16,
+                shadow_blur: 32.0,
+                shadow_offset: (0, 16),
+                shadow_opacity: 0.4,
+            },
+            BeautifyPreset {
+                name: String::from("Sunset"),
+                background: Background::Gradient {
+                    from: Rgb(0xfa, 0x70, 0x9a),
+                    to: Rgb(0xfe, 0xe1, 0x40),
+                    direction: GradientDirection::Vertical,
+                },
+                padding: 96,
+                corner_radius: 16,
+                shadow_blur: 32.0,
+                shadow_offset: (0, 16),
+                shadow_opacity: 0.4,
+            },
+        ]
+    }
+}
+
+/// Impostazioni persistenti della presentazione
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BeautifyOptions {
+    /// Se la presentazione è attiva per default; si può cambiare a ogni salvataggio
+    pub enabled: bool,
+    /// Nome del preset in uso
+    pub preset: String,
+    pub presets: Vec<BeautifyPreset>,
+}
+
+impl Default for BeautifyOptions {
+    fn default() -> Self {
+        let presets = BeautifyPreset::defaults();
+        Self {
+            enabled: false,
+            preset: presets[0].name.clone(),
+            presets,
+        }
+    }
+}
+
+impl BeautifyOptions {
+    /// Preset in uso, o il primo se quello indicato non esiste più
+    pub fn selected(&self) -> Option<&BeautifyPreset> {
+        self.presets
+            .iter()
+            .find(|p| p.name == self.preset)
+            .or_else(|| self.presets.first())
+    }
+
+    /// Come [`Self::selected`], ma modificabile
+    pub fn selected_mut(&mut self) -> Option<&mut BeautifyPreset> {
+        let index = self
+            .presets
+            .iter()
+            .position(|p| p.name == self.preset)
+            .unwrap_or(0);
+        self.presets.get_mut(index)
+    }
+}
+
+/// Mette lo screenshot sullo sfondo del preset, con margine, angoli
+/// arrotondati e ombra. Il risultato è sempre opaco.
+pub fn beautify(image: &RgbaImage, preset: &BeautifyPreset) -> RgbaImage {
+    let (width, height) = image.dimensions();
+    let padding = preset.padding;
+    let (out_width, out_height) = (width + 2 * padding, height + 2 * padding);
+
+    let mut out = RgbaImage::from_fn(out_width, out_height, |x, y| {
+        preset.background.color_at(x, y, out_width, out_height)
+    });
+    let mask = rounded_mask(width, height, preset.corner_radius);
+
+    //L'ombra è la maschera spostata e sfocata, usata come alpha del nero
+    if preset.shadow_opacity > 0.0 {
+        let mut shadow = GrayImage::new(out_width, out_height);
+        imageops::replace(
+            &mut shadow,
+            &mask,
+            padding as i64 + preset.shadow_offset.0 as i64,
+            padding as i64 + preset.shadow_offset.1 as i64,
+        );
+        if preset.shadow_blur > 0.0 {
+            shadow = imageops::blur(&shadow, preset.shadow_blur / 2.0);
+        }
+        let opacity = preset.shadow_opacity.clamp(0.0, 1.0);
+        for (pixel, Luma([alpha])) in out.pixels_mut().zip(shadow.pixels()) {
+            blend(pixel, Rgba([0, 0, 0, 255]), *alpha as f32 / 255.0 * opacity);
+        }
+    }
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let coverage = mask.get_pixel(x, y)[0] as f32 / 255.0;
+        let alpha = pixel[3] as f32 / 255.0 * coverage;
+        blend(out.get_pixel_mut(x + padding, y + padding), *pixel, alpha);
+    }
+    out
+}
+
+/// Copertura di un rettangolo width x height con gli angoli arrotondati,
+/// con i bordi degli angoli sfumati per evitare le scalettature
+fn rounded_mask(width: u32, height: u32, radius: u32) -> GrayImage {
+    let r = radius.min(width / 2).min(height / 2) as f32;
+    let (w, h) = (width as f32, height as f32);
+    GrayImage::from_fn(width, height, |x, y| {
+        let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+        //Centro dell'angolo più vicino, o il punto stesso se non è in un angolo
+        let (cx, cy) = (px.clamp(r, w - r), py.clamp(r, h - r));
+        if cx == px || cy == py {
+            return Luma([255]);
+        }
+        let distance = ((px - cx).powi(2) + (py - cy).powi(2)).sqrt();
+        Luma([((r - distance + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
+
+/// Sovrappone color all'immagine opaca con l'opacità indicata
+fn blend(pixel: &mut Rgba<u8>, color: Rgba<u8>, alpha: f32) {
+    for c in 0..3 {
+        let mixed = pixel[c] as f32 * (1.0 - alpha) + color[c] as f32 * alpha;
+        pixel[c] = mixed.round() as u8;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preset(background: Background) -> BeautifyPreset {
+        BeautifyPreset {
+            name: String::from("test"),
+            background,
+            padding: 5,
+            corner_radius: 0,
+            shadow_blur: 0.0,
+            shadow_offset: (0, 0),
+            shadow_opacity: 0.0,
+        }
+    }
+
+    #[test]
+    fn beautify_padding_and_corners() {
+        let image = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        let white = Rgb(255, 255, 255);
+        let mut preset = preset(Background::Solid { color: white });
+
+        let result = beautify(&image, &preset);
+        assert_eq!(result.dimensions(), (20, 20));
+        assert_eq!(result.get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+        assert_eq!(result.get_pixel(5, 5), &Rgba([255, 0, 0, 255]));
+        assert_eq!(result.get_pixel(14, 14), &Rgba([255, 0, 0, 255]));
+
+        preset.corner_radius = 4;
+        let result = beautify(&image, &preset);
+        assert_eq!(result.get_pixel(5, 5), &Rgba([255, 255, 255, 255]));
+        assert_eq!(result.get_pixel(10, 5), &Rgba([255, 0, 0, 255]));
+
+        //Ombra netta spostata di 3 pixel in basso a destra
+        preset.corner_radius = 0;
+        preset.shadow_offset = (3, 3);
+        preset.shadow_opacity = 1.0;
+        let result = beautify(&image, &preset);
+        assert_eq!(result.get_pixel(16, 16), &Rgba([0, 0, 0, 255]));
+        assert_eq!(result.get_pixel(4, 4), &Rgba([255, 255, 255, 255]));
+        assert_eq!(result.get_pixel(14, 14), &Rgba([255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn beautify_gradient() {
+        let image = RgbaImage::from_pixel(10, 10, Rgba([0, 0, 0, 0]));
+        let preset = preset(Background::Gradient {
+            from: Rgb(0, 0, 0),
+            to: Rgb(200, 100, 0),
+            direction: GradientDirection::Horizontal,
+        });
+        let result = beautify(&image, &preset);
+        assert_eq!(result.get_pixel(0, 10), &Rgba([0, 0, 0, 255]));
+        assert_eq!(result.get_pixel(19, 0), &Rgba([200, 100, 0, 255]));
+        //Lo screenshot trasparente lascia vedere lo sfondo
+        assert_eq!(result.get_pixel(10, 10), result.get_pixel(10, 0));
+    }
+
+    #[test]
+    fn beautify_presets_round_trip() {
+        assert_eq!("#4FACFE".parse::<Rgb>(), Ok(Rgb(0x4f, 0xac, 0xfe)));
+        assert!("#4fac".parse::<Rgb>().is_err());
+
+        let options = BeautifyOptions::default();
+        let toml = toml::to_string(&options).unwrap();
+        assert!(toml.contains("from = \"#4facfe\""));
+        assert_eq!(toml::from_str::<BeautifyOptions>(&toml).unwrap(), options);
+
+        let options = BeautifyOptions {
+            preset: String::from("missing"),
+            ..options
+        };
+        assert_eq!(options.selected().unwrap().name, "Clean");
+        assert_eq!(options.selected().unwrap().scaled(0.5).padding, 32);
+    }
+}
@@ -126,6 +126,20 @@ fn adjust_primitive_colors(primitive: Primitive) -> Primitive {
                 bounds,
             }
         }
+        // Anche i pixel delle immagini vengono letti come (B, G, R, A)
+        Primitive::Image { ref handle, bounds } => {
+            let iced::widget::image::Data::Rgba { width, height, pixels } = handle.data() else {
+                return primitive;
+            };
+            let swapped: Vec<u8> = pixels
+                .chunks_exact(4)
+                .flat_map(|p| [p[2], p[1], p[0], p[3]])
+                .collect();
+            Primitive::Image {
+                handle: iced::widget::image::Handle::from_pixels(*width, *height, swapped),
+                bounds,
+            }
+        }
         Primitive::SolidMesh { buffers, size } => Primitive::SolidMesh {
             size,
             buffers: iced_graphics::primitive::Mesh2D {
@@ -146,17 +160,33 @@ fn adjust_primitive_colors(primitive: Primitive) -> Primitive {
     }
 }
 
+/// Disegna il canvas sul buffer dell'immagine e restituisce il risultato,
+/// eventualmente ritagliato secondo crop_rec.
 pub fn draw_on_buffer<P, Message>(
     program: &P,
     image: &RgbaImage,
     crop_rec: Option<Rectangle<u32>>,
 ) -> Option<RgbaImage>
+where
+    P: Program<Message, Renderer>,
+{
+    let primitives =
+        draw_primitives::<P, Message>(program, Size::new(image.width(), image.height()));
+    rasterize(&primitives, image, crop_rec)
+}
+
+/// Genera le primitive del canvas per un'immagine della dimensione indicata,
+/// con i colori già corretti per il backend software.
+/// Deve essere chiamata sul thread della GUI perché il Program non è Send,
+/// mentre le primitive restituite possono essere rasterizzate altrove con
+/// [`rasterize`].
+pub fn draw_primitives<P, Message>(program: &P, size: Size<u32>) -> Vec<Primitive>
 where
     P: Program<Message, Renderer>,
 {
     let bounds = Rectangle::new(
         Point::new(0.0, 0.0),
-        Size::new(image.width() as f32, image.height() as f32),
+        Size::new(size.width as f32, size.height as f32),
     );
 
     // Internally, this backend is never actually used.
@@ -172,13 +202,25 @@ where
         bounds,
         cursor,
     );
-    let primitives: Vec<iced_graphics::Primitive> = {
-        let mut tmp: Vec<iced_graphics::Primitive> = Vec::new();
-        for g in geo {
-            tmp.push(adjust_primitive_colors(g.into()));
-        }
-        tmp
-    };
+
+    let mut tmp: Vec<Primitive> = Vec::new();
+    for g in geo {
+        tmp.push(adjust_primitive_colors(g.into()));
+    }
+    tmp
+}
+
+/// Rasterizza le primitive sopra l'immagine e restituisce il risultato,
+/// eventualmente ritagliato secondo crop_rec.
+pub fn rasterize(
+    primitives: &[Primitive],
+    image: &RgbaImage,
+    crop_rec: Option<Rectangle<u32>>,
+) -> Option<RgbaImage> {
+    let bounds = Rectangle::new(
+        Point::new(0.0, 0.0),
+        Size::new(image.width() as f32, image.height() as f32),
+    );
 
     let mut fg_pixmap = tiny_skia::Pixmap::new(image.width(), image.height())?;
     let mut mask = tiny_skia::Mask::new(image.width(), image.height())?;
@@ -187,7 +229,6 @@ where
     let damage = [bounds];
     let overlay: [String; 0] = [];
 
-    // Create a new backend because the previous one was moved
     let mut backend = iced_tiny_skia::Backend::new(Default::default());
 
     #[cfg(test)]
@@ -201,7 +242,7 @@ where
     backend.draw(
         &mut fg_pixmap.as_mut(),
         &mut mask,
-        primitives.as_slice(),
+        primitives,
         &viewport,
         &damage,
         iced::Color::TRANSPARENT,
@@ -0,0 +1,661 @@
+//! Interfaccia a riga di comando, per catturare e annotare dagli script
+//! senza aprire la finestra.
+//!
+//! Gli errori vengono scritti su stderr e l'esito è indicato dal codice di
+//! uscita: [`EXIT_OK`], [`EXIT_FAILURE`] se l'operazione non è riuscita e
+//! [`EXIT_USAGE`] se gli argomenti non sono validi.
+
+use crate::batch::{self, BatchOptions, Recipe};
+use crate::config::{self, Config};
+use crate::encoding::{self, EncoderOptions};
+use crate::instance::{self, Request};
+use crate::metadata::{read_metadata_from_file, Crop, Metadata};
+use crate::project::PROJECT_EXTENSION;
+use crate::{screenshot, screenshot_all, Error, ImageFormat, Result, Screen, OPEN_EXTENSIONS};
+use image::{imageops, RgbaImage};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+/// Path di output che indica lo standard output
+const STDOUT: &str = "-";
+
+pub const USAGE: &str = "\
+Usage:
+  pds_project [FILE]               start the graphical interface
+  pds_project capture [--screen N|all] [--delay S] [--region x,y,w,h]
+                      [--format FORMAT] --output PATH
+  pds_project annotate --input PATH --annotations FILE.json [--crop x,y,w,h]
+                       [--format FORMAT] --output PATH
+  pds_project batch --recipe FILE.json --input DIR --output DIR
+                    [--template TEMPLATE] [--format FORMAT] [--jobs N]
+  pds_project list-screens
+  pds_project send COMMAND [PATH]
+  pds_project help
+
+Screens are numbered as printed by list-screens, the primary one is used by
+default. The format is taken from --format, then from the output extension,
+then from the configuration. Use - as output to write the image to stdout.
+
+batch applies the annotations and the optional crop of a recipe to every
+image of a directory. The recipe is an annotations file exported by the
+editor, optionally with a crop field holding x, y, width and height.
+Output names follow the template, {name} is the name of the source image and
+is the default. By default the format of each source image is kept and one
+worker per core is used.
+
+Only one graphical interface runs at a time: launching it again forwards FILE,
+an image or a project, to the running one. send drives the running interface
+with a command of its control socket: show, capture, capture-region,
+capture-all, open PATH, save [PATH], copy or quit.
+
+Exit codes: 0 success, 1 failure, 2 invalid arguments.";
+
+/// Quale schermo catturare
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenArg {
+    Primary,
+    /// Numero dello schermo come mostrato da list-screens, a partire da 1
+    Index(usize),
+    All,
+}
+
+/// Sottocomandi della riga di comando. Un altro argomento, da solo, è il file
+/// da aprire nell'interfaccia grafica, se esiste o ha l'estensione di
+/// un'immagine o di un progetto.
+const SUBCOMMANDS: [&str; 6] = [
+    "capture",
+    "annotate",
+    "batch",
+    "list-screens",
+    "send",
+    "help",
+];
+
+/// Sottocomando richiesto sulla riga di comando
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Capture {
+        screen: ScreenArg,
+        delay: Duration,
+        region: Option<Crop>,
+        format: Option<ImageFormat>,
+        output: PathBuf,
+    },
+    Annotate {
+        input: PathBuf,
+        annotations: PathBuf,
+        crop: Option<Crop>,
+        format: Option<ImageFormat>,
+        output: PathBuf,
+    },
+    Batch {
+        recipe: PathBuf,
+        input: PathBuf,
+        output: PathBuf,
+        template: String,
+        format: Option<ImageFormat>,
+        jobs: usize,
+    },
+    ListScreens,
+    /// Comando da inviare all'istanza in esecuzione
+    Send(Request),
+    Help,
+}
+
+/// Interpreta gli argomenti, senza il nome del programma. Restituisce None
+/// se non è stato indicato un sottocomando e va aperta l'interfaccia grafica,
+/// eventualmente con il file passato come unico argomento.
+pub fn parse<I>(args: I) -> std::result::Result<Option<Command>, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter();
+    let Some(subcommand) = args.next() else {
+        return Ok(None);
+    };
+    let mut args = args.peekable();
+    //Una parola sconosciuta, come un sottocomando scritto male, non apre l'interfaccia
+    if args.peek().is_none()
+        && !subcommand.starts_with('-')
+        && !SUBCOMMANDS.contains(&subcommand.as_str())
+    {
+        let path = Path::new(&subcommand);
+        if path.is_dir() {
+            return Err(format!("'{}' is a directory, not a file to open", subcommand));
+        }
+        if is_openable(path) {
+            return Ok(None);
+        }
+    }
+
+    //send inoltra il resto della riga di comando come richiesta del protocollo.
+    //I path relativi vengono risolti qui, perché l'istanza ha un'altra cartella corrente
+    if subcommand == "send" {
+        let command = args
+            .next()
+            .ok_or_else(|| String::from("send requires a command"))?;
+        let path: Vec<String> = args.collect();
+        let line = if path.is_empty() {
+            command
+        } else {
+            let path = std::env::current_dir()
+                .map(|d| d.join(path.join(" ")))
+                .map_err(|e| e.to_string())?;
+            format!("{} {}", command, path.display())
+        };
+        return line.parse().map(|r| Some(Command::Send(r)));
+    }
+
+    //Coppie --opzione valore, accettando anche la forma --opzione=valore
+    let mut options = Vec::new();
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            return Err(format!("unexpected argument '{}'", arg));
+        };
+        let (name, value) = match name.split_once('=') {
+            Some((name, value)) => (name.to_string(), value.to_string()),
+            None => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing value for --{}", name))?;
+                (name.to_string(), value)
+            }
+        };
+        options.push((name, value));
+    }
+    let mut options = Options(options);
+
+    let command = match subcommand.as_str() {
+        "capture" => {
+            let screen = match options.take("screen").as_deref() {
+                None => ScreenArg::Primary,
+                Some("all") => ScreenArg::All,
+                Some(n) => match n.parse::<usize>() {
+                    Ok(n) if n > 0 => ScreenArg::Index(n),
+                    _ => return Err(format!("invalid screen '{}'", n)),
+                },
+            };
+            let delay = match options.take("delay") {
+                None => Duration::ZERO,
+                Some(s) => s
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|s| s.is_finite() && *s >= 0.0)
+                    .map(Duration::from_secs_f64)
+                    .ok_or_else(|| format!("invalid delay '{}'", s))?,
+            };
+            Command::Capture {
+                screen,
+                delay,
+                region: options.crop("region")?,
+                format: options.format()?,
+                output: options.required("output")?.into(),
+            }
+        }
+        "annotate" => Command::Annotate {
+            input: options.required("input")?.into(),
+            annotations: options.required("annotations")?.into(),
+            crop: options.crop("crop")?,
+            format: options.format()?,
+            output: options.required("output")?.into(),
+        },
+        "batch" => Command::Batch {
+            recipe: options.required("recipe")?.into(),
+            input: options.required("input")?.into(),
+            output: options.required("output")?.into(),
+            template: options
+                .take("template")
+                .unwrap_or_else(|| String::from(batch::DEFAULT_TEMPLATE)),
+            format: options.format()?,
+            jobs: match options.take("jobs") {
+                None => 0,
+                Some(n) => n
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or_else(|| format!("invalid number of jobs '{}'", n))?,
+            },
+        },
+        "list-screens" => Command::ListScreens,
+        "help" | "--help" | "-h" => Command::Help,
+        s if s.starts_with('-') => return Err(format!("unknown option '{}'", s)),
+        s => return Err(format!("unknown command '{}'", s)),
+    };
+
+    if let Some((name, _)) = options.0.first() {
+        return Err(format!("unknown option --{} for {}", name, subcommand));
+    }
+    Ok(Some(command))
+}
+
+/// Opzioni non ancora lette
+struct Options(Vec<(String, String)>);
+
+impl Options {
+    fn take(&mut self, name: &str) -> Option<String> {
+        let i = self.0.iter().position(|(n, _)| n == name)?;
+        Some(self.0.remove(i).1)
+    }
+
+    fn required(&mut self, name: &str) -> std::result::Result<String, String> {
+        self.take(name)
+            .ok_or_else(|| format!("--{} is required", name))
+    }
+
+    fn crop(&mut self, name: &str) -> std::result::Result<Option<Crop>, String> {
+        self.take(name)
+            .map(|s| {
+                s.parse::<Crop>()
+                    .ok()
+                    .filter(|c| c.width > 0 && c.height > 0)
+                    .ok_or_else(|| format!("invalid --{} '{}', expected x,y,width,height", name, s))
+            })
+            .transpose()
+    }
+
+    fn format(&mut self) -> std::result::Result<Option<ImageFormat>, String> {
+        self.take("format")
+            .map(|s| {
+                ImageFormat::from_extension(&s).ok_or_else(|| format!("unknown format '{}'", s))
+            })
+            .transpose()
+    }
+}
+
+/// Se path può essere il file da aprire nell'interfaccia grafica
+fn is_openable(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    path.is_file()
+        || OPEN_EXTENSIONS.contains(&extension.as_str())
+        || extension == PROJECT_EXTENSION
+}
+
+/// Esegue il comando indicato dagli argomenti. Restituisce il codice di
+/// uscita, o None se va aperta l'interfaccia grafica.
+pub fn main<I>(args: I) -> Option<i32>
+where
+    I: IntoIterator<Item = String>,
+{
+    let command = match parse(args) {
+        Ok(command) => command?,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return Some(EXIT_USAGE);
+        }
+    };
+    //Solo i comandi che salvano immagini leggono la configurazione
+    let config = match command {
+        Command::Capture { .. } | Command::Annotate { .. } | Command::Batch { .. } => load_config(),
+        _ => Config::default(),
+    };
+    match run(command, &config) {
+        Ok(()) => Some(EXIT_OK),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            Some(EXIT_FAILURE)
+        }
+    }
+}
+
+/// Esegue il comando, salvando le immagini con il formato di default e le
+/// opzioni dei codificatori di config
+pub fn run(command: Command, config: &Config) -> Result<()> {
+    match command {
+        Command::Capture {
+            screen,
+            delay,
+            region,
+            format,
+            output,
+        } => {
+            std::thread::sleep(delay);
+            let (image, id) = match screen {
+                ScreenArg::All => (screenshot_all()?, None),
+                ScreenArg::Primary => {
+                    let screen = match Screen::primary()? {
+                        Some(s) => s,
+                        None => Screen::all()?
+                            .into_iter()
+                            .next()
+                            .ok_or_else(|| Error::Capture(String::from("no screen available")))?,
+                    };
+                    (screenshot(screen)?, Some(screen.id()))
+                }
+                ScreenArg::Index(n) => {
+                    let screen = Screen::all()?
+                        .into_iter()
+                        .find(|s| s.index == n)
+                        .ok_or_else(|| {
+                            Error::Capture(format!("screen {} does not exist, see list-screens", n))
+                        })?;
+                    (screenshot(screen)?, Some(screen.id()))
+                }
+            };
+            let image = match region {
+                Some(r) => crop(&image, r)?,
+                None => image,
+            };
+            let metadata = Metadata {
+                screen: id,
+                crop: region,
+                ..Metadata::new(chrono::Local::now())
+            };
+            save(&image, &output, format, &metadata, config)
+        }
+        Command::Annotate {
+            input,
+            annotations: file,
+            crop,
+            format,
+            output,
+        } => {
+            let image = image::open(&input)?.to_rgba8();
+            let mut recipe = Recipe::load(&file)?;
+            if crop.is_some() {
+                recipe.crop = crop;
+            }
+            let result = recipe.apply(&image)?;
+
+            //I metadati della cattura originale vengono conservati
+            let mut metadata = read_metadata_from_file(&input).unwrap_or_default();
+            if recipe.crop.is_some() {
+                metadata.crop = recipe.crop;
+            }
+            save(&result, &output, format, &metadata, config)
+        }
+        Command::Batch {
+            recipe,
+            input,
+            output,
+            template,
+            format,
+            jobs,
+        } => {
+            let recipe = Recipe::load(&recipe)?;
+            let inputs = batch::list_images(&input)?;
+            if inputs.is_empty() {
+                return Err(Error::io(
+                    input,
+                    std::io::Error::new(std::io::ErrorKind::NotFound, "no images found"),
+                ));
+            }
+            let options = BatchOptions {
+                template,
+                format,
+                jobs,
+                encoder: config.encoder.clone(),
+            };
+
+            //I file scritti vanno su stdout, gli errori su stderr
+            let results = batch::run(&recipe, &inputs, &output, &options)?;
+            let mut failed = 0;
+            let mut stdout = std::io::stdout().lock();
+            for (input, result) in &results {
+                match result {
+                    Ok(path) => writeln!(stdout, "{}", path.display())?,
+                    Err(e) => {
+                        eprintln!("error: {}: {}", input.display(), e);
+                        failed += 1;
+                    }
+                }
+            }
+            if failed > 0 {
+                return Err(Error::Render(format!(
+                    "{} of {} images failed",
+                    failed,
+                    results.len()
+                )));
+            }
+            Ok(())
+        }
+        Command::ListScreens => {
+            let mut stdout = std::io::stdout().lock();
+            for screen in Screen::all()? {
+                let primary = if screen.primary { "\tprimary" } else { "" };
+                writeln!(stdout, "{}\t{}{}", screen.index, screen.id(), primary)?;
+            }
+            Ok(())
+        }
+        Command::Send(request) => {
+            let path = instance::socket_path()?;
+            instance::send(&path, &[request]).map_err(|e| {
+                if instance::is_not_running(&e) {
+                    Error::Control(String::from("the graphical interface is not running"))
+                } else {
+                    e
+                }
+            })
+        }
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    }
+}
+
+fn crop(image: &RgbaImage, region: Crop) -> Result<RgbaImage> {
+    if !region.fits_in(image.width(), image.height()) {
+        return Err(Error::Capture(format!(
+            "the region {} is outside the {}x{} screen",
+            region,
+            image.width(),
+            image.height()
+        )));
+    }
+    let mut image = image.clone();
+    Ok(imageops::crop(&mut image, region.x, region.y, region.width, region.height).to_image())
+}
+
+/// Legge la configurazione dell'interfaccia grafica, per usare le stesse
+/// opzioni dei codificatori
+fn load_config() -> Config {
+    config::config_path()
+        .and_then(|p| Config::load_or_migrate(&p, Path::new(config::LEGACY_CONFIG_FILE)))
+        .unwrap_or_else(|e| {
+            eprintln!("warning: {}, using the default settings", e);
+            Config::default()
+        })
+}
+
+/// Salva l'immagine con le opzioni dei codificatori della configurazione
+fn save(
+    image: &RgbaImage,
+    output: &Path,
+    format: Option<ImageFormat>,
+    metadata: &Metadata,
+    config: &Config,
+) -> Result<()> {
+    let to_stdout = output.as_os_str() == STDOUT;
+    let format = format
+        .or_else(|| {
+            output
+                .extension()
+                .and_then(|e| ImageFormat::from_extension(&e.to_string_lossy()))
+        })
+        .unwrap_or(config.format);
+    let options: &EncoderOptions = &config.encoder;
+
+    if to_stdout {
+        let mut buf = Cursor::new(Vec::new());
+        encoding::encode(image, format, options, Some(metadata), &mut buf)?;
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(buf.get_ref())?;
+        stdout.flush()?;
+    } else {
+        encoding::save_to_file(image, output, format, options, Some(metadata))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::{Annotation, Annotations, FillStyle};
+    use crate::test_utils::temp_dir;
+    use iced::Size;
+
+    fn args(s: &str) -> Vec<String> {
+        s.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(parse(args("")), Ok(None));
+        assert_eq!(
+            parse(args("capture --screen 2 --delay 1.5 --region 10,20,300,200 --format jpeg --output out.jpg")),
+            Ok(Some(Command::Capture {
+                screen: ScreenArg::Index(2),
+                delay: Duration::from_millis(1500),
+                region: Some(Crop {
+                    x: 10,
+                    y: 20,
+                    width: 300,
+                    height: 200
+                }),
+                format: Some(ImageFormat::Jpeg),
+                output: PathBuf::from("out.jpg"),
+            }))
+        );
+        assert_eq!(
+            parse(args(
+                "annotate --input in.png --annotations=a.json --output -"
+            )),
+            Ok(Some(Command::Annotate {
+                input: PathBuf::from("in.png"),
+                annotations: PathBuf::from("a.json"),
+                crop: None,
+                format: None,
+                output: PathBuf::from("-"),
+            }))
+        );
+        assert_eq!(
+            parse(args(
+                "batch --recipe r.json --input shots --output out --jobs 4"
+            )),
+            Ok(Some(Command::Batch {
+                recipe: PathBuf::from("r.json"),
+                input: PathBuf::from("shots"),
+                output: PathBuf::from("out"),
+                template: String::from("{name}"),
+                format: None,
+                jobs: 4,
+            }))
+        );
+        assert_eq!(parse(args("list-screens")), Ok(Some(Command::ListScreens)));
+        assert_eq!(parse(args("shot.png")), Ok(None));
+        assert_eq!(parse(args("Shot.JPG")), Ok(None));
+        assert_eq!(parse(args("work.snipproj")), Ok(None));
+        assert_eq!(parse(args("Cargo.toml")), Ok(None));
+        assert_eq!(
+            parse(args("src")),
+            Err(String::from("'src' is a directory, not a file to open"))
+        );
+        assert_eq!(
+            parse(args("captur")),
+            Err(String::from("unknown command 'captur'"))
+        );
+        assert_eq!(
+            parse(args("send capture-region")),
+            Ok(Some(Command::Send(Request::CaptureRegion)))
+        );
+        assert_eq!(
+            parse(args("send open /tmp/a.png")),
+            Ok(Some(Command::Send(Request::Open(PathBuf::from(
+                "/tmp/a.png"
+            )))))
+        );
+
+        for invalid in [
+            "capture",
+            "capture --output a.png --screen 0",
+            "capture --output a.png --region 1,2,3",
+            "capture --output a.png --delay -1",
+            "capture --output a.png --format psd",
+            "capture --output a.png --colour red",
+            "capture --output",
+            "annotate --input a.png --output b.png",
+            "batch --recipe r.json --input in --output out --jobs 0",
+            "record --output a.png",
+            "send",
+            "send capture now",
+            "--verbose",
+        ] {
+            assert!(parse(args(invalid)).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn annotate_file() {
+        let dir = temp_dir("cli");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.png");
+        let json = dir.join("annotations.json");
+        let output = dir.join("out.bmp");
+        RgbaImage::from_pixel(40, 30, image::Rgba([255, 255, 255, 255]))
+            .save(&input)
+            .unwrap();
+
+        let mut annotations = Annotations::new(Size::new(40.0, 30.0), ());
+        annotations.set_annotations(
+            vec![Annotation::Rectangle {
+                color: [1.0, 0.0, 0.0, 1.0],
+                fill_style: FillStyle::Fill,
+                x: 5.0,
+                y: 5.0,
+                width: 10.0,
+                height: 10.0,
+            }],
+            Vec::new(),
+        );
+        std::fs::write(&json, annotations.to_json().unwrap()).unwrap();
+
+        let command = parse(
+            [
+                "annotate",
+                "--input",
+                input.to_str().unwrap(),
+                "--annotations",
+                json.to_str().unwrap(),
+                "--crop",
+                "0,0,20,20",
+                "--output",
+                output.to_str().unwrap(),
+            ]
+            .map(String::from),
+        )
+        .unwrap()
+        .unwrap();
+        //La configurazione dell'utente non deve influire sul test
+        let config = Config::default();
+        run(command, &config).unwrap();
+
+        let result = image::open(&output).unwrap().to_rgba8();
+        assert_eq!(result.dimensions(), (20, 20));
+        assert_eq!(result.get_pixel(10, 10), &image::Rgba([255, 0, 0, 255]));
+        assert_eq!(result.get_pixel(2, 2), &image::Rgba([255, 255, 255, 255]));
+
+        //Un crop fuori dall'immagine è un errore, non un panic
+        let command = Command::Annotate {
+            input,
+            annotations: json,
+            crop: Some(Crop {
+                x: 30,
+                y: 0,
+                width: 20,
+                height: 20,
+            }),
+            format: None,
+            output,
+        };
+        assert!(run(command, &config).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,6 +1,7 @@
 use iced::widget::radio::Appearance;
 use iced::widget::{radio, row};
 use iced::{theme, Color, Element};
+use serde::{Deserialize, Serialize};
 
 pub const COLORS: [Color; 8] = [
     Color::WHITE,
@@ -43,7 +44,8 @@ pub const COLORS: [Color; 8] = [
     },
 ];
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 #[repr(u8)]
 pub enum ToolColor {
     White = 0,
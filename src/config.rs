@@ -0,0 +1,384 @@
+//! Configurazione persistente dell'applicazione.
+//!
+//! Viene salvata in TOML in `$XDG_CONFIG_HOME/pds_project/config.toml` (o
+//! nell'equivalente della piattaforma) e contiene un numero di versione dello
+//! schema, così che le versioni future possano migrare i file più vecchi.
+//! Il vecchio `config.config` nella cartella corrente viene migrato in
+//! automatico al primo avvio.
+
+use crate::beautify::BeautifyOptions;
+use crate::color_picker::ToolColor;
+use crate::encoding::EncoderOptions;
+use crate::hotkey::{Accelerator, Binding, EditorAction, HotkeyAction};
+use crate::watermark::WatermarkOptions;
+use crate::{read_config_file, Delays, Error, ImageFormat, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Versione attuale dello schema del file di configurazione
+pub const CONFIG_VERSION: u32 = 1;
+/// Nome della cartella di configurazione dell'applicazione
+pub const APP_NAME: &str = env!("CARGO_PKG_NAME");
+/// Nome del file di configurazione
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+/// Vecchio file di configurazione a due righe, letto dalla cartella corrente
+pub const LEGACY_CONFIG_FILE: &str = "config.config";
+/// Template di default per il nome dei file salvati
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "screenshot_{date}_{time}";
+
+/// Impostazioni di default degli strumenti di annotazione
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolDefaults {
+    /// Colore selezionato all'avvio
+    pub color: ToolColor,
+    /// Spessore del tratto di penna, frecce e rettangoli
+    pub stroke_width: f32,
+    /// Spessore del tratto dell'evidenziatore
+    pub highlighter_width: f32,
+    /// Opacità dell'evidenziatore, tra 0 e 1
+    pub highlighter_opacity: f32,
+    /// Dimensione del testo
+    pub text_size: f32,
+    /// Scala dei timbri rispetto alla loro dimensione in pixel
+    pub stamp_scale: f32,
+    /// Opacità dei timbri, tra 0 e 1
+    pub stamp_opacity: f32,
+}
+
+impl Default for ToolDefaults {
+    fn default() -> Self {
+        Self {
+            color: ToolColor::Black,
+            stroke_width: 1.0,
+            highlighter_width: 3.0,
+            highlighter_opacity: 0.5,
+            text_size: 25.0,
+            stamp_scale: 1.0,
+            stamp_opacity: 1.0,
+        }
+    }
+}
+
+/// Tutte le impostazioni persistenti dell'applicazione.
+/// I campi mancanti nel file vengono riempiti con i valori di default.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Versione dello schema con cui è stato scritto il file
+    pub version: u32,
+    /// Cartella di salvataggio, se None si usa la cartella Pictures
+    pub save_path: Option<PathBuf>,
+    /// Formato di salvataggio
+    pub format: ImageFormat,
+    /// Delay prima dello screenshot
+    pub delay: Delays,
+    /// Id dello schermo da catturare, se None si usa il primario
+    pub screen: Option<u32>,
+    /// Template del nome dei file salvati, senza estensione
+    pub filename_template: String,
+    /// Cartella dei timbri, se None si usa [`crate::stamps::stamps_dir`]
+    pub stamps_dir: Option<PathBuf>,
+    /// Hotkey globali, associano un'azione a una combinazione di tasti come "Ctrl+Alt+4"
+    pub hotkeys: BTreeMap<HotkeyAction, String>,
+    /// Scorciatoie dell'editor, attive solo quando la finestra ha il focus
+    pub shortcuts: BTreeMap<EditorAction, String>,
+    /// Impostazioni degli strumenti di annotazione
+    pub tools: ToolDefaults,
+    /// Opzioni dei codificatori di Jpeg, Png e Gif
+    pub encoder: EncoderOptions,
+    /// Filigrana e piè di pagina aggiunti all'esportazione
+    pub watermark: WatermarkOptions,
+    /// Preset della presentazione: sfondo, margine, angoli e ombra
+    pub beautify: BeautifyOptions,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            save_path: None,
+            format: ImageFormat::Png,
+            delay: Delays::default(),
+            screen: None,
+            filename_template: String::from(DEFAULT_FILENAME_TEMPLATE),
+            stamps_dir: None,
+            hotkeys: HotkeyAction::default_bindings(),
+            shortcuts: EditorAction::default_bindings(),
+            tools: ToolDefaults::default(),
+            encoder: EncoderOptions::default(),
+            watermark: WatermarkOptions::default(),
+            beautify: BeautifyOptions::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Legge e valida il file di configurazione, migrando gli schemi più vecchi
+    pub fn load(path: &Path) -> Result<Self> {
+        let s = std::fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let mut config: Config = toml::from_str(&s)
+            .map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+
+        if config.version > CONFIG_VERSION {
+            return Err(Error::Config(format!(
+                "{}: schema version {} is newer than the supported {}",
+                path.display(),
+                config.version,
+                CONFIG_VERSION
+            )));
+        }
+        // Non esistono ancora schemi precedenti da convertire: basta
+        // aggiornare il numero di versione
+        config.version = CONFIG_VERSION;
+
+        // Le azioni aggiunte dopo che il file è stato scritto ricevono la
+        // combinazione di default
+        for (action, keys) in HotkeyAction::default_bindings() {
+            config.hotkeys.entry(action).or_insert(keys);
+        }
+        for (action, keys) in EditorAction::default_bindings() {
+            config.shortcuts.entry(action).or_insert(keys);
+        }
+
+        Ok(config)
+    }
+
+    /// Scrive la configurazione, creando la cartella se necessario.
+    /// Il file viene prima scritto accanto e poi rinominato, così un errore
+    /// a metà non lascia una configurazione troncata.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| Error::io(dir, e))?;
+        }
+        let s = toml::to_string_pretty(self).map_err(|e| Error::Config(e.to_string()))?;
+
+        let tmp = path.with_extension("toml.tmp");
+        std::fs::write(&tmp, s).map_err(|e| Error::io(&tmp, e))?;
+        std::fs::rename(&tmp, path).map_err(|e| Error::io(path, e))
+    }
+
+    /// Restituisce tutte le combinazioni di tasti configurate, globali e
+    /// dell'editor
+    pub fn bindings(&self) -> impl Iterator<Item = (Binding, &String)> {
+        let global = self.hotkeys.iter().map(|(a, s)| (Binding::Global(*a), s));
+        let editor = self.shortcuts.iter().map(|(a, s)| (Binding::Editor(*a), s));
+        global.chain(editor)
+    }
+
+    /// Associa accelerator all'azione indicata. Se la combinazione è già
+    /// usata da un'altra azione la configurazione non viene modificata.
+    pub fn set_binding(&mut self, binding: Binding, accelerator: &Accelerator) -> Result<()> {
+        let conflict = self.bindings().find(|(b, s)| {
+            *b != binding && s.parse::<Accelerator>().ok().as_ref() == Some(accelerator)
+        });
+        if let Some((other, _)) = conflict {
+            return Err(Error::Hotkey(format!(
+                "{} is already assigned to \"{}\"",
+                accelerator, other
+            )));
+        }
+
+        let s = accelerator.to_string();
+        match binding {
+            Binding::Global(a) => self.hotkeys.insert(a, s),
+            Binding::Editor(a) => self.shortcuts.insert(a, s),
+        };
+        Ok(())
+    }
+
+    /// Restituisce l'azione dell'editor associata alla combinazione premuta
+    pub fn editor_action(&self, accelerator: &Accelerator) -> Option<EditorAction> {
+        self.shortcuts
+            .iter()
+            .find(|(_, s)| s.parse::<Accelerator>().ok().as_ref() == Some(accelerator))
+            .map(|(a, _)| *a)
+    }
+
+    /// Carica la configurazione da path. Se non esiste ma esiste il vecchio
+    /// file legacy, lo converte e salva subito il nuovo file; altrimenti
+    /// restituisce la configurazione di default.
+    pub fn load_or_migrate(path: &Path, legacy: &Path) -> Result<Self> {
+        if path.exists() {
+            return Self::load(path);
+        }
+        if !legacy.exists() {
+            return Ok(Self::default());
+        }
+
+        let (save_path, format) = read_config_file(legacy.to_path_buf())?;
+        let config = Self {
+            save_path: Some(save_path),
+            format: format.unwrap_or(ImageFormat::Png),
+            ..Self::default()
+        };
+        config.save(path)?;
+        log::info!("Migrated {} to {}", legacy.display(), path.display());
+        Ok(config)
+    }
+}
+
+/// Sposta da parte un file di configurazione che non si riesce a leggere,
+/// così che salvare le impostazioni di default non lo sovrascriva e
+/// l'utente possa recuperarlo. Le copie già messe da parte non vengono
+/// sovrascritte. Restituisce il nuovo path del file.
+pub fn set_aside(path: &Path) -> Result<PathBuf> {
+    let backup = (0..)
+        .map(|i| match i {
+            0 => path.with_extension("toml.broken"),
+            i => path.with_extension(format!("toml.broken.{}", i)),
+        })
+        .find(|p| !p.exists())
+        .unwrap();
+    std::fs::rename(path, &backup).map_err(|e| Error::io(path, e))?;
+    log::warn!("Moved the unreadable {} to {}", path.display(), backup.display());
+    Ok(backup)
+}
+
+/// Restituisce la cartella di configurazione dell'applicazione
+#[cfg(target_os = "linux")]
+pub fn config_dir() -> Result<PathBuf> {
+    //Se XDG_CONFIG_HOME non è definita allora è settata a default in $HOME/.config
+    let base = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(p) if !p.is_empty() => PathBuf::from(p),
+        _ => {
+            let home = std::env::var("HOME")
+                .map_err(|_| Error::Config(String::from("the HOME variable is not set")))?;
+            PathBuf::from(home).join(".config")
+        }
+    };
+    Ok(base.join(APP_NAME))
+}
+
+/// Restituisce la cartella di configurazione dell'applicazione
+#[cfg(target_os = "windows")]
+pub fn config_dir() -> Result<PathBuf> {
+    let base = std::env::var("APPDATA")
+        .map_err(|_| Error::Config(String::from("the APPDATA variable is not set")))?;
+    Ok(PathBuf::from(base).join(APP_NAME))
+}
+
+/// Restituisce la cartella di configurazione dell'applicazione
+#[cfg(target_os = "macos")]
+pub fn config_dir() -> Result<PathBuf> {
+    let home = std::env::var("HOME")
+        .map_err(|_| Error::Config(String::from("the HOME variable is not set")))?;
+    Ok(PathBuf::from(home)
+        .join("Library/Application Support")
+        .join(APP_NAME))
+}
+
+/// Restituisce il path del file di configurazione
+pub fn config_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join(CONFIG_FILE_NAME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn round_trip() {
+        let dir = temp_dir("config_round_trip");
+        let path = dir.join(CONFIG_FILE_NAME);
+
+        let mut config = Config {
+            save_path: Some(PathBuf::from("pictures")),
+            format: ImageFormat::Jpeg,
+            delay: Delays::Five,
+            screen: Some(3),
+            ..Config::default()
+        };
+        config.tools.color = ToolColor::Red;
+        config.encoder.jpeg.quality = 75;
+        config.encoder.gif.dithering = false;
+        config.save(&path).unwrap();
+
+        assert_eq!(config, Config::load(&path).unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrate_legacy() {
+        let dir = temp_dir("config_migrate");
+        let path = dir.join(CONFIG_FILE_NAME);
+
+        let config = Config::load_or_migrate(&path, Path::new("tests/config.config")).unwrap();
+        assert_eq!(config.save_path, Some(PathBuf::from("path")));
+        assert_eq!(config.format, ImageFormat::Png);
+        assert!(path.exists(), "The migrated configuration was not saved");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rebind_detects_duplicates() {
+        let mut config = Config::default();
+        let save = Binding::Editor(EditorAction::Save);
+
+        let taken = config.hotkeys[&HotkeyAction::CaptureScreen].parse().unwrap();
+        assert!(matches!(config.set_binding(save, &taken), Err(Error::Hotkey(_))));
+        assert_eq!(config.shortcuts, EditorAction::default_bindings());
+
+        //Riassegnare la stessa combinazione alla stessa azione non è un duplicato
+        let current = config.shortcuts[&EditorAction::Save].parse().unwrap();
+        config.set_binding(save, &current).unwrap();
+
+        let free: Accelerator = "Ctrl+Shift+F5".parse().unwrap();
+        config.set_binding(save, &free).unwrap();
+        assert_eq!(config.shortcuts[&EditorAction::Save], "Ctrl+Shift+F5");
+        assert_eq!(config.editor_action(&free), Some(EditorAction::Save));
+        assert_eq!(config.editor_action(&current), None);
+    }
+
+    #[test]
+    fn missing_fields_and_versions() {
+        let config: Config = toml::from_str("version = 1\nformat = \"jpeg\"").unwrap();
+        assert_eq!(config.format, ImageFormat::Jpeg);
+        assert_eq!(config.filename_template, DEFAULT_FILENAME_TEMPLATE);
+
+        let dir = temp_dir("config_version");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+        std::fs::write(&path, format!("version = {}", CONFIG_VERSION + 1)).unwrap();
+        assert!(matches!(Config::load(&path), Err(Error::Config(_))));
+
+        //Le scorciatoie mancanti vengono completate con quelle di default
+        std::fs::write(&path, "version = 1\n[shortcuts]\nsave = \"Ctrl+Shift+S\"").unwrap();
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.shortcuts[&EditorAction::Save], "Ctrl+Shift+S");
+        assert_eq!(config.shortcuts[&EditorAction::Pen], "P");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn set_aside_unreadable() {
+        let dir = temp_dir("config_set_aside");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+
+        for (i, backup) in ["config.toml.broken", "config.toml.broken.1"]
+            .into_iter()
+            .enumerate()
+        {
+            let content = format!("format = [{}", i);
+            std::fs::write(&path, &content).unwrap();
+            assert!(matches!(Config::load(&path), Err(Error::Config(_))));
+
+            //Il file originale viene conservato, senza sovrascrivere le copie precedenti
+            assert_eq!(set_aside(&path).unwrap(), dir.join(backup));
+            assert_eq!(std::fs::read_to_string(dir.join(backup)).unwrap(), content);
+            assert!(!path.exists());
+        }
+
+        //Ora le impostazioni di default si possono salvare senza perdere nulla
+        Config::default().save(&path).unwrap();
+        assert_eq!(Config::load(&path).unwrap(), Config::default());
+        assert!(dir.join("config.toml.broken").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
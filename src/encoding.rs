@@ -0,0 +1,640 @@
+//! Codifica delle immagini nei formati supportati da [`ImageFormat`].
+//!
+//! I formati che non supportano la trasparenza (Jpeg e Pnm) ricevono
+//! l'immagine appiattita su uno sfondo bianco, mentre le immagini troppo
+//! grandi per il formato scelto vengono rifiutate con un errore.
+//! Jpeg, Png e Gif si possono configurare con [`EncoderOptions`]. Png, Jpeg,
+//! WebP e Tiff conservano anche i [`Metadata`] della cattura.
+
+use crate::metadata::Metadata;
+use crate::optimize::optimize_png;
+use crate::pdf::PdfOptions;
+use crate::svg::SvgOptions;
+use crate::{Error, ImageFormat, Result};
+use color_quant::NeuQuant;
+use image::codecs::pnm::{PnmSubtype, SampleEncoding};
+use image::codecs::webp::{WebPEncoder, WebPQuality};
+use image::error::{EncodingError, ImageFormatHint};
+use image::{ImageEncoder, ImageOutputFormat, Rgb, RgbImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+
+/// Colore su cui viene appiattita la trasparenza
+pub const FLATTEN_BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+
+/// Sottocampionamento della crominanza nei Jpeg
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChromaSubsampling {
+    /// Nessun sottocampionamento, il testo colorato resta nitido
+    #[default]
+    Yuv444,
+    Yuv422,
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    pub const ALL: [ChromaSubsampling; 3] = [Self::Yuv444, Self::Yuv422, Self::Yuv420];
+}
+
+impl std::fmt::Display for ChromaSubsampling {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Yuv444 => write!(f, "4:4:4"),
+            Self::Yuv422 => write!(f, "4:2:2"),
+            Self::Yuv420 => write!(f, "4:2:0"),
+        }
+    }
+}
+
+/// Livello di compressione dei Png
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub const ALL: [PngCompression; 3] = [Self::Fast, Self::Default, Self::Best];
+}
+
+impl std::fmt::Display for PngCompression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fast => write!(f, "Fast"),
+            Self::Default => write!(f, "Default"),
+            Self::Best => write!(f, "Best"),
+        }
+    }
+}
+
+/// Filtro applicato alle righe dei Png prima della compressione
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PngFilter {
+    None,
+    Sub,
+    Up,
+    Avg,
+    Paeth,
+    /// Sceglie il filtro migliore riga per riga
+    #[default]
+    Adaptive,
+}
+
+impl PngFilter {
+    pub const ALL: [PngFilter; 6] = [
+        Self::None,
+        Self::Sub,
+        Self::Up,
+        Self::Avg,
+        Self::Paeth,
+        Self::Adaptive,
+    ];
+}
+
+impl std::fmt::Display for PngFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::Sub => write!(f, "Sub"),
+            Self::Up => write!(f, "Up"),
+            Self::Avg => write!(f, "Average"),
+            Self::Paeth => write!(f, "Paeth"),
+            Self::Adaptive => write!(f, "Adaptive"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JpegOptions {
+    /// Qualità tra 1 e 100
+    pub quality: u8,
+    pub subsampling: ChromaSubsampling,
+}
+
+impl Default for JpegOptions {
+    fn default() -> Self {
+        Self {
+            quality: 90,
+            subsampling: ChromaSubsampling::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PngOptions {
+    pub compression: PngCompression,
+    pub filter: PngFilter,
+    /// Se ottimizzare il file dopo la codifica con [`crate::optimize::optimize_png`]
+    pub optimize: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GifOptions {
+    /// Numero di colori della palette, tra 4 e 256
+    pub palette_size: u16,
+    /// Se usare il dithering Floyd-Steinberg per mascherare le bande di colore
+    pub dithering: bool,
+}
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self {
+            palette_size: 256,
+            dithering: true,
+        }
+    }
+}
+
+/// Impostazioni dei codificatori dei formati configurabili
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncoderOptions {
+    pub jpeg: JpegOptions,
+    pub png: PngOptions,
+    pub gif: GifOptions,
+    pub svg: SvgOptions,
+    pub pdf: PdfOptions,
+    /// Se non scrivere alcun metadato, per condividere i file senza rivelare
+    /// quando e come sono stati catturati
+    pub strip_metadata: bool,
+}
+
+/// Compone l'immagine su uno sfondo opaco, eliminando il canale alpha
+pub fn flatten(image: &RgbaImage, background: Rgb<u8>) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let p = image.get_pixel(x, y);
+        let a = p[3] as u32;
+        let blend = |c: u8, bg: u8| ((c as u32 * a + bg as u32 * (255 - a) + 127) / 255) as u8;
+        Rgb([
+            blend(p[0], background[0]),
+            blend(p[1], background[1]),
+            blend(p[2], background[2]),
+        ])
+    })
+}
+
+/// Codifica l'immagine nel formato indicato scrivendola su w, insieme agli
+/// eventuali metadati. Se l'ottimizzazione dei Png è attiva restituisce i
+/// byte risparmiati.
+pub fn encode<W: Write + Seek>(
+    image: &RgbaImage,
+    format: ImageFormat,
+    options: &EncoderOptions,
+    metadata: Option<&Metadata>,
+    w: &mut W,
+) -> Result<Option<usize>> {
+    let metadata = metadata.filter(|_| !options.strip_metadata);
+    if let Some(max) = format.max_dimension() {
+        if image.width() > max || image.height() > max {
+            return Err(encoding_error(
+                format,
+                format!(
+                    "{}x{} exceeds the maximum size of {}x{} pixels",
+                    image.width(),
+                    image.height(),
+                    max,
+                    max
+                ),
+            ));
+        }
+    }
+
+    match format {
+        ImageFormat::Jpeg => encode_jpeg(image, &options.jpeg, metadata, w)?,
+        ImageFormat::Png if options.png.optimize => {
+            let mut data = Vec::new();
+            encode_png(image, &options.png, metadata, &mut data)?;
+            let optimized = optimize_png(&data)?;
+            w.write_all(&optimized.data)?;
+            return Ok(Some(optimized.saved()));
+        }
+        ImageFormat::Png => encode_png(image, &options.png, metadata, w)?,
+        ImageFormat::Gif => encode_gif(image, &options.gif, w)?,
+        ImageFormat::Pnm => flatten(image, FLATTEN_BACKGROUND).write_to(
+            w,
+            ImageOutputFormat::Pnm(PnmSubtype::Pixmap(SampleEncoding::Binary)),
+        )?,
+        ImageFormat::WebP => encode_webp(image, metadata, w)?,
+        ImageFormat::Tiff => match metadata {
+            Some(metadata) => w.write_all(&metadata.to_tiff(image)?)?,
+            None => image.write_to(w, image::ImageFormat::Tiff)?,
+        },
+        f => image.write_to(w, Into::<image::ImageFormat>::into(f))?,
+    }
+    Ok(None)
+}
+
+/// Codifica un Png con il crate png, che a differenza del codificatore di
+/// image permette di aggiungere i chunk di testo
+fn encode_png<W: Write>(
+    image: &RgbaImage,
+    options: &PngOptions,
+    metadata: Option<&Metadata>,
+    w: &mut W,
+) -> Result<()> {
+    let png_error = |e: png::EncodingError| encoding_error(ImageFormat::Png, e.to_string());
+    let mut encoder = png::Encoder::new(w, image.width(), image.height());
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(match options.compression {
+        PngCompression::Fast => png::Compression::Fast,
+        PngCompression::Default => png::Compression::Default,
+        PngCompression::Best => png::Compression::Best,
+    });
+    match options.filter {
+        PngFilter::None => encoder.set_filter(png::FilterType::NoFilter),
+        PngFilter::Sub => encoder.set_filter(png::FilterType::Sub),
+        PngFilter::Up => encoder.set_filter(png::FilterType::Up),
+        PngFilter::Avg => encoder.set_filter(png::FilterType::Avg),
+        PngFilter::Paeth => encoder.set_filter(png::FilterType::Paeth),
+        PngFilter::Adaptive => encoder.set_adaptive_filter(png::AdaptiveFilterType::Adaptive),
+    }
+    for (keyword, text, utf8) in metadata.map(Metadata::png_texts).unwrap_or_default() {
+        if utf8 {
+            encoder.add_itxt_chunk(keyword.to_string(), text)
+        } else {
+            encoder.add_text_chunk(keyword.to_string(), text)
+        }
+        .map_err(png_error)?;
+    }
+
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(image.as_raw()).map_err(png_error)?;
+    writer.finish().map_err(png_error)
+}
+
+/// Codifica un WebP senza perdita. I metadati vengono scritti come EXIF in
+/// un chunk del formato esteso, che richiede un chunk VP8X in testa.
+fn encode_webp<W: Write>(image: &RgbaImage, metadata: Option<&Metadata>, w: &mut W) -> Result<()> {
+    let mut data = Vec::new();
+    //Gli screenshot contengono soprattutto testo, che la compressione con perdita rovina
+    WebPEncoder::new_with_quality(&mut data, WebPQuality::lossless()).write_image(
+        image.as_raw(),
+        image.width(),
+        image.height(),
+        image::ColorType::Rgba8,
+    )?;
+    let Some(metadata) = metadata else {
+        w.write_all(&data)?;
+        return Ok(());
+    };
+
+    //libwebp scrive il formato semplice: RIFF, dimensione, WEBP e un solo chunk VP8L
+    let bitstream = data
+        .get(12..)
+        .filter(|chunk| chunk.starts_with(b"VP8L"))
+        .ok_or_else(|| {
+            encoding_error(
+                ImageFormat::WebP,
+                String::from("unexpected lossless WebP layout"),
+            )
+        })?;
+    let mut vp8x = [0u8; 10];
+    //Flag EXIF, più il flag alpha se qualche pixel è trasparente
+    vp8x[0] = 0x08;
+    if image.pixels().any(|p| p[3] < 255) {
+        vp8x[0] |= 0x10;
+    }
+    //Dimensioni della tela meno uno, su 24 bit
+    vp8x[4..7].copy_from_slice(&image.width().saturating_sub(1).to_le_bytes()[..3]);
+    vp8x[7..10].copy_from_slice(&image.height().saturating_sub(1).to_le_bytes()[..3]);
+
+    let mut chunks = Vec::new();
+    push_riff_chunk(&mut chunks, b"VP8X", &vp8x);
+    chunks.extend_from_slice(bitstream);
+    push_riff_chunk(&mut chunks, b"EXIF", &metadata.to_exif()?);
+    w.write_all(b"RIFF")?;
+    w.write_all(&(chunks.len() as u32 + 4).to_le_bytes())?;
+    w.write_all(b"WEBP")?;
+    w.write_all(&chunks)?;
+    Ok(())
+}
+
+/// Aggiunge a buf un chunk RIFF, con il byte di riempimento se la sua
+/// lunghezza è dispari
+fn push_riff_chunk(buf: &mut Vec<u8>, fourcc: &[u8; 4], data: &[u8]) {
+    buf.extend_from_slice(fourcc);
+    buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buf.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        buf.push(0);
+    }
+}
+
+/// Codifica un Jpeg con jpeg-encoder, che a differenza del codificatore di
+/// image permette di scegliere il sottocampionamento. I metadati vengono
+/// scritti come EXIF in un segmento APP1.
+fn encode_jpeg<W: Write>(
+    image: &RgbaImage,
+    options: &JpegOptions,
+    metadata: Option<&Metadata>,
+    w: &mut W,
+) -> Result<()> {
+    let jpeg_error =
+        |e: jpeg_encoder::EncodingError| encoding_error(ImageFormat::Jpeg, e.to_string());
+    let flat = flatten(image, FLATTEN_BACKGROUND);
+    let mut encoder = jpeg_encoder::Encoder::new(w, options.quality.clamp(1, 100));
+    encoder.set_sampling_factor(match options.subsampling {
+        ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+        ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+        ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+    });
+    if let Some(metadata) = metadata {
+        let mut segment = b"Exif\0\0".to_vec();
+        segment.extend_from_slice(&metadata.to_exif()?);
+        encoder.add_app_segment(1, &segment).map_err(jpeg_error)?;
+    }
+    encoder
+        .encode(
+            flat.as_raw(),
+            flat.width() as u16,
+            flat.height() as u16,
+            jpeg_encoder::ColorType::Rgb,
+        )
+        .map_err(jpeg_error)
+}
+
+/// Riduce l'immagine alla palette indicata e la codifica come Gif. I pixel
+/// quasi trasparenti usano un indice della palette riservato.
+fn encode_gif<W: Write>(image: &RgbaImage, options: &GifOptions, w: &mut W) -> Result<()> {
+    let palette_size = options.palette_size.clamp(4, 256) as usize;
+    let transparent = image.pixels().any(|p| p[3] < 128);
+    let colors = if transparent {
+        palette_size - 1
+    } else {
+        palette_size
+    };
+
+    //La palette viene calcolata sui colori opachi
+    let opaque: Vec<u8> = image
+        .pixels()
+        .flat_map(|p| [p[0], p[1], p[2], 255])
+        .collect();
+    let quant = NeuQuant::new(10, colors, &opaque);
+    let mut palette = quant.color_map_rgb();
+    let transparent_index = if transparent {
+        palette.extend_from_slice(&[0, 0, 0]);
+        Some((palette.len() / 3 - 1) as u8)
+    } else {
+        None
+    };
+
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut indices = vec![0u8; width * height];
+    //Errore di quantizzazione da propagare ai pixel successivi, riga corrente e successiva
+    let mut error = vec![[0f32; 3]; width * 2];
+    for y in 0..height {
+        let (current, next) = error.split_at_mut(width);
+        for x in 0..width {
+            let p = image.get_pixel(x as u32, y as u32);
+            if p[3] < 128 {
+                indices[y * width + x] = transparent_index.unwrap_or(0);
+                continue;
+            }
+
+            let mut c = [p[0] as f32, p[1] as f32, p[2] as f32];
+            if options.dithering {
+                for (v, e) in c.iter_mut().zip(current[x]) {
+                    *v = (*v + e).clamp(0.0, 255.0);
+                }
+            }
+            let index = quant.index_of(&[c[0] as u8, c[1] as u8, c[2] as u8, 255]);
+            indices[y * width + x] = index as u8;
+
+            if options.dithering {
+                //Floyd-Steinberg: 7/16 a destra, 3/16 in basso a sinistra, 5/16 sotto, 1/16 in basso a destra
+                let chosen = &palette[index * 3..index * 3 + 3];
+                for i in 0..3 {
+                    let e = c[i] - chosen[i] as f32;
+                    if x + 1 < width {
+                        current[x + 1][i] += e * 7.0 / 16.0;
+                        next[x + 1][i] += e / 16.0;
+                    }
+                    if x > 0 {
+                        next[x - 1][i] += e * 3.0 / 16.0;
+                    }
+                    next[x][i] += e * 5.0 / 16.0;
+                }
+            }
+        }
+        current.copy_from_slice(next);
+        next.fill([0.0; 3]);
+    }
+
+    let gif_error = |e: gif::EncodingError| encoding_error(ImageFormat::Gif, e.to_string());
+    let mut encoder =
+        gif::Encoder::new(w, width as u16, height as u16, &palette).map_err(gif_error)?;
+    let frame = gif::Frame {
+        width: width as u16,
+        height: height as u16,
+        transparent: transparent_index,
+        buffer: Cow::Owned(indices),
+        ..gif::Frame::default()
+    };
+    encoder.write_frame(&frame).map_err(gif_error)
+}
+
+/// Salva l'immagine in path. Se la codifica fallisce il file parziale viene
+/// eliminato, così non resta un file corrotto al posto dello screenshot.
+/// Come [`encode`] restituisce gli eventuali byte risparmiati.
+pub fn save_to_file(
+    image: &RgbaImage,
+    path: &Path,
+    format: ImageFormat,
+    options: &EncoderOptions,
+    metadata: Option<&Metadata>,
+) -> Result<Option<usize>> {
+    let file = File::create(path).map_err(|e| Error::io(path, e))?;
+    write_to_file(image, file, path, format, options, metadata)
+}
+
+/// Come [`save_to_file`], ma su un file già aperto, ad esempio quello
+/// creato da [`crate::naming::FileName::create`]
+pub fn write_to_file(
+    image: &RgbaImage,
+    file: File,
+    path: &Path,
+    format: ImageFormat,
+    options: &EncoderOptions,
+    metadata: Option<&Metadata>,
+) -> Result<Option<usize>> {
+    let mut writer = BufWriter::new(file);
+    let result = encode(image, format, options, metadata, &mut writer).and_then(|saved| {
+        writer.flush().map_err(|e| Error::io(path, e))?;
+        Ok(saved)
+    });
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(path);
+    }
+    result.map_err(|e| match e {
+        Error::Io { path: None, source } => Error::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        },
+        e => e,
+    })
+}
+
+fn encoding_error(format: ImageFormat, message: String) -> Error {
+    image::ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(format.into()),
+        message,
+    ))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ALL_FORMATS;
+    use image::Rgba;
+    use std::io::Cursor;
+
+    /// Immagine di prova con un pixel semitrasparente e uno trasparente
+    fn test_image() -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(16, 8, Rgba([200, 10, 10, 255]));
+        image.put_pixel(0, 0, Rgba([0, 0, 0, 128]));
+        image.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+        image
+    }
+
+    #[test]
+    fn encode_all_formats() {
+        let image = test_image();
+
+        for format in ALL_FORMATS {
+            let mut buf = Cursor::new(Vec::new());
+            encode(&image, format, &EncoderOptions::default(), None, &mut buf).unwrap();
+
+            let decoded =
+                image::load_from_memory_with_format(buf.get_ref(), format.into()).unwrap();
+            assert_eq!(
+                (decoded.width(), decoded.height()),
+                image.dimensions(),
+                "{} changed the image size",
+                format
+            );
+
+            let decoded = decoded.to_rgba8();
+            if format.supports_alpha() {
+                assert_eq!(decoded.get_pixel(1, 0)[3], 0, "{} lost the alpha", format);
+            } else {
+                assert!(
+                    decoded.pixels().all(|p| p[3] == 255),
+                    "{} kept the alpha",
+                    format
+                );
+            }
+            if format == ImageFormat::Pnm {
+                assert_eq!(decoded.get_pixel(1, 0), &Rgba([255, 255, 255, 255]));
+            }
+            //Il WebP è senza perdita: a parte il colore dei pixel del tutto
+            //trasparenti, che libwebp può cambiare, l'immagine resta uguale
+            if format == ImageFormat::WebP {
+                assert!(
+                    decoded
+                        .pixels()
+                        .zip(image.pixels())
+                        .all(|(d, o)| d == o || (d[3] == 0 && o[3] == 0)),
+                    "WebP is not lossless"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn encoder_options() {
+        let image = image::open("tests/screenshot.png").unwrap().to_rgba8();
+        let encoded_size = |format, options: &EncoderOptions| {
+            let mut buf = Cursor::new(Vec::new());
+            encode(&image, format, options, None, &mut buf).unwrap();
+            buf.into_inner().len()
+        };
+
+        let mut options = EncoderOptions::default();
+        let high = encoded_size(ImageFormat::Jpeg, &options);
+        options.jpeg.quality = 30;
+        options.jpeg.subsampling = ChromaSubsampling::Yuv420;
+        assert!(encoded_size(ImageFormat::Jpeg, &options) < high);
+
+        options.png.compression = PngCompression::Fast;
+        let fast = encoded_size(ImageFormat::Png, &options);
+        options.png.compression = PngCompression::Best;
+        assert!(encoded_size(ImageFormat::Png, &options) <= fast);
+
+        options.png.optimize = true;
+        let small = test_image();
+        let mut buf = Cursor::new(Vec::new());
+        let saved = encode(&small, ImageFormat::Png, &options, None, &mut buf).unwrap();
+        assert!(saved.is_some());
+        assert_eq!(
+            image::load_from_memory(buf.get_ref()).unwrap().to_rgba8(),
+            small
+        );
+
+        //Con una palette ridotta i colori del Gif non superano la dimensione richiesta
+        options.gif = GifOptions {
+            palette_size: 16,
+            dithering: true,
+        };
+        let mut buf = Cursor::new(Vec::new());
+        encode(&image, ImageFormat::Gif, &options, None, &mut buf).unwrap();
+        let gif = image::load_from_memory(buf.get_ref()).unwrap().to_rgba8();
+        let colors: std::collections::HashSet<_> = gif.pixels().collect();
+        assert!(colors.len() <= 16);
+    }
+
+    #[test]
+    fn flatten_alpha() {
+        let flat = flatten(&test_image(), FLATTEN_BACKGROUND);
+        assert_eq!(flat.get_pixel(0, 0), &Rgb([127, 127, 127]));
+        assert_eq!(flat.get_pixel(1, 0), &Rgb([255, 255, 255]));
+        assert_eq!(flat.get_pixel(2, 0), &Rgb([200, 10, 10]));
+    }
+
+    #[test]
+    fn reject_too_large() {
+        let image = RgbaImage::new(257, 10);
+        let mut buf = Cursor::new(Vec::new());
+        assert!(matches!(
+            encode(
+                &image,
+                ImageFormat::Ico,
+                &EncoderOptions::default(),
+                None,
+                &mut buf
+            ),
+            Err(Error::Encode(_))
+        ));
+    }
+
+    #[test]
+    fn format_names_round_trip() {
+        for format in ALL_FORMATS {
+            assert_eq!(
+                ImageFormat::from_extension(format.extensions()[0]),
+                Some(format)
+            );
+            let toml = toml::to_string(&crate::config::Config {
+                format,
+                ..Default::default()
+            })
+            .unwrap();
+            let config: crate::config::Config = toml::from_str(&toml).unwrap();
+            assert_eq!(config.format, format);
+        }
+        assert!(ImageFormat::try_from(image::ImageFormat::OpenExr).is_err());
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt::Formatter;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Errori restituiti dalle funzioni della libreria.
+/// È Clone, e quindi può viaggiare dentro i messaggi di iced, perché le cause
+/// che non lo sono vengono condivise tramite Arc.
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// Non è stato possibile enumerare gli schermi o catturarne uno
+    Capture(String),
+    /// La configurazione è mancante, malformata o incompleta
+    Config(String),
+    /// Lettura o scrittura su file non riuscita
+    Io {
+        path: Option<PathBuf>,
+        source: Arc<std::io::Error>,
+    },
+    /// Codifica o decodifica dell'immagine non riuscita
+    Encode(Arc<image::ImageError>),
+    /// Non è stato possibile renderizzare le annotazioni sull'immagine
+    Render(String),
+    /// Accesso agli appunti non riuscito
+    Clipboard(String),
+    /// Creazione del gestore o registrazione di una hotkey non riuscita
+    Hotkey(String),
+    /// Il file di progetto è malformato o di una versione più recente
+    Project(String),
+    /// Le annotazioni importate sono malformate o di una versione più recente
+    Annotations(String),
+    /// L'istanza in esecuzione ha rifiutato un comando o non è raggiungibile
+    Control(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Costruisce un errore di IO ricordando il file coinvolto
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        Self::Io {
+            path: Some(path.into()),
+            source: Arc::new(source),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Capture(s) => write!(f, "Screen capture failed: {}", s),
+            Error::Config(s) => write!(f, "Invalid configuration: {}", s),
+            Error::Io {
+                path: Some(p),
+                source,
+            } => write!(f, "{}: {}", p.display(), source),
+            Error::Io { path: None, source } => write!(f, "I/O error: {}", source),
+            Error::Encode(e) => write!(f, "Image encoding failed: {}", e),
+            Error::Render(s) => write!(f, "Rendering failed: {}", s),
+            Error::Clipboard(s) => write!(f, "Clipboard unavailable: {}", s),
+            Error::Hotkey(s) => write!(f, "Hotkey error: {}", s),
+            Error::Project(s) => write!(f, "Invalid project: {}", s),
+            Error::Annotations(s) => write!(f, "Invalid annotations: {}", s),
+            Error::Control(s) => write!(f, "Control request failed: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source.as_ref()),
+            Error::Encode(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io {
+            path: None,
+            source: Arc::new(value),
+        }
+    }
+}
+
+impl From<image::ImageError> for Error {
+    fn from(value: image::ImageError) -> Self {
+        Self::Encode(Arc::new(value))
+    }
+}
+
+impl From<arboard::Error> for Error {
+    fn from(value: arboard::Error) -> Self {
+        Self::Clipboard(value.to_string())
+    }
+}
+
+impl From<global_hotkey::Error> for Error {
+    fn from(value: global_hotkey::Error) -> Self {
+        Self::Hotkey(value.to_string())
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(value: zip::result::ZipError) -> Self {
+        match value {
+            zip::result::ZipError::Io(e) => e.into(),
+            e => Self::Project(e.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Project(value.to_string())
+    }
+}
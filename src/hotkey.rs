@@ -1,14 +1,23 @@
-use global_hotkey::GlobalHotKeyEvent;
+use crate::{Error, Result};
+use global_hotkey::hotkey::{Code, HotKey, Modifiers};
+use global_hotkey::{GlobalHotKeyEvent, GlobalHotKeyManager};
+use iced::keyboard::{self, KeyCode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::future;
 use std::pin::Pin;
+use std::str::FromStr;
 use std::sync::Mutex;
 use std::task::{Context, Poll};
 
+/// Future che si risolve quando viene premuta una qualunque hotkey globale.
+/// Il messaggio prodotto contiene l'id della hotkey premuta, che va poi
+/// tradotto nell'azione corrispondente con [`HotkeyBindings::action`].
 pub struct Hotkey<Message> {
-    pub msg: Message,
+    pub on_press: fn(u32) -> Message,
 }
 
-impl<Message: Clone> future::Future for Hotkey<Message> {
+impl<Message> future::Future for Hotkey<Message> {
     type Output = (Message, ());
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
@@ -36,12 +45,556 @@ impl<Message: Clone> future::Future for Hotkey<Message> {
         };
 
         if let Ok(mut fh_mg) = FIRED_HOTKEY.lock() {
-            if fh_mg.is_some() {
-                *fh_mg = None;
-                return Poll::Ready((self.msg.clone(), ()));
+            if let Some(id) = fh_mg.take() {
+                return Poll::Ready(((self.on_press)(id), ()));
             }
         }
 
         return Poll::Pending;
     }
 }
+
+/// Azioni che possono essere associate a una hotkey globale
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HotkeyAction {
+    CaptureScreen,
+    CaptureRegion,
+    CaptureAllScreens,
+    RepeatLastCapture,
+    CopyLastCapture,
+}
+
+impl HotkeyAction {
+    pub const ALL: [HotkeyAction; 5] = [
+        HotkeyAction::CaptureScreen,
+        HotkeyAction::CaptureRegion,
+        HotkeyAction::CaptureAllScreens,
+        HotkeyAction::RepeatLastCapture,
+        HotkeyAction::CopyLastCapture,
+    ];
+
+    /// Hotkey di default di ogni azione
+    pub fn default_bindings() -> BTreeMap<HotkeyAction, String> {
+        BTreeMap::from([
+            (HotkeyAction::CaptureScreen, String::from("Shift+D")),
+            (HotkeyAction::CaptureRegion, String::from("Ctrl+Alt+R")),
+            (HotkeyAction::CaptureAllScreens, String::from("Ctrl+Alt+A")),
+            (HotkeyAction::RepeatLastCapture, String::from("Ctrl+Alt+L")),
+            (HotkeyAction::CopyLastCapture, String::from("Ctrl+Alt+C")),
+        ])
+    }
+}
+
+impl std::fmt::Display for HotkeyAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                HotkeyAction::CaptureScreen => "Capture screen",
+                HotkeyAction::CaptureRegion => "Capture region",
+                HotkeyAction::CaptureAllScreens => "Capture all screens",
+                HotkeyAction::RepeatLastCapture => "Repeat last capture",
+                HotkeyAction::CopyLastCapture => "Copy last capture",
+            }
+        )
+    }
+}
+
+/// Azioni dell'editor associabili a una scorciatoia, attive solo quando la
+/// finestra ha il focus
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EditorAction {
+    Copy,
+    Paste,
+    Save,
+    Open,
+    Pen,
+    Rectangle,
+    Ellipse,
+    Arrow,
+    Text,
+    Highlighter,
+    Crop,
+    Deselect,
+    DeleteAnnotation,
+    CheatSheet,
+}
+
+impl EditorAction {
+    pub const ALL: [EditorAction; 14] = [
+        EditorAction::Copy,
+        EditorAction::Paste,
+        EditorAction::Save,
+        EditorAction::Open,
+        EditorAction::Pen,
+        EditorAction::Rectangle,
+        EditorAction::Ellipse,
+        EditorAction::Arrow,
+        EditorAction::Text,
+        EditorAction::Highlighter,
+        EditorAction::Crop,
+        EditorAction::Deselect,
+        EditorAction::DeleteAnnotation,
+        EditorAction::CheatSheet,
+    ];
+
+    /// Scorciatoie di default di ogni azione, con Cmd al posto di Ctrl su macOS.
+    /// Gli strumenti si selezionano con un solo tasto.
+    pub fn default_bindings() -> BTreeMap<EditorAction, String> {
+        let modifier = if cfg!(target_os = "macos") {
+            "Super"
+        } else {
+            "Ctrl"
+        };
+        BTreeMap::from([
+            (EditorAction::Copy, format!("{}+C", modifier)),
+            (EditorAction::Paste, format!("{}+V", modifier)),
+            (EditorAction::Save, format!("{}+S", modifier)),
+            (EditorAction::Open, format!("{}+O", modifier)),
+            (EditorAction::Pen, String::from("P")),
+            (EditorAction::Rectangle, String::from("R")),
+            (EditorAction::Ellipse, String::from("E")),
+            (EditorAction::Arrow, String::from("A")),
+            (EditorAction::Text, String::from("T")),
+            (EditorAction::Highlighter, String::from("H")),
+            (EditorAction::Crop, String::from("C")),
+            (EditorAction::Deselect, String::from("Escape")),
+            (EditorAction::DeleteAnnotation, String::from("Delete")),
+            (EditorAction::CheatSheet, String::from("F1")),
+        ])
+    }
+}
+
+impl std::fmt::Display for EditorAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                EditorAction::Copy => "Copy to clipboard",
+                EditorAction::Paste => "Paste image from clipboard",
+                EditorAction::Save => "Save",
+                EditorAction::Open => "Open image or project",
+                EditorAction::Pen => "Pen",
+                EditorAction::Rectangle => "Rectangle",
+                EditorAction::Ellipse => "Ellipse",
+                EditorAction::Arrow => "Arrow",
+                EditorAction::Text => "Text",
+                EditorAction::Highlighter => "Highlighter",
+                EditorAction::Crop => "Crop",
+                EditorAction::Deselect => "Deselect tool",
+                EditorAction::DeleteAnnotation => "Delete selected annotation",
+                EditorAction::CheatSheet => "Show shortcuts",
+            }
+        )
+    }
+}
+
+/// Una qualunque azione associabile a una combinazione di tasti, globale o
+/// dell'editor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Global(HotkeyAction),
+    Editor(EditorAction),
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Global(a) => write!(f, "{}", a),
+            Binding::Editor(a) => write!(f, "{}", a),
+        }
+    }
+}
+
+/// Nomi dei tasti accettati negli acceleratori. Il primo nome di ogni tasto è
+/// quello usato per mostrarlo, gli altri sono alias.
+const KEY_NAMES: &[(&str, Code)] = &[
+    ("A", Code::KeyA),
+    ("B", Code::KeyB),
+    ("C", Code::KeyC),
+    ("D", Code::KeyD),
+    ("E", Code::KeyE),
+    ("F", Code::KeyF),
+    ("G", Code::KeyG),
+    ("H", Code::KeyH),
+    ("I", Code::KeyI),
+    ("J", Code::KeyJ),
+    ("K", Code::KeyK),
+    ("L", Code::KeyL),
+    ("M", Code::KeyM),
+    ("N", Code::KeyN),
+    ("O", Code::KeyO),
+    ("P", Code::KeyP),
+    ("Q", Code::KeyQ),
+    ("R", Code::KeyR),
+    ("S", Code::KeyS),
+    ("T", Code::KeyT),
+    ("U", Code::KeyU),
+    ("V", Code::KeyV),
+    ("W", Code::KeyW),
+    ("X", Code::KeyX),
+    ("Y", Code::KeyY),
+    ("Z", Code::KeyZ),
+    ("0", Code::Digit0),
+    ("1", Code::Digit1),
+    ("2", Code::Digit2),
+    ("3", Code::Digit3),
+    ("4", Code::Digit4),
+    ("5", Code::Digit5),
+    ("6", Code::Digit6),
+    ("7", Code::Digit7),
+    ("8", Code::Digit8),
+    ("9", Code::Digit9),
+    ("F1", Code::F1),
+    ("F2", Code::F2),
+    ("F3", Code::F3),
+    ("F4", Code::F4),
+    ("F5", Code::F5),
+    ("F6", Code::F6),
+    ("F7", Code::F7),
+    ("F8", Code::F8),
+    ("F9", Code::F9),
+    ("F10", Code::F10),
+    ("F11", Code::F11),
+    ("F12", Code::F12),
+    ("Space", Code::Space),
+    ("Enter", Code::Enter),
+    ("Return", Code::Enter),
+    ("Tab", Code::Tab),
+    ("Escape", Code::Escape),
+    ("Esc", Code::Escape),
+    ("Backspace", Code::Backspace),
+    ("Delete", Code::Delete),
+    ("Del", Code::Delete),
+    ("Insert", Code::Insert),
+    ("Home", Code::Home),
+    ("End", Code::End),
+    ("PageUp", Code::PageUp),
+    ("PageDown", Code::PageDown),
+    ("Up", Code::ArrowUp),
+    ("Down", Code::ArrowDown),
+    ("Left", Code::ArrowLeft),
+    ("Right", Code::ArrowRight),
+    ("PrintScreen", Code::PrintScreen),
+    ("Print", Code::PrintScreen),
+    ("-", Code::Minus),
+    ("Minus", Code::Minus),
+    ("=", Code::Equal),
+    ("Equal", Code::Equal),
+    (",", Code::Comma),
+    ("Comma", Code::Comma),
+    (".", Code::Period),
+    ("Period", Code::Period),
+    ("/", Code::Slash),
+    ("Slash", Code::Slash),
+    ("\\", Code::Backslash),
+    ("Backslash", Code::Backslash),
+    (";", Code::Semicolon),
+    ("Semicolon", Code::Semicolon),
+    ("'", Code::Quote),
+    ("Quote", Code::Quote),
+    ("[", Code::BracketLeft),
+    ("]", Code::BracketRight),
+    ("`", Code::Backquote),
+];
+
+/// Combinazione di tasti nel formato testuale "Ctrl+Alt+4": zero o più
+/// modificatori seguiti da un tasto, separati da '+'. Il confronto dei nomi
+/// non distingue maiuscole e minuscole.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Accelerator {
+    pub modifiers: Modifiers,
+    pub key: Code,
+}
+
+impl Accelerator {
+    pub fn new(modifiers: Modifiers, key: Code) -> Self {
+        Self { modifiers, key }
+    }
+
+    /// Restituisce la hotkey globale corrispondente
+    pub fn to_hotkey(&self) -> HotKey {
+        let mods = if self.modifiers.is_empty() {
+            None
+        } else {
+            Some(self.modifiers)
+        };
+        HotKey::new(mods, self.key)
+    }
+
+    /// Converte un tasto premuto nella finestra nell'acceleratore
+    /// corrispondente. Restituisce None per i tasti che non possono far parte
+    /// di un acceleratore, come i modificatori premuti da soli.
+    pub fn from_iced(key_code: KeyCode, modifiers: keyboard::Modifiers) -> Option<Self> {
+        let key = match key_code {
+            KeyCode::A => Code::KeyA,
+            KeyCode::B => Code::KeyB,
+            KeyCode::C => Code::KeyC,
+            KeyCode::D => Code::KeyD,
+            KeyCode::E => Code::KeyE,
+            KeyCode::F => Code::KeyF,
+            KeyCode::G => Code::KeyG,
+            KeyCode::H => Code::KeyH,
+            KeyCode::I => Code::KeyI,
+            KeyCode::J => Code::KeyJ,
+            KeyCode::K => Code::KeyK,
+            KeyCode::L => Code::KeyL,
+            KeyCode::M => Code::KeyM,
+            KeyCode::N => Code::KeyN,
+            KeyCode::O => Code::KeyO,
+            KeyCode::P => Code::KeyP,
+            KeyCode::Q => Code::KeyQ,
+            KeyCode::R => Code::KeyR,
+            KeyCode::S => Code::KeyS,
+            KeyCode::T => Code::KeyT,
+            KeyCode::U => Code::KeyU,
+            KeyCode::V => Code::KeyV,
+            KeyCode::W => Code::KeyW,
+            KeyCode::X => Code::KeyX,
+            KeyCode::Y => Code::KeyY,
+            KeyCode::Z => Code::KeyZ,
+            KeyCode::Key0 | KeyCode::Numpad0 => Code::Digit0,
+            KeyCode::Key1 | KeyCode::Numpad1 => Code::Digit1,
+            KeyCode::Key2 | KeyCode::Numpad2 => Code::Digit2,
+            KeyCode::Key3 | KeyCode::Numpad3 => Code::Digit3,
+            KeyCode::Key4 | KeyCode::Numpad4 => Code::Digit4,
+            KeyCode::Key5 | KeyCode::Numpad5 => Code::Digit5,
+            KeyCode::Key6 | KeyCode::Numpad6 => Code::Digit6,
+            KeyCode::Key7 | KeyCode::Numpad7 => Code::Digit7,
+            KeyCode::Key8 | KeyCode::Numpad8 => Code::Digit8,
+            KeyCode::Key9 | KeyCode::Numpad9 => Code::Digit9,
+            KeyCode::F1 => Code::F1,
+            KeyCode::F2 => Code::F2,
+            KeyCode::F3 => Code::F3,
+            KeyCode::F4 => Code::F4,
+            KeyCode::F5 => Code::F5,
+            KeyCode::F6 => Code::F6,
+            KeyCode::F7 => Code::F7,
+            KeyCode::F8 => Code::F8,
+            KeyCode::F9 => Code::F9,
+            KeyCode::F10 => Code::F10,
+            KeyCode::F11 => Code::F11,
+            KeyCode::F12 => Code::F12,
+            KeyCode::Space => Code::Space,
+            KeyCode::Enter | KeyCode::NumpadEnter => Code::Enter,
+            KeyCode::Tab => Code::Tab,
+            KeyCode::Escape => Code::Escape,
+            KeyCode::Backspace => Code::Backspace,
+            KeyCode::Delete => Code::Delete,
+            KeyCode::Insert => Code::Insert,
+            KeyCode::Home => Code::Home,
+            KeyCode::End => Code::End,
+            KeyCode::PageUp => Code::PageUp,
+            KeyCode::PageDown => Code::PageDown,
+            KeyCode::Up => Code::ArrowUp,
+            KeyCode::Down => Code::ArrowDown,
+            KeyCode::Left => Code::ArrowLeft,
+            KeyCode::Right => Code::ArrowRight,
+            KeyCode::Snapshot => Code::PrintScreen,
+            KeyCode::Minus => Code::Minus,
+            KeyCode::Equals => Code::Equal,
+            KeyCode::Comma => Code::Comma,
+            KeyCode::Period => Code::Period,
+            KeyCode::Slash => Code::Slash,
+            KeyCode::Backslash => Code::Backslash,
+            KeyCode::Semicolon => Code::Semicolon,
+            KeyCode::Apostrophe => Code::Quote,
+            KeyCode::LBracket => Code::BracketLeft,
+            KeyCode::RBracket => Code::BracketRight,
+            KeyCode::Grave => Code::Backquote,
+            _ => return None,
+        };
+
+        let mut mods = Modifiers::empty();
+        for (pressed, m) in [
+            (modifiers.control(), Modifiers::CONTROL),
+            (modifiers.alt(), Modifiers::ALT),
+            (modifiers.shift(), Modifiers::SHIFT),
+            (modifiers.logo(), Modifiers::SUPER),
+        ] {
+            if pressed {
+                mods |= m;
+            }
+        }
+
+        Some(Self::new(mods, key))
+    }
+}
+
+impl FromStr for Accelerator {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |reason: &str| Error::Hotkey(format!("invalid accelerator \"{}\": {}", s, reason));
+
+        // Il tasto '+' non è supportato, quindi l'ultimo token è sempre il tasto
+        let mut tokens: Vec<&str> = s.split('+').map(str::trim).collect();
+        let key = tokens.pop().unwrap_or_default();
+        if key.is_empty() {
+            return Err(invalid("missing key"));
+        }
+
+        let mut modifiers = Modifiers::empty();
+        for t in tokens {
+            let m = match t.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => Modifiers::CONTROL,
+                "alt" | "option" => Modifiers::ALT,
+                "shift" => Modifiers::SHIFT,
+                "super" | "cmd" | "command" | "win" | "logo" | "meta" => Modifiers::SUPER,
+                _ => return Err(invalid(&format!("unknown modifier \"{}\"", t))),
+            };
+            if modifiers.contains(m) {
+                return Err(invalid(&format!("repeated modifier \"{}\"", t)));
+            }
+            modifiers |= m;
+        }
+
+        let key = KEY_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, code)| *code)
+            .ok_or_else(|| invalid(&format!("unknown key \"{}\"", key)))?;
+
+        Ok(Self { modifiers, key })
+    }
+}
+
+impl std::fmt::Display for Accelerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (m, name) in [
+            (Modifiers::CONTROL, "Ctrl"),
+            (Modifiers::ALT, "Alt"),
+            (Modifiers::SHIFT, "Shift"),
+            (Modifiers::SUPER, "Super"),
+        ] {
+            if self.modifiers.contains(m) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        let name = KEY_NAMES
+            .iter()
+            .find(|(_, code)| *code == self.key)
+            .map(|(name, _)| *name)
+            .unwrap_or("?");
+        write!(f, "{}", name)
+    }
+}
+
+/// Hotkey globali registrate presso il sistema operativo, con l'azione
+/// associata a ciascuna
+#[derive(Default)]
+pub struct HotkeyBindings {
+    registered: HashMap<u32, (HotkeyAction, HotKey)>,
+}
+
+impl HotkeyBindings {
+    /// Registra le hotkey indicate. Gli acceleratori non validi, quelli
+    /// assegnati a più azioni e quelli che il sistema rifiuta vengono saltati
+    /// e restituiti come errori, mentre gli altri restano registrati.
+    pub fn register(
+        &mut self,
+        manager: &GlobalHotKeyManager,
+        bindings: &BTreeMap<HotkeyAction, String>,
+    ) -> Vec<Error> {
+        let mut errors = Vec::new();
+
+        for (action, accelerator) in bindings {
+            let hotkey = match accelerator.parse::<Accelerator>() {
+                Ok(a) => a.to_hotkey(),
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+
+            if let Some((other, _)) = self.registered.get(&hotkey.id()) {
+                errors.push(Error::Hotkey(format!(
+                    "{} is assigned to both \"{}\" and \"{}\"",
+                    accelerator, other, action
+                )));
+                continue;
+            }
+
+            if let Err(e) = manager.register(hotkey) {
+                errors.push(Error::Hotkey(format!(
+                    "cannot register {} for \"{}\": {}",
+                    accelerator, action, e
+                )));
+                continue;
+            }
+            self.registered.insert(hotkey.id(), (*action, hotkey));
+        }
+
+        errors
+    }
+
+    /// Rimuove tutte le hotkey registrate
+    pub fn unregister_all(&mut self, manager: &GlobalHotKeyManager) -> Vec<Error> {
+        self.registered
+            .drain()
+            .filter_map(|(_, (_, hotkey))| manager.unregister(hotkey).err())
+            .map(Error::from)
+            .collect()
+    }
+
+    /// Restituisce l'azione associata alla hotkey con l'id indicato
+    pub fn action(&self, id: u32) -> Option<HotkeyAction> {
+        self.registered.get(&id).map(|(action, _)| *action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accelerator() {
+        let a: Accelerator = "Ctrl+Alt+4".parse().unwrap();
+        assert_eq!(a, Accelerator::new(Modifiers::CONTROL | Modifiers::ALT, Code::Digit4));
+
+        let a: Accelerator = " shift + d ".parse().unwrap();
+        assert_eq!(a, Accelerator::new(Modifiers::SHIFT, Code::KeyD));
+
+        let a: Accelerator = "PrintScreen".parse().unwrap();
+        assert_eq!(a, Accelerator::new(Modifiers::empty(), Code::PrintScreen));
+    }
+
+    #[test]
+    fn parse_invalid_accelerator() {
+        for s in ["", "Ctrl+", "Hyper+A", "Ctrl+Ctrl+A", "Ctrl+NotAKey"] {
+            assert!(
+                matches!(s.parse::<Accelerator>(), Err(Error::Hotkey(_))),
+                "\"{}\" should not be accepted",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn accelerator_from_iced() {
+        let a = Accelerator::from_iced(
+            KeyCode::S,
+            keyboard::Modifiers::CTRL | keyboard::Modifiers::SHIFT,
+        );
+        assert_eq!(a, Some("Ctrl+Shift+S".parse().unwrap()));
+
+        let a = Accelerator::from_iced(KeyCode::Numpad4, keyboard::Modifiers::empty());
+        assert_eq!(a, Some("4".parse().unwrap()));
+
+        assert_eq!(
+            Accelerator::from_iced(KeyCode::LControl, keyboard::Modifiers::CTRL),
+            None
+        );
+    }
+
+    #[test]
+    fn accelerator_display_round_trip() {
+        let a: Accelerator = "super+shift+alt+ctrl+esc".parse().unwrap();
+        assert_eq!(a.to_string(), "Ctrl+Alt+Shift+Super+Escape");
+        assert_eq!(a, a.to_string().parse().unwrap());
+    }
+}
@@ -0,0 +1,383 @@
+//! Istanza singola dell'applicazione e protocollo di controllo locale.
+//!
+//! La prima istanza dell'interfaccia grafica ascolta su un socket Unix
+//! ([`socket_path`]). Un secondo avvio inoltra la propria richiesta
+//! all'istanza in esecuzione e termina, così le hotkey globali e il file di
+//! configurazione hanno un solo proprietario. Lo stesso socket permette a
+//! script e scorciatoie del window manager di comandare l'applicazione senza
+//! hotkey globali, con `pds_project send COMMAND` o con qualunque client,
+//! ad esempio `echo capture-region | socat - UNIX-CONNECT:$SOCKET`.
+//! Sulle piattaforme senza socket Unix l'interfaccia parte comunque, ma senza
+//! istanza singola né controllo remoto.
+//!
+//! # Protocollo
+//!
+//! Ogni richiesta è una riga di testo UTF-8 terminata da `\n`, formata dal
+//! nome del comando e, per alcuni comandi, da un argomento separato da uno
+//! spazio. Su una connessione si possono inviare più richieste.
+//!
+//! | Comando               | Effetto                                              |
+//! |-----------------------|------------------------------------------------------|
+//! | `show`                | mostra la finestra                                   |
+//! | `capture`             | cattura lo schermo selezionato                       |
+//! | `capture-region`      | cattura lo schermo selezionato e apre il crop        |
+//! | `capture-all`         | cattura tutti gli schermi                            |
+//! | `open PATH`           | apre un'immagine o un progetto                       |
+//! | `save [PATH]`         | salva nella cartella di salvataggio, o in PATH       |
+//! | `copy`                | copia lo screenshot annotato negli appunti           |
+//! | `quit`                | chiude l'applicazione                                |
+//!
+//! I path devono essere assoluti e possono contenere spazi. Per ogni riga
+//! l'istanza risponde con una riga `ok`, se il comando è stato accettato, o
+//! `error MESSAGE`. I comandi vengono eseguiti in modo asincrono: l'esito,
+//! ad esempio di un salvataggio, viene mostrato nell'applicazione.
+
+use crate::{Error, Result};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::Receiver;
+
+/// Nome del file del socket
+const SOCKET_FILE_NAME: &str = concat!(env!("CARGO_PKG_NAME"), ".sock");
+
+/// Tempo massimo di attesa di una riga, perché un client bloccato non fermi
+/// gli altri
+#[cfg(unix)]
+const TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Comando ricevuto sul socket di controllo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Show,
+    Capture,
+    CaptureRegion,
+    CaptureAll,
+    Open(PathBuf),
+    Save(Option<PathBuf>),
+    Copy,
+    Quit,
+}
+
+impl FromStr for Request {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim_end_matches(['\r', '\n']);
+        let (command, argument) = match s.split_once(' ') {
+            Some((c, a)) if !a.trim().is_empty() => (c, Some(a)),
+            Some((c, _)) => (c, None),
+            None => (s, None),
+        };
+        let absolute = |a: &str| {
+            let path = PathBuf::from(a);
+            if path.is_absolute() {
+                Ok(path)
+            } else {
+                Err(format!("the path '{}' is not absolute", a))
+            }
+        };
+
+        match (command, argument) {
+            ("show", None) => Ok(Self::Show),
+            ("capture", None) => Ok(Self::Capture),
+            ("capture-region", None) => Ok(Self::CaptureRegion),
+            ("capture-all", None) => Ok(Self::CaptureAll),
+            ("open", Some(a)) => absolute(a).map(Self::Open),
+            ("open", None) => Err(String::from("open requires a path")),
+            ("save", a) => a.map(absolute).transpose().map(Self::Save),
+            ("copy", None) => Ok(Self::Copy),
+            ("quit", None) => Ok(Self::Quit),
+            ("show" | "capture" | "capture-region" | "capture-all" | "copy" | "quit", Some(_)) => {
+                Err(format!("{} takes no argument", command))
+            }
+            _ => Err(format!("unknown command '{}'", command)),
+        }
+    }
+}
+
+impl std::fmt::Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Show => write!(f, "show"),
+            Self::Capture => write!(f, "capture"),
+            Self::CaptureRegion => write!(f, "capture-region"),
+            Self::CaptureAll => write!(f, "capture-all"),
+            Self::Open(p) => write!(f, "open {}", p.display()),
+            Self::Save(None) => write!(f, "save"),
+            Self::Save(Some(p)) => write!(f, "save {}", p.display()),
+            Self::Copy => write!(f, "copy"),
+            Self::Quit => write!(f, "quit"),
+        }
+    }
+}
+
+/// Esito di [`acquire`]
+pub enum Instance {
+    /// Non c'era un'altra istanza: questa ascolta sul socket
+    Primary(Server),
+    /// Le richieste sono state inoltrate all'istanza in esecuzione
+    Forwarded,
+}
+
+/// Restituisce il path del socket di controllo: la cartella di runtime
+/// dell'utente, se definita, altrimenti la cartella di configurazione
+pub fn socket_path() -> Result<PathBuf> {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) if !dir.is_empty() => Ok(Path::new(&dir).join(SOCKET_FILE_NAME)),
+        _ => Ok(crate::config::config_dir()?.join(SOCKET_FILE_NAME)),
+    }
+}
+
+/// Inoltra le richieste all'istanza in esecuzione, o se non ce n'è una si
+/// mette in ascolto sul socket
+pub fn acquire(path: &Path, requests: &[Request]) -> Result<Instance> {
+    //Un secondo tentativo serve se un'altra istanza parte nello stesso momento
+    for _ in 0..2 {
+        match send(path, requests) {
+            Ok(()) => return Ok(Instance::Forwarded),
+            Err(e) if !is_not_running(&e) => return Err(e),
+            Err(_) => {}
+        }
+        match Server::bind(path) {
+            Ok(server) => return Ok(Instance::Primary(server)),
+            Err(Error::Io { source, .. }) if source.kind() == std::io::ErrorKind::AddrInUse => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(Error::Control(format!(
+        "unable to reach or replace the instance listening on {}",
+        path.display()
+    )))
+}
+
+/// Se l'errore di [`send`] indica che non c'è un'istanza in ascolto
+pub fn is_not_running(e: &Error) -> bool {
+    matches!(
+        e,
+        Error::Io { source, .. } if matches!(
+            source.kind(),
+            std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused
+        )
+    )
+}
+
+/// Invia le richieste all'istanza in esecuzione e attende le risposte.
+/// Restituisce un errore al primo comando rifiutato.
+#[cfg(unix)]
+pub fn send(path: &Path, requests: &[Request]) -> Result<()> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let stream = UnixStream::connect(path).map_err(|e| Error::io(path, e))?;
+    stream
+        .set_read_timeout(Some(TIMEOUT))
+        .map_err(|e| Error::io(path, e))?;
+    let mut reader = BufReader::new(&stream);
+    let mut writer = &stream;
+    for request in requests {
+        writeln!(writer, "{}", request).map_err(|e| Error::io(path, e))?;
+        let mut reply = String::new();
+        reader
+            .read_line(&mut reply)
+            .map_err(|e| Error::io(path, e))?;
+        match reply.trim_end() {
+            "ok" => {}
+            r => {
+                let message = r.strip_prefix("error ").unwrap_or(r);
+                return Err(Error::Control(format!("{}: {}", request, message)));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn send(path: &Path, _requests: &[Request]) -> Result<()> {
+    Err(unsupported(path))
+}
+
+/// Errore di I/O, e non di controllo, così che l'interfaccia parta comunque
+/// come istanza principale
+#[cfg(not(unix))]
+fn unsupported(path: &Path) -> Error {
+    Error::io(
+        path,
+        std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the control socket is not supported on this platform",
+        ),
+    )
+}
+
+/// Socket di controllo dell'istanza principale. Il file viene rimosso
+/// quando il server viene distrutto.
+pub struct Server {
+    #[cfg(unix)]
+    listener: std::os::unix::net::UnixListener,
+    path: PathBuf,
+}
+
+impl Server {
+    /// Si mette in ascolto su path. Un file rimasto da un'istanza terminata
+    /// senza rimuoverlo viene sostituito, un socket ancora in uso no.
+    #[cfg(unix)]
+    pub fn bind(path: &Path) -> Result<Self> {
+        use std::os::unix::net::{UnixListener, UnixStream};
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        if path.exists() {
+            match UnixStream::connect(path) {
+                Ok(_) => {
+                    return Err(Error::io(
+                        path,
+                        std::io::Error::from(std::io::ErrorKind::AddrInUse),
+                    ))
+                }
+                Err(_) => std::fs::remove_file(path).map_err(|e| Error::io(path, e))?,
+            }
+        }
+        let listener = UnixListener::bind(path).map_err(|e| Error::io(path, e))?;
+        log::info!("Listening for control requests on {}", path.display());
+        Ok(Self {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn bind(path: &Path) -> Result<Self> {
+        Err(unsupported(path))
+    }
+
+    /// Serve le connessioni in un thread dedicato e restituisce il canale
+    /// su cui arrivano i comandi accettati
+    pub fn spawn(self) -> Receiver<Request> {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        #[cfg(unix)]
+        std::thread::spawn(move || {
+            for stream in self.listener.incoming() {
+                let result = stream
+                    .and_then(|s| serve(s, &sender))
+                    .map_err(|e| Error::io(&self.path, e));
+                match result {
+                    Ok(true) => {}
+                    //L'applicazione è stata chiusa
+                    Ok(false) => break,
+                    Err(e) => log::warn!("Control connection failed: {}", e),
+                }
+            }
+        });
+        #[cfg(not(unix))]
+        drop(sender);
+        receiver
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Risponde alle richieste di una connessione. Restituisce false se il
+/// canale è chiuso e il server deve fermarsi.
+#[cfg(unix)]
+fn serve(
+    stream: std::os::unix::net::UnixStream,
+    sender: &std::sync::mpsc::Sender<Request>,
+) -> std::io::Result<bool> {
+    use std::io::{BufRead, BufReader, Write};
+
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut writer = &stream;
+    for line in BufReader::new(&stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match line.parse::<Request>() {
+            Ok(request) => {
+                log::info!("Control request: {}", request);
+                if sender.send(request).is_err() {
+                    writeln!(writer, "error the application is closing")?;
+                    return Ok(false);
+                }
+                writeln!(writer, "ok")?;
+            }
+            Err(e) => writeln!(writer, "error {}", e)?,
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+
+    #[test]
+    fn parse_requests() {
+        for line in [
+            "show",
+            "capture",
+            "capture-region",
+            "capture-all",
+            "open /tmp/my shot.png",
+            "save",
+            "save /tmp/out.png",
+            "copy",
+            "quit",
+        ] {
+            let request: Request = line.parse().unwrap();
+            assert_eq!(request.to_string(), line);
+        }
+        assert_eq!(
+            "open /tmp/a.png\r\n".parse(),
+            Ok(Request::Open(PathBuf::from("/tmp/a.png")))
+        );
+
+        for invalid in [
+            "",
+            "open",
+            "open a.png",
+            "save ../a.png",
+            "copy now",
+            "record",
+        ] {
+            assert!(invalid.parse::<Request>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn single_instance() {
+        let dir = temp_dir("instance");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(SOCKET_FILE_NAME);
+        //Un file rimasto da un'istanza precedente non blocca l'avvio
+        std::fs::write(&path, "").unwrap();
+
+        let Ok(Instance::Primary(server)) = acquire(&path, &[Request::Show]) else {
+            panic!("the first instance must listen on the socket");
+        };
+        let receiver = server.spawn();
+
+        let requests = [Request::Open(PathBuf::from("/tmp/a.png")), Request::Copy];
+        assert!(matches!(acquire(&path, &requests), Ok(Instance::Forwarded)));
+        assert_eq!(receiver.recv().unwrap(), requests[0]);
+        assert_eq!(receiver.recv().unwrap(), requests[1]);
+
+        //Un comando non valido riceve un errore e non arriva all'applicazione
+        let stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        use std::io::{BufRead, BufReader, Write};
+        writeln!(&stream, "open relative.png").unwrap();
+        let mut reply = String::new();
+        BufReader::new(&stream).read_line(&mut reply).unwrap();
+        assert!(reply.starts_with("error "), "{}", reply);
+        assert!(receiver.try_recv().is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
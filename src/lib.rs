@@ -1,29 +1,71 @@
 pub mod annotations;
+pub mod batch;
+pub mod beautify;
 pub mod canvas_to_pixels;
+pub mod cli;
 pub mod color_picker;
+pub mod config;
 pub mod crop_tool;
+pub mod encoding;
+mod error;
 pub mod hotkey;
+pub mod instance;
+pub mod metadata;
 pub mod modal;
+pub mod naming;
+pub mod optimize;
+pub mod pdf;
+pub mod project;
+pub mod resize;
+pub mod stamps;
+pub mod svg;
+#[cfg(test)]
+mod test_utils;
+pub mod transform;
+pub mod watermark;
+
+pub use error::{Error, Result};
 
 use image::RgbaImage;
 use screenshots;
+use serde::{Deserialize, Serialize};
 use std::fmt::Formatter;
 use std::fs::File;
 use std::io::Read;
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ImageFormat {
     Png,
     Bmp,
     Jpeg,
     Gif,
+    WebP,
+    Tiff,
+    Qoi,
+    Ico,
+    Tga,
+    Pnm,
 }
-pub const ALL_FORMATS: [ImageFormat; 4] = [
+pub const ALL_FORMATS: [ImageFormat; 10] = [
     ImageFormat::Png,
     ImageFormat::Bmp,
     ImageFormat::Jpeg,
     ImageFormat::Gif,
+    ImageFormat::WebP,
+    ImageFormat::Tiff,
+    ImageFormat::Qoi,
+    ImageFormat::Ico,
+    ImageFormat::Tga,
+    ImageFormat::Pnm,
+];
+
+///Estensioni delle immagini che possono essere aperte nell'editor, compresi i
+///formati che la libreria image sa leggere ma l'applicazione non sa scrivere
+pub const OPEN_EXTENSIONS: [&str; 20] = [
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "tiff", "tif", "webp", "pnm", "pbm", "pgm", "ppm",
+    "pam", "tga", "dds", "hdr", "exr", "ff", "qoi",
 ];
 
 impl ImageFormat {
@@ -33,8 +75,53 @@ impl ImageFormat {
             Self::Bmp => "Bitmap",
             Self::Jpeg => "Jpeg",
             Self::Gif => "Gif",
+            Self::WebP => "WebP",
+            Self::Tiff => "Tiff",
+            Self::Qoi => "Qoi",
+            Self::Ico => "Icon",
+            Self::Tga => "Tga",
+            Self::Pnm => "Pnm",
+        }
+    }
+
+    ///Estensioni accettate per il formato, la prima è quella usata per i nuovi file
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Self::Png => &["png"],
+            Self::Bmp => &["bmp"],
+            Self::Jpeg => &["jpg", "jpeg"],
+            Self::Gif => &["gif"],
+            Self::WebP => &["webp"],
+            Self::Tiff => &["tiff", "tif"],
+            Self::Qoi => &["qoi"],
+            Self::Ico => &["ico"],
+            Self::Tga => &["tga"],
+            //Viene scritto un pixmap binario (P6)
+            Self::Pnm => &["ppm", "pnm"],
+        }
+    }
+
+    ///Se il formato può salvare la trasparenza
+    pub fn supports_alpha(&self) -> bool {
+        !matches!(self, Self::Jpeg | Self::Pnm)
+    }
+
+    ///Dimensione massima dei lati dell'immagine, se il formato ne ha una
+    pub fn max_dimension(&self) -> Option<u32> {
+        match self {
+            Self::Ico => Some(256),
+            Self::WebP => Some(16383),
+            Self::Jpeg | Self::Gif | Self::Tga => Some(u16::MAX as u32),
+            _ => None,
         }
     }
+
+    ///Cerca il formato a partire dall'estensione di un file
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        ALL_FORMATS
+            .into_iter()
+            .find(|f| f.extensions().iter().any(|e| e.eq_ignore_ascii_case(ext)))
+    }
 }
 
 impl Into<image::ImageFormat> for ImageFormat {
@@ -44,38 +131,51 @@ impl Into<image::ImageFormat> for ImageFormat {
             Self::Bmp => image::ImageFormat::Bmp,
             Self::Jpeg => image::ImageFormat::Jpeg,
             Self::Gif => image::ImageFormat::Gif,
+            Self::WebP => image::ImageFormat::WebP,
+            Self::Tiff => image::ImageFormat::Tiff,
+            Self::Qoi => image::ImageFormat::Qoi,
+            Self::Ico => image::ImageFormat::Ico,
+            Self::Tga => image::ImageFormat::Tga,
+            Self::Pnm => image::ImageFormat::Pnm,
         }
     }
 }
 
-impl From<image::ImageFormat> for ImageFormat {
-    fn from(value: image::ImageFormat) -> Self {
+///I formati che l'applicazione non sa scrivere, come Avif o OpenExr, vengono rifiutati
+impl TryFrom<image::ImageFormat> for ImageFormat {
+    type Error = Error;
+
+    fn try_from(value: image::ImageFormat) -> Result<Self> {
         match value {
-            image::ImageFormat::Png => Self::Png,
-            image::ImageFormat::Bmp => Self::Bmp,
-            image::ImageFormat::Jpeg => Self::Jpeg,
-            image::ImageFormat::Gif => Self::Gif,
-            _ => Self::Png,
+            image::ImageFormat::Png => Ok(Self::Png),
+            image::ImageFormat::Bmp => Ok(Self::Bmp),
+            image::ImageFormat::Jpeg => Ok(Self::Jpeg),
+            image::ImageFormat::Gif => Ok(Self::Gif),
+            image::ImageFormat::WebP => Ok(Self::WebP),
+            image::ImageFormat::Tiff => Ok(Self::Tiff),
+            image::ImageFormat::Qoi => Ok(Self::Qoi),
+            image::ImageFormat::Ico => Ok(Self::Ico),
+            image::ImageFormat::Tga => Ok(Self::Tga),
+            image::ImageFormat::Pnm => Ok(Self::Pnm),
+            f => Err(image::ImageError::Unsupported(
+                image::error::UnsupportedError::from_format_and_kind(
+                    f.into(),
+                    image::error::UnsupportedErrorKind::Format(f.into()),
+                ),
+            )
+            .into()),
         }
     }
 }
 
 impl std::fmt::Display for ImageFormat {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                ImageFormat::Png => "Png",
-                ImageFormat::Bmp => "Bitmap",
-                ImageFormat::Jpeg => "Jpeg",
-                ImageFormat::Gif => "Gif",
-            }
-        )
+        write!(f, "{}", self.as_str())
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Delays {
     #[default]
     Zero,
@@ -111,9 +211,14 @@ pub struct Screen {
 }
 
 impl Screen {
-    pub fn all() -> Vec<Self> {
-        let screens = screenshots::Screen::all().unwrap();
-        screens
+    /// Identificativo dello schermo assegnato dal sistema operativo
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn all() -> Result<Vec<Self>> {
+        let screens = screenshots::Screen::all().map_err(|e| Error::Capture(e.to_string()))?;
+        Ok(screens
             .iter()
             .enumerate()
             .map(|(i, s)| Screen {
@@ -121,12 +226,12 @@ impl Screen {
                 index: i + 1,
                 primary: s.display_info.is_primary,
             })
-            .collect()
+            .collect())
     }
 
-    pub fn primary() -> Option<Self> {
-        let screens = screenshots::Screen::all().unwrap();
-        screens.iter().enumerate().find_map(|(i, s)| {
+    pub fn primary() -> Result<Option<Self>> {
+        let screens = screenshots::Screen::all().map_err(|e| Error::Capture(e.to_string()))?;
+        Ok(screens.iter().enumerate().find_map(|(i, s)| {
             if s.display_info.is_primary {
                 let ret = Screen {
                     id: s.display_info.id,
@@ -137,7 +242,7 @@ impl Screen {
             } else {
                 None
             }
-        })
+        }))
     }
 }
 
@@ -151,60 +256,87 @@ impl std::fmt::Display for Screen {
     }
 }
 
-pub fn screenshot(screen: Screen) -> Option<RgbaImage> {
-    let screens = screenshots::Screen::all()
-        .expect("Non esistono schermi e qundi non si può lanciare l'applicazione");
-    let screen = screens.iter().find(|s| s.display_info.id == screen.id);
+///Cattura lo schermo indicato. Se non esiste più viene catturato il primo schermo disponibile
+pub fn screenshot(screen: Screen) -> Result<RgbaImage> {
+    let screens = screenshots::Screen::all().map_err(|e| Error::Capture(e.to_string()))?;
+    let screen = screens
+        .iter()
+        .find(|s| s.display_info.id == screen.id)
+        .or_else(|| screens.first())
+        .ok_or_else(|| Error::Capture(String::from("no screen available")))?;
 
-    let screen = if let Some(s) = screen {
-        s
-    } else {
-        //Se superiamo il primo expect allora c'è sicuramente un primo schermo
-        screens.first().unwrap()
-    };
+    let image = screen
+        .capture()
+        .map_err(|e| Error::Capture(e.to_string()))?;
+    let ret: RgbaImage = image::load_from_memory(image.buffer())?.to_rgba8();
+    Ok(ret)
+}
 
-    if let Ok(image) = screen.capture() {
-        let buffer = image.buffer();
-        let ret: RgbaImage = image::load_from_memory(buffer).expect("TODO").to_rgba8();
-        Some(ret)
-    } else {
-        error_popup(
-            "Errore",
-            "Non è stato possibile fare lo screenshot, riprova",
-        );
-        None
+///Cattura tutti gli schermi e li compone in un'unica immagine rispettando la loro disposizione.
+///Le zone non coperte da alcuno schermo restano trasparenti
+pub fn screenshot_all() -> Result<RgbaImage> {
+    let screens = screenshots::Screen::all().map_err(|e| Error::Capture(e.to_string()))?;
+
+    let mut captures = Vec::with_capacity(screens.len());
+    for s in &screens {
+        let image = s.capture().map_err(|e| Error::Capture(e.to_string()))?;
+        let image = image::load_from_memory(image.buffer())?.to_rgba8();
+        captures.push((s.display_info, image));
     }
-}
 
-use chrono::{DateTime, Local, Utc};
-use rfd::FileDialog;
-///Genera il nome dello screenshot secondo il seguente pattern: screenshot_{date}_{time}.{imageFormat}
-pub fn generate_file_name(format: ImageFormat) -> String {
-    let utc = Utc::now();
-    let converted: DateTime<Local> = DateTime::from(utc);
+    //Le posizioni degli schermi sono in pixel logici, mentre le catture in pixel fisici:
+    //si usa il fattore di scala maggiore per non sovrapporre le immagini
+    let scale = captures
+        .iter()
+        .map(|(info, image)| image.width() as f32 / info.width.max(1) as f32)
+        .fold(1.0, f32::max);
+    let min_x = captures.iter().map(|(info, _)| info.x).min();
+    let min_y = captures.iter().map(|(info, _)| info.y).min();
+    let (Some(min_x), Some(min_y)) = (min_x, min_y) else {
+        return Err(Error::Capture(String::from("no screen available")));
+    };
 
-    // Ottieni la data e l'ora correnti
-    let date = converted.format("%Y-%m-%d").to_string();
-    let time = converted.format("%H-%M-%S").to_string();
+    let origins: Vec<(i64, i64)> = captures
+        .iter()
+        .map(|(info, _)| {
+            (
+                ((info.x - min_x) as f32 * scale) as i64,
+                ((info.y - min_y) as f32 * scale) as i64,
+            )
+        })
+        .collect();
+    let width = captures
+        .iter()
+        .zip(&origins)
+        .map(|((_, image), (x, _))| *x as u32 + image.width())
+        .max()
+        .unwrap_or(0);
+    let height = captures
+        .iter()
+        .zip(&origins)
+        .map(|((_, image), (_, y))| *y as u32 + image.height())
+        .max()
+        .unwrap_or(0);
 
-    let extension = Into::<image::ImageFormat>::into(format).extensions_str()[0];
-    format!("screenshot_{}_{}.{}", date, time, extension)
+    let mut ret = RgbaImage::new(width, height);
+    for ((_, image), (x, y)) in captures.iter().zip(origins) {
+        image::imageops::overlay(&mut ret, image, x, y);
+    }
+    Ok(ret)
 }
 
-///Apre un FileDialog per far scegliere dove salvare e che nome dare allo screenshot, impostandolo prima a uno di default
-///Restituisce il path scelto dall'utente
-pub fn save(task: FileDialog, format: ImageFormat) -> Option<PathBuf> {
-    let filename = generate_file_name(format);
-
-    let mut path = task.set_file_name(&*filename);
+use rfd::FileDialog;
+///Apre un FileDialog per far scegliere dove salvare e che nome dare allo screenshot, impostandolo prima a
+///file_name, di solito generato con naming::FileName. Restituisce il path scelto dall'utente
+pub fn save(task: FileDialog, file_name: &str, format: ImageFormat) -> Option<PathBuf> {
+    let mut path = task.set_file_name(file_name);
 
     let mut formats = ALL_FORMATS;
     let pos = formats.iter().position(|f| format == *f).unwrap();
     formats.swap(0, pos);
 
     for f in formats {
-        let ext_str = Into::<image::ImageFormat>::into(f).extensions_str();
-        path = path.add_filter(f.as_str(), ext_str);
+        path = path.add_filter(f.as_str(), f.extensions());
     }
     let path = path.save_file();
     path
@@ -219,34 +351,23 @@ pub fn error_popup(title: &str, description: &str) {
 }
 
 ///Legge il file di configurazione
-pub fn read_config_file(path: PathBuf) -> Option<(PathBuf, Option<ImageFormat>)> {
-    let mut f = if let Ok(f) = File::open(path) {
-        f
-    } else {
-        return None;
-    };
+pub fn read_config_file(path: PathBuf) -> Result<(PathBuf, Option<ImageFormat>)> {
+    let mut f = File::open(&path).map_err(|e| Error::io(&path, e))?;
     let mut s = String::new();
-    f.read_to_string(&mut s)
-        .expect("Non è stato possibile leggere il file");
+    f.read_to_string(&mut s).map_err(|e| Error::io(&path, e))?;
     let mut l = s.lines();
-    let path = if let Some(p) = l.next() {
-        p
-    } else {
-        return None;
-    };
-    let m = if let Some(m) = l.next() {
-        m
-    } else {
-        return None;
-    };
+    let p = l.next().ok_or_else(|| {
+        Error::Config(format!("{}: missing save path", path.display()))
+    })?;
+    let m = l.next().ok_or_else(|| {
+        Error::Config(format!("{}: missing image format", path.display()))
+    })?;
+    //Le versioni precedenti salvavano as_str(), cioè "Bitmap" per Bmp
     let format = match m {
-        "Png" => Some(ImageFormat::Png),
         "Bmp" => Some(ImageFormat::Bmp),
-        "Jpeg" => Some(ImageFormat::Jpeg),
-        "Gif" => Some(ImageFormat::Gif),
-        _ => None,
+        _ => ALL_FORMATS.into_iter().find(|f| f.as_str() == m),
     };
-    Some((PathBuf::from(path), format))
+    Ok((PathBuf::from(p), format))
 }
 ///Tronca la stringa in modo tale da non farla andare a capo a una dimensione fissa
 pub fn cut_default_path(s: &str) -> String {
@@ -261,7 +382,7 @@ pub fn cut_default_path(s: &str) -> String {
 }
 #[cfg(test)]
 mod test {
-    use crate::{cut_default_path, read_config_file, ImageFormat};
+    use crate::{cut_default_path, read_config_file, Error, ImageFormat};
     use std::path::PathBuf;
 
     #[test]
@@ -270,6 +391,11 @@ mod test {
         assert!(ret.0 == PathBuf::from("path") && ret.1.unwrap() == ImageFormat::Png)
     }
     #[test]
+    fn read_config_file_missing_test() {
+        let ret = read_config_file(PathBuf::from("tests/missing.config"));
+        assert!(matches!(ret, Err(Error::Io { path: Some(_), .. })));
+    }
+    #[test]
     fn cut_default_path_test() {
         let s = "abcdefghijklmnopqr...".to_string();
         let a = cut_default_path("abcdefghijklmnopqrstuw");
@@ -3,35 +3,216 @@ mod styles;
 
 use iced::alignment::{Horizontal, Vertical};
 use iced::widget::{
-    button, column, container, pick_list, row, slider, svg, text, text_input, Canvas,
+    button, checkbox, column, container, pick_list, row, scrollable, slider, svg, text,
+    text_input, Canvas,
 };
 use iced::widget::{horizontal_rule, horizontal_space, vertical_rule, vertical_space};
 use iced::{executor, widget, Application, Command, Subscription, Theme};
 use iced::{theme, theme::Button, Alignment, Element, Length, Settings};
+use iced::keyboard::{KeyCode, Modifiers};
 use iced::{Color, Rectangle, Size};
+use iced_graphics::Primitive;
 use image::{imageops, RgbaImage};
-use pds_project::annotations::{self, Annotations};
+use pds_project::annotations::{self, Annotation, Annotations};
+use pds_project::beautify::{self, Background, BeautifyOptions, BeautifyPreset, GradientDirection};
 use pds_project::color_picker::{self, ToolColor};
+use pds_project::config::{self, Config};
 use pds_project::crop_tool;
-use pds_project::hotkey;
+use pds_project::hotkey::{
+    self, Accelerator, Binding, EditorAction, HotkeyAction, HotkeyBindings,
+};
+use pds_project::instance::{self, Instance, Request};
 use pds_project::modal::Modal;
-use pds_project::screenshot;
+use pds_project::{screenshot, screenshot_all};
 use pds_project::{canvas_to_pixels as cp, Delays};
-use pds_project::{generate_file_name, save, ImageFormat, Screen, ALL_FORMATS};
+use pds_project::encoding::{self, EncoderOptions};
+use pds_project::metadata::{read_metadata_from_file, Crop, Metadata};
+use pds_project::naming::FileName;
+use pds_project::pdf;
+use pds_project::project::{self, HistoryStep, Project};
+use pds_project::resize::{self, ResizeFilter, ResizeMode};
+use pds_project::stamps::{self, Stamp};
+use pds_project::svg as vector;
+use pds_project::transform::Transform;
+use pds_project::watermark::{Watermark, WatermarkOptions, WatermarkPosition};
+use pds_project::{save, Error, ImageFormat, Screen, ALL_FORMATS, OPEN_EXTENSIONS};
+use global_hotkey::GlobalHotKeyManager;
 use rfd::FileDialog;
 use rodio;
 use std::fs::File;
-use std::io::{BufReader, Write};
-use std::path::PathBuf;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, Mutex};
 use styles::ButtonStyle;
 
 //Min size crop
 const MIN_SIZE_RATIO: f32 = 0.03;
+//Lato più lungo dell'anteprima della presentazione
+const PREVIEW_SIZE: u32 = 1024;
+//Attesa prima di ricalcolare l'anteprima, per non farlo a ogni passo di uno slider
+const PREVIEW_DELAY: std::time::Duration = std::time::Duration::from_millis(150);
+
+/// Screenshot annotato e rimpicciolito su cui viene calcolata l'anteprima,
+/// con il fattore di scala rispetto alle dimensioni reali
+#[derive(Debug, Clone)]
+pub struct PreviewBase {
+    image: Arc<RgbaImage>,
+    factor: f32,
+}
 
 #[derive(Debug, Clone)]
 enum HistoryEntry {
     Annotate,
     Crop(Rectangle<u32>),
+    DeleteAnnotation,
+    /// Ridimensionamento, con lo stato precedente necessario per annullarlo:
+    /// originale, annotazioni, annotazioni eliminate e crop della cronologia
+    Resize {
+        original: RgbaImage,
+        annotations: Vec<Annotation>,
+        deleted: Vec<(usize, Annotation)>,
+        crops: Vec<Rectangle<u32>>,
+    },
+    /// Rotazione o ribaltamento, annullato applicando la trasformazione inversa
+    Transform(Transform),
+}
+
+impl HistoryEntry {
+    /// Passo da salvare nel progetto. Ridimensionamenti e trasformazioni non
+    /// vengono salvati: riaprendo il progetto l'immagine modificata è l'originale.
+    fn step(&self) -> Option<HistoryStep> {
+        match self {
+            HistoryEntry::Annotate => Some(HistoryStep::Annotate),
+            HistoryEntry::Crop(r) => Some(HistoryStep::Crop(Crop {
+                x: r.x,
+                y: r.y,
+                width: r.width,
+                height: r.height,
+            })),
+            HistoryEntry::DeleteAnnotation => Some(HistoryStep::DeleteAnnotation),
+            HistoryEntry::Resize { .. } | HistoryEntry::Transform(_) => None,
+        }
+    }
+}
+
+impl From<HistoryStep> for HistoryEntry {
+    fn from(step: HistoryStep) -> Self {
+        match step {
+            HistoryStep::Annotate => HistoryEntry::Annotate,
+            HistoryStep::Crop(c) => HistoryEntry::Crop(Rectangle {
+                x: c.x,
+                y: c.y,
+                width: c.width,
+                height: c.height,
+            }),
+            HistoryStep::DeleteAnnotation => HistoryEntry::DeleteAnnotation,
+        }
+    }
+}
+
+/// Destinazione di un'esportazione dello screenshot
+#[derive(Debug, Clone)]
+pub enum ExportTarget {
+    /// File scelto dall'utente, che ha già confermato un'eventuale sovrascrittura
+    File(PathBuf),
+    /// Nuovo file nella cartella indicata, con il nome generato dal template
+    Folder(PathBuf, FileName),
+    Clipboard,
+}
+
+/// Tutto il necessario per renderizzare ed esportare lo screenshot fuori dal
+/// thread della GUI
+struct ExportJob {
+    primitives: Vec<Primitive>,
+    image: RgbaImage,
+    crop: Option<Rectangle<u32>>,
+    target: ExportTarget,
+    /// Formato da usare se non è deducibile dall'estensione del file
+    format: ImageFormat,
+    options: EncoderOptions,
+    metadata: Metadata,
+    /// Filigrana da aggiungere, None se disattivata per questo salvataggio
+    watermark: Option<WatermarkOptions>,
+    /// Preset della presentazione, None se disattivata per questo salvataggio
+    beautify: Option<BeautifyPreset>,
+}
+
+impl ExportJob {
+    /// Applica le annotazioni allo screenshot e lo salva su file o lo copia
+    /// negli appunti. Operazione lenta, da eseguire in background.
+    /// Restituisce anche i byte risparmiati dall'ottimizzazione dei Png.
+    fn run(self) -> pds_project::Result<(ExportTarget, Option<usize>)> {
+        let mut image = cp::rasterize(&self.primitives, &self.image, self.crop).ok_or_else(|| {
+            Error::Render(String::from("the annotated screenshot has an invalid size"))
+        })?;
+        if let Some(options) = &self.watermark {
+            let captured_at = self.metadata.captured_at.unwrap_or_else(|| chrono::Local::now().into());
+            image = Watermark::new(options, captured_at)?.apply(&image)?;
+        }
+        if let Some(preset) = &self.beautify {
+            image = beautify::beautify(&image, preset);
+        }
+
+        match &self.target {
+            //Nella finestra di salvataggio l'utente può aver scelto un altro formato
+            ExportTarget::File(path) => {
+                let format = path
+                    .extension()
+                    .and_then(|e| ImageFormat::from_extension(&e.to_string_lossy()))
+                    .unwrap_or(self.format);
+                let saved = encoding::save_to_file(
+                    &image,
+                    path,
+                    format,
+                    &self.options,
+                    Some(&self.metadata),
+                )?;
+                return Ok((self.target, saved));
+            }
+            //Il nome dipende anche dalle dimensioni, note solo dopo il crop
+            ExportTarget::Folder(dir, name) => {
+                let (path, file) = name.create(dir, image.width(), image.height())?;
+                let saved = encoding::write_to_file(
+                    &image,
+                    file,
+                    &path,
+                    name.format,
+                    &self.options,
+                    Some(&self.metadata),
+                )?;
+                return Ok((ExportTarget::File(path), saved));
+            }
+            ExportTarget::Clipboard => {
+                let data = arboard::ImageData {
+                    width: image.width() as usize,
+                    height: image.height() as usize,
+                    bytes: std::borrow::Cow::Borrowed(image.as_raw()),
+                };
+                arboard::Clipboard::new()?.set_image(data)?;
+            }
+        }
+
+        Ok((self.target, None))
+    }
+}
+
+/// Cosa catturare quando viene richiesto un nuovo screenshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureMode {
+    /// Tutto lo schermo selezionato
+    Screen,
+    /// Lo schermo selezionato, aprendo subito lo strumento di crop
+    Region,
+    /// Tutti gli schermi composti in un'unica immagine
+    AllScreens,
+}
+
+/// Notifica non modale mostrata nella barra di stato
+#[derive(Debug, Clone)]
+enum Toast {
+    Working(String),
+    Success(String),
+    Error(String),
 }
 
 #[derive(Debug, Clone)]
@@ -43,15 +224,81 @@ struct ResHandles {
     pen_icon: svg::Handle,
     plus_icon: svg::Handle,
     square_icon: svg::Handle,
+    circle_icon: svg::Handle,
     stopwatch_icon: svg::Handle,
     text_icon: svg::Handle,
     undo_icon: svg::Handle,
 }
 
+/// Modo della finestra di ridimensionamento
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ResizeKind {
+    Percent,
+    Exact,
+    MaxWidth,
+}
+
+impl ResizeKind {
+    const ALL: [ResizeKind; 3] = [ResizeKind::Percent, ResizeKind::Exact, ResizeKind::MaxWidth];
+}
+
+impl std::fmt::Display for ResizeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ResizeKind::Percent => "Percentage",
+            ResizeKind::Exact => "Exact size",
+            ResizeKind::MaxWidth => "Max width",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Stato della finestra di ridimensionamento. Le misure restano testo
+/// finché l'utente le sta scrivendo.
+#[derive(Debug, Clone, PartialEq)]
+struct ResizeDialog {
+    kind: ResizeKind,
+    percent: String,
+    width: String,
+    height: String,
+    /// Se cambiando una dimensione esatta va aggiornata anche l'altra
+    aspect_lock: bool,
+    max_width: String,
+    filter: ResizeFilter,
+}
+
+impl ResizeDialog {
+    /// Finestra per un'immagine visibile di width x height pixel
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            kind: ResizeKind::Percent,
+            percent: String::from("50"),
+            width: width.to_string(),
+            height: height.to_string(),
+            aspect_lock: true,
+            max_width: width.min(1200).to_string(),
+            filter: ResizeFilter::default(),
+        }
+    }
+
+    /// Modo scelto, None se il campo corrispondente non è un numero
+    fn mode(&self) -> Option<ResizeMode> {
+        match self.kind {
+            ResizeKind::Percent => self.percent.trim().parse().ok().map(ResizeMode::Percent),
+            ResizeKind::Exact => Some(ResizeMode::Exact {
+                width: self.width.trim().parse().ok()?,
+                height: self.height.trim().parse().ok()?,
+            }),
+            ResizeKind::MaxWidth => self.max_width.trim().parse().ok().map(ResizeMode::MaxWidth),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub enum PickListTools {
     #[default]
     Rectangle,
+    Ellipse,
     Arrow,
     Text {
         text: String,
@@ -59,13 +306,20 @@ pub enum PickListTools {
     },
     Pen,
     Highlighter,
+    Stamp {
+        scale: f32,
+        opacity: f32,
+    },
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
-    InitScreenshot,
-    TakeScreenshot,
+    InitScreenshot(CaptureMode),
+    TakeScreenshot(CaptureMode),
+    GlobalHotkey(u32),
     CopyToClipboard,
+    Paste,
+    Pasted(Result<RgbaImage, Error>),
     Save,
     SaveAs,
     Settings,
@@ -80,27 +334,104 @@ pub enum Message {
     EndCrop,
     CancelCrop,
     NewAnnotation,
+    ExportFinished(Result<(ExportTarget, Option<usize>), Error>),
+    DismissToast(u64),
+    KeyPressed(KeyCode, Modifiers),
+    ShowShortcuts(bool),
+    RecordBinding(Binding),
+    ResetBindings,
+    ShowCheatSheet(bool),
+    FilenameTemplateChanged(String),
+    SaveFilenameTemplate,
+    EncoderOptionsChanged(EncoderOptions),
+    SaveConfig(u64),
+    NoteChanged(String),
+    ExportSvg,
+    ExportPdf,
+    PdfTitleChanged(String),
+    SaveProject,
+    ProjectSaved(Result<PathBuf, Error>),
+    Open,
+    ProjectOpened(Result<Project, Error>),
+    Deselect,
+    DeleteAnnotation,
+    Control(Request),
+    OpenFile(PathBuf),
+    ImageOpened(Result<(RgbaImage, Metadata), Error>),
+    StampSelected(Stamp),
+    ChooseStampsFolder,
+    StampLoaded(Result<(Stamp, RgbaImage), Error>),
+    WatermarkToggled(bool),
+    WatermarkOptionsChanged(WatermarkOptions),
+    ChooseWatermarkLogo,
+    BeautifyToggled(bool),
+    BeautifyOptionsChanged(BeautifyOptions),
+    BeautifyColorChanged(usize, String),
+    PresetNameChanged(String),
+    SavePreset,
+    DeletePreset,
+    ShowPreview(bool),
+    RenderPreview(u64),
+    PreviewRendered(u64, Result<(PreviewBase, RgbaImage), Error>),
+    ShowResize(bool),
+    ResizeDialogChanged(ResizeDialog),
+    ApplyResize,
+    Resized(u64, Result<(RgbaImage, f32, f32), Error>),
+    TransformDocument(Transform),
+}
+
+/// Parametri di avvio dell'interfaccia grafica
+#[derive(Default)]
+struct Flags {
+    /// Comandi ricevuti sul socket di controllo, None se non è disponibile
+    control: Option<mpsc::Receiver<Request>>,
+    /// Immagine o progetto da aprire all'avvio
+    open: Option<PathBuf>,
 }
 
 pub fn main() -> iced::Result {
-    use global_hotkey::{
-        hotkey::{Code, HotKey, Modifiers},
-        GlobalHotKeyManager,
-    };
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
 
-    // initialize the hotkeys manager
-    let manager: GlobalHotKeyManager =
-        GlobalHotKeyManager::new().expect("Errore nella configurazione delle hotkeys");
-    // construct the hotkey
-    let hotkey = HotKey::new(Some(Modifiers::SHIFT), Code::KeyD);
-    // register it
-    manager.register(hotkey).expect("Impossibile registrare hotkey");
+    //Con un sottocomando l'applicazione lavora senza aprire la finestra
+    if let Some(code) = pds_project::cli::main(args.clone()) {
+        std::process::exit(code);
+    }
+
+    //Se l'interfaccia è già aperta le viene inoltrato il file da aprire, o la richiesta
+    //di mostrarsi, e questo processo termina
+    let open = args.first().map(|p| {
+        std::env::current_dir()
+            .map(|d| d.join(p))
+            .unwrap_or_else(|_| PathBuf::from(p))
+    });
+    let request = match &open {
+        Some(path) => Request::Open(path.clone()),
+        None => Request::Show,
+    };
+    let control = match instance::socket_path().and_then(|p| instance::acquire(&p, &[request])) {
+        Ok(Instance::Forwarded) => {
+            log::info!("Request forwarded to the running instance");
+            return Ok(());
+        }
+        Ok(Instance::Primary(server)) => Some(server.spawn()),
+        Err(e @ Error::Control(_)) => {
+            eprintln!("error: {}", e);
+            std::process::exit(pds_project::cli::EXIT_FAILURE);
+        }
+        Err(e) => {
+            log::warn!("Control socket unavailable: {}", e);
+            None
+        }
+    };
 
-    let settings = Settings::<()> {
+    let settings = Settings {
         window: iced::window::Settings {
             min_size: Some((800, 500)),
             ..iced::window::Settings::default()
         },
+        flags: Flags { control, open },
         ..Settings::default()
     };
 
@@ -119,8 +450,38 @@ set_delay: se Some allora il prossimo screen avrà un delay
 selected_tool: tool per le annotazioni selezionato
 crop_tool: strumento per il crop
 tool_color: colore selezionato da color picker
+tools: impostazioni degli strumenti del documento corrente, prese dalla configurazione o dal progetto aperto
 color_picker: radio button per i colori
 selected_screen: su quale schermo stiamo facendo lo screen
+toast: notifica mostrata nella barra di stato
+toast_id: identificativo dell'ultima notifica, per non chiudere quelle più recenti
+config_save_id: identificativo dell'ultimo salvataggio ritardato della configurazione
+config_unsaved: se ci sono modifiche alla configurazione non ancora salvate
+exporting: se è in corso un salvataggio o una copia in background
+hotkey_manager: gestore delle hotkey globali, deve vivere quanto l'applicazione
+hotkey_bindings: hotkey registrate e azione associata a ciascuna
+last_capture: modalità dell'ultimo screenshot, usata per ripeterlo
+captured_screen: id dello schermo dello screenshot corrente, None se sono stati catturati tutti
+captured_at: istante in cui è stato catturato lo screenshot corrente
+note: nota dell'utente da salvare nei metadati dei file esportati
+shortcuts_page: se la pagina delle scorciatoie deve essere mostrata
+recording: azione di cui si sta registrando la nuova combinazione di tasti
+binding_error: ultimo errore della pagina delle scorciatoie, ad esempio un duplicato
+cheat_sheet: se deve essere mostrato il riepilogo delle scorciatoie
+config: impostazioni persistenti, salvate in config_path se la cartella di configurazione è nota
+control: comandi ricevuti sul socket di controllo, letti dalla subscription
+stamps: timbri della libreria, riletti ogni volta che viene scelto lo strumento
+stamp: timbro scelto e già caricato, usato dallo strumento Stamp
+watermark: se aggiungere filigrana e piè di pagina ai prossimi salvataggi
+beautify: se applicare il preset di presentazione ai prossimi salvataggi
+preview: se al posto dell'editor va mostrata l'anteprima della presentazione
+preview_image: ultima anteprima renderizzata, in scala ridotta
+preview_id: identificativo dell'ultima anteprima richiesta, per scartare quelle superate
+preview_base: screenshot rimpicciolito dell'ultima anteprima, riusato finché l'anteprima resta aperta
+color_inputs: testo dei campi dei colori dello sfondo, anche se non ancora validi
+preset_name: nome con cui salvare il preset corrente
+resize_dialog: finestra di ridimensionamento, se aperta
+document_id: identificativo dello screenshot originale, cambia a ogni sua sostituzione
  **/
 struct ScreenCapture {
     path_save: PathBuf,
@@ -134,10 +495,42 @@ struct ScreenCapture {
     selected_tool: Option<PickListTools>,
     crop_tool: Option<crop_tool::CropTool>,
     tool_color: ToolColor,
+    tools: config::ToolDefaults,
     color_picker: color_picker::ColorPicker,
     all_screens: Vec<Screen>,
     selected_screen: Option<Screen>,
     resources: ResHandles,
+    toast: Option<Toast>,
+    toast_id: u64,
+    config_save_id: u64,
+    config_unsaved: bool,
+    exporting: bool,
+    hotkey_manager: Option<GlobalHotKeyManager>,
+    hotkey_bindings: HotkeyBindings,
+    last_capture: Option<CaptureMode>,
+    captured_screen: Option<u32>,
+    captured_at: Option<chrono::DateTime<chrono::Local>>,
+    note: String,
+    pdf_title: String,
+    shortcuts_page: bool,
+    recording: Option<Binding>,
+    binding_error: Option<String>,
+    cheat_sheet: bool,
+    config: Config,
+    config_path: Option<PathBuf>,
+    control: Option<Arc<Mutex<mpsc::Receiver<Request>>>>,
+    stamps: Vec<Stamp>,
+    stamp: Option<(Stamp, Arc<RgbaImage>)>,
+    watermark: bool,
+    beautify: bool,
+    preview: bool,
+    preview_image: Option<widget::image::Handle>,
+    preview_id: u64,
+    preview_base: Option<PreviewBase>,
+    color_inputs: [String; 2],
+    preset_name: String,
+    resize_dialog: Option<ResizeDialog>,
+    document_id: u64,
 }
 
 impl ScreenCapture {
@@ -146,17 +539,22 @@ impl ScreenCapture {
     fn update_annotations(&mut self) {
         if let Some(tool) = &self.selected_tool {
             let color = self.tool_color.into();
+            let defaults = &self.tools;
             let t = match tool {
                 PickListTools::Rectangle => {
                     annotations::Tool::Rectangle {
                         color,
-                        fill_style: annotations::FillStyle::Stroke(1.0),
+                        fill_style: annotations::FillStyle::Stroke(defaults.stroke_width),
                         //fill_style: annotations::FillStyle::Fill,
                     }
                 }
+                PickListTools::Ellipse => annotations::Tool::Ellipse {
+                    color,
+                    fill_style: annotations::FillStyle::Stroke(defaults.stroke_width),
+                },
                 PickListTools::Arrow => annotations::Tool::Arrow {
                     color,
-                    stroke_width: 1.0,
+                    stroke_width: defaults.stroke_width,
                 },
                 PickListTools::Text { text, size } => annotations::Tool::Text {
                     color,
@@ -166,12 +564,27 @@ impl ScreenCapture {
                 },
                 PickListTools::Pen => annotations::Tool::FreeHand {
                     color,
-                    stroke_width: 1.0,
+                    stroke_width: defaults.stroke_width,
                 },
                 PickListTools::Highlighter => annotations::Tool::FreeHand {
-                    color: Color { a: 0.5, ..color },
-                    stroke_width: 3.0,
+                    color: Color {
+                        a: defaults.highlighter_opacity,
+                        ..color
+                    },
+                    stroke_width: defaults.highlighter_width,
                 },
+                PickListTools::Stamp { scale, opacity } => {
+                    //Finché non viene scelto un timbro lo strumento non disegna nulla
+                    let Some((_, image)) = &self.stamp else {
+                        self.annotations.set_tool(None);
+                        return;
+                    };
+                    annotations::Tool::Image {
+                        image: image.clone(),
+                        scale: *scale,
+                        opacity: *opacity,
+                    }
+                }
             };
             self.annotations.set_tool(Some(t));
         } else {
@@ -179,6 +592,14 @@ impl ScreenCapture {
         }
     }
 
+    /// Cartella dei timbri scelta nelle impostazioni, o quella di default
+    fn stamps_dir(&self) -> pds_project::Result<PathBuf> {
+        match &self.config.stamps_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => stamps::stamps_dir(),
+        }
+    }
+
     /// Cerca nella cronologia l'ultima operazione di crop e restituisce il
     /// Rectangle che descrive la zona. Se non è mai stata fatta un'operazione
     /// di crop, retituisce None
@@ -197,6 +618,75 @@ impl ScreenCapture {
         }
     }
 
+    /// Sostituisce lo screenshot corrente con image, scartando annotazioni,
+    /// crop e cronologia di quello precedente
+    fn set_document(&mut self, image: RgbaImage) {
+        let size = Size::new(image.width() as f32, image.height() as f32);
+        self.original_screenshot = Some(image);
+        self.document_id += 1;
+        self.edited_screenshot = self.original_screenshot.clone();
+        self.captured_screen = None;
+        self.captured_at = None;
+        self.note.clear();
+        self.pdf_title.clear();
+
+        self.annotations.clear_annotations();
+        self.annotations.set_image_size(size);
+        self.selected_tool = None;
+        self.crop_tool = None;
+        self.history.clear();
+        self.tools = self.config.tools.clone();
+        self.preview = false;
+        self.preview_image = None;
+        self.preview_base = None;
+        self.resize_dialog = None;
+    }
+
+    /// Crop della cronologia, dal più vecchio al più recente
+    fn history_crops(&mut self) -> impl Iterator<Item = &mut Rectangle<u32>> {
+        self.history.iter_mut().filter_map(|h| match h {
+            HistoryEntry::Crop(r) => Some(r),
+            _ => None,
+        })
+    }
+
+    /// Sostituisce ogni crop della cronologia con il risultato di f
+    fn map_history_crops(&mut self, f: impl Fn(Crop) -> Crop) {
+        for r in self.history_crops() {
+            let c = f(Crop {
+                x: r.x,
+                y: r.y,
+                width: r.width,
+                height: r.height,
+            });
+            *r = Rectangle {
+                x: c.x,
+                y: c.y,
+                width: c.width,
+                height: c.height,
+            };
+        }
+    }
+
+    /// Ruota o ribalta lo screenshot originale, le annotazioni e i crop
+    /// della cronologia, poi aggiorna la parte visibile
+    fn transform_document(&mut self, transform: Transform) {
+        let Some(original) = &self.original_screenshot else {
+            return;
+        };
+        //Un crop o un'annotazione a metà userebbero le coordinate di prima:
+        //vengono annullati, lasciando selezionato lo stesso strumento
+        self.crop_tool = None;
+        let (width, height) = original.dimensions();
+        self.original_screenshot = Some(transform.apply(original));
+        self.document_id += 1;
+        self.annotations.transform(transform);
+        self.map_history_crops(|c| transform.crop(c, width, height));
+        let crop_area = self.get_last_crop();
+        self.set_screenshot_crop(crop_area);
+        self.update_annotations();
+    }
+
     /// Imposta la superficie del crop.
     fn set_screenshot_crop(&mut self, crop_rec: Option<Rectangle<u32>>) {
         if let Some(mut s) = self.original_screenshot.clone() {
@@ -217,43 +707,362 @@ impl ScreenCapture {
         }
     }
 
+    /// Genera le primitive delle annotazioni sull'intero screenshot originale.
+    /// Restituisce anche l'area dell'ultimo crop da applicare al risultato.
+    fn annotation_primitives(&mut self) -> Option<(Vec<Primitive>, Option<Rectangle<u32>>)> {
+        let s = self.original_screenshot.as_ref()?;
+        let size = Size::new(s.width(), s.height());
+        let crop_area = self.get_last_crop();
+
+        //La selezione è solo un aiuto visivo e non deve finire nell'immagine esportata
+        let ca = self.annotations.set_crop(Rectangle::with_size(Size::new(
+            size.width as f32,
+            size.height as f32,
+        )));
+        let selected = self.annotations.select(None);
+        let primitives =
+            cp::draw_primitives::<Annotations<Message>, Message>(&self.annotations, size);
+        self.annotations.set_crop(ca);
+        self.annotations.select(selected);
+
+        Some((primitives, crop_area))
+    }
+
     /// Renderizza lo screenshot ritagliato e con le annotazioni.
     /// Restituisce una RgbaImage pronta per essere salvata.
     fn render_screenshot(&mut self) -> Option<RgbaImage> {
-        if let Some(s) = &self.original_screenshot {
-            let crop_area = self.get_last_crop();
+        let (primitives, crop_area) = self.annotation_primitives()?;
+        cp::rasterize(&primitives, self.original_screenshot.as_ref()?, crop_area)
+    }
+
+    /// Chiede di ricalcolare l'anteprima della presentazione. Il calcolo parte
+    /// con RenderPreview solo se nel frattempo non ne è stato chiesto un altro.
+    fn refresh_preview(&mut self) -> Command<Message> {
+        if !self.preview {
+            return Command::none();
+        }
+        self.preview_id += 1;
+        let id = self.preview_id;
+        Command::perform(tokio::time::sleep(PREVIEW_DELAY), move |_| {
+            Message::RenderPreview(id)
+        })
+    }
+
+    /// Calcola in background l'anteprima della presentazione, con la
+    /// filigrana se attiva, su una copia rimpicciolita dello screenshot. La
+    /// copia viene fatta solo la prima volta e poi riusata.
+    /// Arriva con PreviewRendered; quelle superate da una più recente vengono scartate.
+    fn render_preview(&mut self, id: u64) -> Command<Message> {
+        let Some(preset) = self.config.beautify.selected().cloned() else {
+            return Command::none();
+        };
+        //Ok se lo screenshot rimpicciolito è già pronto, altrimenti quanto serve per calcolarlo
+        let source = match self.preview_base.clone() {
+            Some(base) => Ok(base),
+            None => {
+                let Some((primitives, crop)) = self.annotation_primitives() else {
+                    return Command::none();
+                };
+                //Se annotation_primitives ha avuto successo lo screenshot esiste
+                Err((primitives, crop, self.original_screenshot.clone().unwrap()))
+            }
+        };
+        let watermark = Some(self.config.watermark.clone()).filter(|_| self.watermark);
+        let captured_at: chrono::DateTime<chrono::FixedOffset> =
+            self.captured_at.unwrap_or_else(chrono::Local::now).into();
+
+        Command::perform(
+            async move {
+                tokio::task::spawn_blocking(move || {
+                    let base = match source {
+                        Ok(base) => base,
+                        Err((primitives, crop, image)) => {
+                            let image = cp::rasterize(&primitives, &image, crop).ok_or_else(|| {
+                                Error::Render(String::from(
+                                    "the annotated screenshot has an invalid size",
+                                ))
+                            })?;
+                            let factor = (PREVIEW_SIZE as f32
+                                / image.width().max(image.height()) as f32)
+                                .min(1.0);
+                            let image = imageops::thumbnail(
+                                &image,
+                                ((image.width() as f32 * factor).round() as u32).max(1),
+                                ((image.height() as f32 * factor).round() as u32).max(1),
+                            );
+                            PreviewBase {
+                                image: Arc::new(image),
+                                factor,
+                            }
+                        }
+                    };
+                    let preview = match &watermark {
+                        Some(options) => {
+                            let image = Watermark::new(options, captured_at)?.apply(&base.image)?;
+                            beautify::beautify(&image, &preset.scaled(base.factor))
+                        }
+                        None => beautify::beautify(&base.image, &preset.scaled(base.factor)),
+                    };
+                    Ok((base, preview))
+                })
+                .await
+                .unwrap_or_else(|e| Err(Error::Render(e.to_string())))
+            },
+            move |result| Message::PreviewRendered(id, result),
+        )
+    }
+
+    /// Riporta nei campi dei colori quelli dello sfondo del preset in uso
+    fn sync_color_inputs(&mut self) {
+        self.color_inputs = match self.config.beautify.selected().map(|p| &p.background) {
+            Some(Background::Solid { color }) => [color.to_string(), color.to_string()],
+            Some(Background::Gradient { from, to, .. }) => [from.to_string(), to.to_string()],
+            None => Default::default(),
+        };
+    }
+
+    /// Avvia in background il rendering e l'esportazione dello screenshot.
+    /// Nel frattempo viene mostrato un indicatore non modale e, al termine,
+    /// arriva il messaggio ExportFinished con l'esito.
+    fn start_export(&mut self, target: ExportTarget) -> Command<Message> {
+        //Come i bottoni disabilitati, anche scorciatoie e socket non avviano
+        //una seconda esportazione, che potrebbe scrivere sullo stesso file
+        if self.exporting {
+            return Command::none();
+        }
+        let Some((primitives, crop)) = self.annotation_primitives() else {
+            return self.show_toast(Toast::Error(String::from("No screenshot to export")));
+        };
+        //Se annotation_primitives ha avuto successo lo screenshot esiste
+        let image = self.original_screenshot.clone().unwrap();
+
+        let progress = match &target {
+            ExportTarget::File(_) | ExportTarget::Folder(..) => "Saving…",
+            ExportTarget::Clipboard => "Copying to clipboard…",
+        };
+        let metadata = Metadata {
+            screen: self.captured_screen,
+            crop: crop.map(|c| Crop {
+                x: c.x,
+                y: c.y,
+                width: c.width,
+                height: c.height,
+            }),
+            note: Some(self.note.trim().to_string()).filter(|n| !n.is_empty()),
+            ..Metadata::new(self.captured_at.unwrap_or_else(chrono::Local::now))
+        };
+        let job = ExportJob {
+            primitives,
+            image,
+            crop,
+            target,
+            format: self.format,
+            options: self.config.encoder.clone(),
+            metadata,
+            watermark: Some(self.config.watermark.clone()).filter(|_| self.watermark),
+            beautify: self.config.beautify.selected().cloned().filter(|_| self.beautify),
+        };
+
+        self.exporting = true;
+        Command::batch([
+            self.show_toast(Toast::Working(String::from(progress))),
+            Command::perform(
+                async move {
+                    tokio::task::spawn_blocking(move || job.run())
+                        .await
+                        .unwrap_or_else(|e| Err(Error::Render(e.to_string())))
+                },
+                Message::ExportFinished,
+            ),
+        ])
+    }
+
+    /// Chiede dove salvare un documento con le annotazioni vettoriali e lo
+    /// scrive in background con save. kind è il nome del formato, in
+    /// maiuscolo, e in minuscolo la sua estensione.
+    fn export_document<F>(&mut self, kind: &str, save: F) -> Command<Message>
+    where
+        F: FnOnce(&RgbaImage, &[Annotation], Option<Crop>, &Path) -> pds_project::Result<()>
+            + Send
+            + 'static,
+    {
+        let Some(original) = self.original_screenshot.clone() else {
+            return self.show_toast(Toast::Error(String::from("No screenshot to save")));
+        };
+        let crop = self.get_last_crop().map(|r| Crop {
+            x: r.x,
+            y: r.y,
+            width: r.width,
+            height: r.height,
+        });
+        let extension = kind.to_lowercase();
+        let (width, height) = crop.map_or(original.dimensions(), |c| (c.width, c.height));
+        let mut file_name = self.file_name().render(width, height, 1);
+        file_name.set_extension(&extension);
+        let Some(path) = FileDialog::new()
+            .set_directory(&self.path_save)
+            .set_file_name(&file_name.file_name().unwrap_or_default().to_string_lossy())
+            .add_filter(kind, &[extension.as_str()])
+            .save_file()
+        else {
+            return Command::none();
+        };
 
-            let ca = self.annotations.set_crop(Rectangle::with_size(Size::new(
-                s.width() as f32,
-                s.height() as f32,
+        let annotations = self.annotations.annotations();
+        self.exporting = true;
+        Command::batch([
+            self.show_toast(Toast::Working(format!("Exporting {}…", kind))),
+            Command::perform(
+                async move {
+                    tokio::task::spawn_blocking(move || {
+                        save(&original, &annotations, crop, &path)
+                            .map(|_| (ExportTarget::File(path), None))
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(Error::Render(e.to_string())))
+                },
+                Message::ExportFinished,
+            ),
+        ])
+    }
+
+    /// Prepara il nome del file da salvare a partire dal template configurato
+    /// e dalle informazioni sullo screenshot corrente
+    fn file_name(&self) -> FileName {
+        let tool = match self.last_capture.unwrap_or(CaptureMode::Screen) {
+            CaptureMode::Screen => "screen",
+            CaptureMode::Region => "region",
+            CaptureMode::AllScreens => "all-screens",
+        };
+        FileName {
+            screen: Some(
+                self.captured_screen
+                    .map(|id| id.to_string())
+                    .unwrap_or_else(|| String::from("all")),
+            ),
+            tool: Some(String::from(tool)),
+            ..FileName::new(&self.config.filename_template, self.format)
+        }
+    }
+
+    /// Registra l'errore nei log e lo mostra nella barra di stato
+    fn report_error(&mut self, context: &str, e: Error) -> Command<Message> {
+        log::error!("{}: {}", context, e);
+        self.show_toast(Toast::Error(format!("{}: {}", context, e)))
+    }
+
+    /// Riporta le impostazioni correnti nella configurazione e la salva in
+    /// modo permanente
+    fn write_config(&mut self) -> pds_project::Result<()> {
+        self.config.save_path = Some(self.path_save.clone());
+        self.config.format = self.format;
+        self.config.delay = self.set_delay.unwrap_or_default();
+        self.config.screen = self.selected_screen.map(|s| s.id());
+        self.config.tools.color = self.tool_color;
+
+        let Some(path) = &self.config_path else {
+            return Err(Error::Config(String::from(
+                "the configuration folder is unknown or its file could not be set aside",
             )));
-            let tmp = cp::draw_on_buffer::<Annotations<Message>, Message>(
-                &self.annotations,
-                &s,
-                crop_area,
-            );
-            self.annotations.set_crop(ca);
+        };
+        self.config.save(path)
+    }
+
+    /// Salva la configurazione solo dopo che l'utente ha smesso per un attimo
+    /// di modificarla, così che trascinare uno slider non scriva il file a
+    /// ogni passo
+    fn schedule_config_save(&mut self) -> Command<Message> {
+        self.config_save_id += 1;
+        self.config_unsaved = true;
+        let id = self.config_save_id;
+        Command::perform(
+            tokio::time::sleep(std::time::Duration::from_millis(500)),
+            move |_| Message::SaveConfig(id),
+        )
+    }
+
+    /// Mostra una notifica nella barra di stato. Le notifiche di esito
+    /// vengono chiuse automaticamente dopo qualche secondo.
+    fn show_toast(&mut self, toast: Toast) -> Command<Message> {
+        self.toast_id += 1;
+        let id = self.toast_id;
+        let timeout = match &toast {
+            Toast::Working(_) => None,
+            Toast::Success(_) => Some(std::time::Duration::from_secs(4)),
+            Toast::Error(_) => Some(std::time::Duration::from_secs(10)),
+        };
+        self.toast = Some(toast);
 
-            tmp
+        if let Some(t) = timeout {
+            Command::perform(tokio::time::sleep(t), move |_| Message::DismissToast(id))
         } else {
-            None
+            Command::none()
+        }
+    }
+
+    /// Registra di nuovo le hotkey globali a partire dalla configurazione,
+    /// così che le modifiche abbiano effetto senza riavviare l'applicazione
+    fn reload_hotkeys(&mut self) -> Command<Message> {
+        let Some(manager) = &self.hotkey_manager else {
+            return Command::none();
+        };
+        let mut errors = self.hotkey_bindings.unregister_all(manager);
+        errors.extend(self.hotkey_bindings.register(manager, &self.config.hotkeys));
+
+        let commands: Vec<_> = errors
+            .into_iter()
+            .map(|e| self.report_error("Unable to register a global hotkey", e))
+            .collect();
+        Command::batch(commands)
+    }
+
+    /// Termina la registrazione di una combinazione di tasti e riattiva le
+    /// hotkey globali, sospese durante la registrazione
+    fn stop_recording(&mut self) -> Command<Message> {
+        if self.recording.take().is_some() {
+            self.reload_hotkeys()
+        } else {
+            Command::none()
         }
     }
 
     /// Funzione che genera la GUI della barra di selezione degli strumenti di
     /// annotazione e salvataggio.
     fn tool_selection(&self) -> Element<Message> {
+        let mut save_button = button("Save").style(Button::Custom(Box::new(ButtonStyle::new(
+            Color::TRANSPARENT,
+        ))));
+        let mut save_as_button = button("Save As").style(Button::Custom(Box::new(ButtonStyle::new(
+            Color::TRANSPARENT,
+        ))));
+        let mut svg_button = button("Export SVG").style(Button::Custom(Box::new(
+            ButtonStyle::new(Color::TRANSPARENT),
+        )));
+        let mut pdf_button = button("Export PDF").style(Button::Custom(Box::new(
+            ButtonStyle::new(Color::TRANSPARENT),
+        )));
+        let mut save_project_button = button("Save Project").style(Button::Custom(Box::new(
+            ButtonStyle::new(Color::TRANSPARENT),
+        )));
+        //Durante un'esportazione in corso i bottoni sono disabilitati
+        if !self.exporting {
+            save_button = save_button.on_press(Message::Save);
+            save_as_button = save_as_button.on_press(Message::SaveAs);
+            svg_button = svg_button.on_press(Message::ExportSvg);
+            pdf_button = pdf_button.on_press(Message::ExportPdf);
+            //Senza uno screenshot non c'è un progetto da salvare
+            if self.original_screenshot.is_some() {
+                save_project_button = save_project_button.on_press(Message::SaveProject);
+            }
+        }
         let save_buttons = row![
-            button("Save")
-                .style(Button::Custom(Box::new(ButtonStyle::new(
-                    Color::TRANSPARENT
-                ))))
-                .on_press(Message::Save),
-            button("Save As")
-                .style(Button::Custom(Box::new(ButtonStyle::new(
-                    Color::TRANSPARENT
-                ))))
-                .on_press(Message::SaveAs),
+            save_button,
+            save_as_button,
+            svg_button,
+            pdf_button,
+            save_project_button,
+            checkbox("Watermark", self.watermark, Message::WatermarkToggled).size(16),
+            checkbox("Beautify", self.beautify, Message::BeautifyToggled).size(16),
         ]
         .spacing(10)
         .align_items(Alignment::Center);
@@ -273,6 +1082,13 @@ impl ScreenCapture {
                 } else {
                     theme::Button::Text
                 }),
+            button(widget::svg(self.resources.circle_icon.clone()))
+                .on_press(Message::ToolSelected(PickListTools::Ellipse))
+                .style(if let Some(PickListTools::Ellipse) = self.selected_tool {
+                    theme::Button::Primary
+                } else {
+                    theme::Button::Text
+                }),
             button(widget::svg(self.resources.arrow_icon.clone()))
                 .on_press(Message::ToolSelected(PickListTools::Arrow))
                 .style(if let Some(PickListTools::Arrow) = self.selected_tool {
@@ -283,7 +1099,7 @@ impl ScreenCapture {
             button(widget::svg(self.resources.text_icon.clone()))
                 .on_press(Message::ToolSelected(PickListTools::Text {
                     text: "".to_string(),
-                    size: 25.0
+                    size: self.tools.text_size
                 }))
                 .style(
                     if let Some(PickListTools::Text { .. }) = self.selected_tool {
@@ -301,6 +1117,18 @@ impl ScreenCapture {
                         theme::Button::Text
                     }
                 ),
+            button("Stamp")
+                .on_press(Message::ToolSelected(PickListTools::Stamp {
+                    scale: self.tools.stamp_scale,
+                    opacity: self.tools.stamp_opacity
+                }))
+                .style(
+                    if let Some(PickListTools::Stamp { .. }) = self.selected_tool {
+                        theme::Button::Primary
+                    } else {
+                        theme::Button::Text
+                    }
+                ),
             vertical_rule(1.0),
             self.color_picker
                 .view(Some(self.tool_color), |sel| -> Message {
@@ -317,9 +1145,17 @@ impl ScreenCapture {
                 button(widget::svg(self.resources.crop_icon.clone()))
                     .on_press(Message::BeginCrop)
                     .style(theme::Button::Text),
+                button("Resize")
+                    .on_press(Message::ShowResize(true))
+                    .style(theme::Button::Text),
+                pick_list(&Transform::ALL[..], None, Message::TransformDocument)
+                    .placeholder("Rotate / Flip"),
                 vertical_rule(1.0),
                 draw_controls,
                 horizontal_space(Length::Fill),
+                button("?")
+                    .style(theme::Button::Text)
+                    .on_press(Message::ShowCheatSheet(true)),
                 if self.history.is_empty() {
                     button(widget::svg(self.resources.undo_icon.clone()))
                 } else {
@@ -363,7 +1199,7 @@ impl ScreenCapture {
         .spacing(5)
         .align_items(Alignment::Center);
 
-        let right_top_buttons = column![
+        let mut right_top_buttons = column![
             button(
                 row![
                     horizontal_space(Length::Fill),
@@ -378,12 +1214,33 @@ impl ScreenCapture {
             )
             .style(Button::Custom(Box::new(ButtonStyle::new(Color::WHITE))))
             .width(Length::Fill)
-            .on_press(Message::InitScreenshot),
+            .on_press(Message::InitScreenshot(CaptureMode::Screen)),
+            button(row![
+                horizontal_space(Length::Fill),
+                text("Open"),
+                horizontal_space(Length::Fill),
+            ])
+            .style(Button::Custom(Box::new(ButtonStyle::new(Color::WHITE))))
+            .width(Length::Fill)
+            .on_press(Message::Open),
             screens_pick_list,
             delays_pick_list
         ]
         .spacing(10);
+        if self.original_screenshot.is_some() {
+            right_top_buttons = right_top_buttons.push(
+                text_input("Note (saved in the file)", &self.note).on_input(Message::NoteChanged),
+            );
+            right_top_buttons = right_top_buttons.push(
+                text_input("Title (PDF export)", &self.pdf_title)
+                    .on_input(Message::PdfTitleChanged),
+            );
+        }
         let name = pds_project::cut_default_path(self.path_save.to_str().unwrap_or("Invalid path"));
+        let stamps_dir = self
+            .stamps_dir()
+            .map(|d| pds_project::cut_default_path(d.to_str().unwrap_or("Invalid path")))
+            .unwrap_or_else(|_| String::from("Choose a folder"));
         let right_bottom_buttons = if self.settings {
             column![
                 text("Default file format:").width(Length::Fill),
@@ -391,12 +1248,63 @@ impl ScreenCapture {
                     Message::ChangeFormat { format: sel }
                 })
                 .placeholder("Choose a image format"),
+                self.encoder_settings(),
+                checkbox(
+                    "Strip metadata",
+                    self.config.encoder.strip_metadata,
+                    Self::edit_encoder(&self.config.encoder, |o, strip| o.strip_metadata = strip),
+                )
+                .size(16),
+                checkbox(
+                    "SVG: annotations only",
+                    self.config.encoder.svg.annotations_only,
+                    Self::edit_encoder(&self.config.encoder, |o, only| o.svg.annotations_only = only),
+                )
+                .size(16),
+                text("PDF page size:").size(14),
+                pick_list(
+                    &pdf::PageSize::ALL[..],
+                    Some(self.config.encoder.pdf.page_size),
+                    Self::edit_encoder(&self.config.encoder, |o, size| o.pdf.page_size = size),
+                )
+                .width(Length::Fill),
+                checkbox(
+                    "PDF: capture timestamp",
+                    self.config.encoder.pdf.timestamp,
+                    Self::edit_encoder(&self.config.encoder, |o, timestamp| o.pdf.timestamp = timestamp),
+                )
+                .size(16),
+                horizontal_rule(1.0),
+                self.watermark_settings(),
+                horizontal_rule(1.0),
+                self.beautify_settings(),
                 horizontal_rule(1.0),
                 text("Default save path:").width(Length::Fill),
                 button(text(name))
                     .style(Button::Custom(Box::new(ButtonStyle::new(Color::WHITE))))
                     .width(Length::Fill)
                     .on_press(Message::ChooseSaveFolder),
+                text("Stamp folder:").width(Length::Fill),
+                button(text(stamps_dir))
+                    .style(Button::Custom(Box::new(ButtonStyle::new(Color::WHITE))))
+                    .width(Length::Fill)
+                    .on_press(Message::ChooseStampsFolder),
+                horizontal_rule(1.0),
+                text("File name template:").width(Length::Fill),
+                text_input(config::DEFAULT_FILENAME_TEMPLATE, &self.config.filename_template)
+                    .on_input(Message::FilenameTemplateChanged)
+                    .on_submit(Message::SaveFilenameTemplate),
+                text("{date} {time} {ms} {counter} {screen} {width} {height} {tool}, / for folders")
+                    .size(12),
+                horizontal_rule(1.0),
+                button(row![
+                    horizontal_space(Length::Fill),
+                    text("Keyboard shortcuts"),
+                    horizontal_space(Length::Fill),
+                ])
+                .style(Button::Custom(Box::new(ButtonStyle::new(Color::WHITE))))
+                .width(Length::Fill)
+                .on_press(Message::ShowShortcuts(true)),
                 button(row![
                     horizontal_space(Length::Fill),
                     text("Close settings"),
@@ -428,7 +1336,8 @@ impl ScreenCapture {
             column![
                 right_top_buttons,
                 vertical_space(Length::FillPortion(1)),
-                right_bottom_buttons
+                //Le impostazioni non stanno più in altezza nella barra
+                scrollable(right_bottom_buttons)
             ]
             .align_items(Alignment::Center),
         )
@@ -440,166 +1349,927 @@ impl ScreenCapture {
         .into()
     }
 
-    /// Funzione che genera la barra contenente i bottoni per confermare o
-    /// annullare un'operazione di crop
-    fn crop_dialog<'a>() -> Element<'a, Message> {
-        container(
-            row![
-                horizontal_space(Length::Fill),
-                button("Ok").on_press(Message::EndCrop),
-                button("Cancel").on_press(Message::CancelCrop),
-                horizontal_space(Length::Fill),
-            ]
-            .spacing(10),
-        )
-        .style(theme::Container::Box)
-        .height(50)
-        .padding(10)
-        .into()
-    }
-
-    /// Funzione che genera la GUI per personalizzare lo strumento di
-    /// annotazione Text
-    fn text_dialog<'a>(text: &'a String, size: f32) -> Element<'a, Message> {
-        container(
-            row![
-                horizontal_space(Length::Fill),
-                text_input("Text...", &text)
-                    .on_input(move |s| Message::ToolSelected(PickListTools::Text { text: s, size }))
-                    .width(200),
-                slider(1.0..=25.0, size, |v| Message::ToolSelected(
-                    PickListTools::Text {
-                        text: text.clone(),
-                        size: v
-                    }
-                )),
-                horizontal_space(Length::Fill),
-            ]
-            .spacing(10)
-            .align_items(Alignment::Center),
-        )
-        .width(Length::Fill)
-        .style(theme::Container::Box)
-        .padding(20)
-        .into()
+    /// Messaggio che applica edit alle opzioni del codificatore
+    fn edit_encoder<T>(
+        options: &EncoderOptions,
+        edit: impl Fn(&mut EncoderOptions, T),
+    ) -> impl Fn(T) -> Message {
+        let options = options.clone();
+        move |value| {
+            let mut options = options.clone();
+            edit(&mut options, value);
+            Message::EncoderOptionsChanged(options)
+        }
     }
-}
-
-impl Application for ScreenCapture {
-    type Executor = executor::Default;
-
-    type Message = Message;
-
-    type Theme = Theme;
-
-    type Flags = ();
 
-    fn new(_flag: ()) -> (ScreenCapture, Command<Message>) {
-        //Se esiste il file config, che contiene il path per il salvataggio e il formato del file, utilizza quelli altrimenti li inizializza alla cartella Pictures e Png
-        let mut format = None;
-        let path = if let Some(t) = pds_project::read_config_file(PathBuf::from("config.config")) {
-            format = t.1;
-            t.0
-        } else {
-            platform::default_path::take_default_path()
+    /// Opzioni del codificatore del formato di salvataggio selezionato, vuoto
+    /// per i formati che non hanno opzioni
+    fn encoder_settings(&self) -> Element<Message> {
+        let options = &self.config.encoder;
+        let controls: Vec<Element<Message>> = match self.format {
+            ImageFormat::Jpeg => vec![
+                text(format!("Quality: {}", options.jpeg.quality)).size(14).into(),
+                slider(
+                    1..=100,
+                    options.jpeg.quality,
+                    Self::edit_encoder(options, |o, quality| o.jpeg.quality = quality),
+                )
+                .into(),
+                text("Chroma subsampling:").size(14).into(),
+                pick_list(
+                    &encoding::ChromaSubsampling::ALL[..],
+                    Some(options.jpeg.subsampling),
+                    Self::edit_encoder(options, |o, subsampling| o.jpeg.subsampling = subsampling),
+                )
+                .width(Length::Fill)
+                .into(),
+            ],
+            ImageFormat::Png => vec![
+                checkbox(
+                    "Optimize after saving",
+                    options.png.optimize,
+                    Self::edit_encoder(options, |o, optimize| o.png.optimize = optimize),
+                )
+                .size(16)
+                .into(),
+                text("Compression:").size(14).into(),
+                pick_list(
+                    &encoding::PngCompression::ALL[..],
+                    Some(options.png.compression),
+                    Self::edit_encoder(options, |o, compression| o.png.compression = compression),
+                )
+                .width(Length::Fill)
+                .into(),
+                text("Filter:").size(14).into(),
+                pick_list(
+                    &encoding::PngFilter::ALL[..],
+                    Some(options.png.filter),
+                    Self::edit_encoder(options, |o, filter| o.png.filter = filter),
+                )
+                .width(Length::Fill)
+                .into(),
+            ],
+            ImageFormat::Gif => vec![
+                text(format!("Palette: {} colors", options.gif.palette_size))
+                    .size(14)
+                    .into(),
+                slider(
+                    4..=256,
+                    options.gif.palette_size,
+                    Self::edit_encoder(options, |o, size| o.gif.palette_size = size),
+                )
+                .into(),
+                checkbox(
+                    "Dithering",
+                    options.gif.dithering,
+                    Self::edit_encoder(options, |o, dithering| o.gif.dithering = dithering),
+                )
+                .size(16)
+                .into(),
+            ],
+            _ => vec![],
         };
-
-        (
-            Self {
-                path_save: path,
-                original_screenshot: None,
-                edited_screenshot: None,
-                settings: false,
-                format: format.unwrap_or(ImageFormat::Png),
-                set_delay: Some(Delays::default()),
-                annotations: Annotations::new(iced::Size::ZERO, Message::NewAnnotation),
-                history: Vec::new(),
-                selected_tool: None,
-                crop_tool: None,
-                tool_color: color_picker::ToolColor::Black,
-                color_picker: color_picker::ColorPicker {},
-                all_screens: Screen::all(),
-                selected_screen: Screen::primary(),
-                resources: ResHandles {
-                    crop_icon: svg::Handle::from_path("res/crop.svg"),
-                    arrow_icon: svg::Handle::from_path("res/arrow.svg"),
-                    highlighter_icon: svg::Handle::from_path("res/highlighter.svg"),
-                    display_icon: svg::Handle::from_path("res/display.svg"),
-                    pen_icon: svg::Handle::from_path("res/pen.svg"),
-                    plus_icon: svg::Handle::from_path("res/plus.svg"),
-                    square_icon: svg::Handle::from_path("res/square.svg"),
-                    stopwatch_icon: svg::Handle::from_path("res/stopwatch.svg"),
-                    text_icon: svg::Handle::from_path("res/text.svg"),
-                    undo_icon: svg::Handle::from_path("res/undo.svg"),
-                },
-            },
-            Command::none(),
-        )
+        widget::Column::with_children(controls).spacing(5).into()
     }
 
-    fn title(&self) -> String {
-        String::from("Screenshot utility")
+    /// Messaggio che applica edit al preset di presentazione in uso
+    fn edit_preset<T>(
+        options: &BeautifyOptions,
+        edit: impl Fn(&mut BeautifyPreset, T),
+    ) -> impl Fn(T) -> Message {
+        let options = options.clone();
+        move |value| {
+            let mut options = options.clone();
+            if let Some(preset) = options.selected_mut() {
+                edit(preset, value);
+            }
+            Message::BeautifyOptionsChanged(options)
+        }
     }
 
-    fn update(&mut self, message: Self::Message) -> Command<Message> {
-        match message {
-            //Salva l'immagine nella cartella di default dandogli come nome screenshot_{date}_{time}.{imageFormat} se esiste,
-            //altrimenti restituisce un popup d'errore
-            Message::Save => {
-                if let Some(image) = self.render_screenshot() {
-                    let filename = generate_file_name(self.format);
-
-                    let mut a = self.path_save.clone();
-                    a.push(filename);
-                    match image.save(a.as_path()) {
-                        Ok(_) => {}
-                        Err(_) => {
-                            pds_project::error_popup("Errore", "Salvataggio non riuscito, riprova o controlla la cartella di default");
-                        }
-                    }
-                } else {
-                    pds_project::error_popup("Nessuna immagine", "Nessuno screenshot da salvare");
-                }
-                Command::none()
+    /// Preset della presentazione: scelta, modifica e anteprima
+    fn beautify_settings(&self) -> Element<Message> {
+        let options = &self.config.beautify;
+        let mut controls = column![
+            text("Beautify:").width(Length::Fill),
+            checkbox("Enabled by default", options.enabled, {
+                let options = options.clone();
+                move |enabled| Message::BeautifyOptionsChanged(BeautifyOptions {
+                    enabled,
+                    ..options.clone()
+                })
+            })
+            .size(16),
+            checkbox("Preview", self.preview, Message::ShowPreview).size(16),
+            pick_list(&options.presets[..], options.selected().cloned(), {
+                let options = options.clone();
+                move |preset| Message::BeautifyOptionsChanged(BeautifyOptions {
+                    preset: preset.name,
+                    ..options.clone()
+                })
+            })
+            .width(Length::Fill),
+        ]
+        .spacing(5);
+
+        if let Some(preset) = options.selected() {
+            let gradient = matches!(preset.background, Background::Gradient { .. });
+            controls = controls
+                .push(
+                    checkbox(
+                        "Gradient",
+                        gradient,
+                        Self::edit_preset(options, |preset, gradient: bool| {
+                            preset.background = match (gradient, &preset.background) {
+                                (true, Background::Solid { color }) => Background::Gradient {
+                                    from: *color,
+                                    to: *color,
+                                    direction: GradientDirection::default(),
+                                },
+                                (false, Background::Gradient { from, .. }) => {
+                                    Background::Solid { color: *from }
+                                }
+                                (_, background) => background.clone(),
+                            };
+                        }),
+                    )
+                    .size(16),
+                )
+                .push(
+                    text_input("#rrggbb", &self.color_inputs[0])
+                        .on_input(|color| Message::BeautifyColorChanged(0, color)),
+                );
+            if let Background::Gradient { direction, .. } = preset.background {
+                controls = controls
+                    .push(
+                        text_input("#rrggbb", &self.color_inputs[1])
+                            .on_input(|color| Message::BeautifyColorChanged(1, color)),
+                    )
+                    .push(
+                        pick_list(
+                            &GradientDirection::ALL[..],
+                            Some(direction),
+                            Self::edit_preset(options, |preset, direction| {
+                                if let Background::Gradient { direction: d, .. } =
+                                    &mut preset.background
+                                {
+                                    *d = direction;
+                                }
+                            }),
+                        )
+                        .width(Length::Fill),
+                    );
             }
-            //Apre un FileDialog per scegliere dove salvare lo screenshot e lo salva
-            Message::SaveAs => {
-                if let Some(image) = self.render_screenshot() {
-                    let fd = FileDialog::new().clone().set_directory(&self.path_save);
-                    if let Some(s) = save(fd, self.format) {
-                        match image.save(s) {
-                            Ok(_) => {}
-                            Err(_) => {
-                                pds_project::error_popup("Errore", "Salvataggio non riuscito, riprova o controlla la cartella di default");
+            controls = controls
+                .push(text(format!("Padding: {} px", preset.padding)).size(14))
+                .push(slider(
+                    0..=256,
+                    preset.padding,
+                    Self::edit_preset(options, |preset, padding| preset.padding = padding),
+                ))
+                .push(text(format!("Corner radius: {} px", preset.corner_radius)).size(14))
+                .push(slider(
+                    0..=64,
+                    preset.corner_radius,
+                    Self::edit_preset(options, |preset, radius| preset.corner_radius = radius),
+                ))
+                .push(text(format!("Shadow blur: {:.0} px", preset.shadow_blur)).size(14))
+                .push(
+                    slider(
+                        0.0..=64.0,
+                        preset.shadow_blur,
+                        Self::edit_preset(options, |preset, blur| preset.shadow_blur = blur),
+                    )
+                    .step(1.0),
+                )
+                .push(
+                    text(format!(
+                        "Shadow offset: {}, {} px",
+                        preset.shadow_offset.0, preset.shadow_offset.1
+                    ))
+                    .size(14),
+                )
+                .push(slider(
+                    -32..=32,
+                    preset.shadow_offset.0,
+                    Self::edit_preset(options, |preset, x| preset.shadow_offset.0 = x),
+                ))
+                .push(slider(
+                    -32..=32,
+                    preset.shadow_offset.1,
+                    Self::edit_preset(options, |preset, y| preset.shadow_offset.1 = y),
+                ))
+                .push(
+                    text(format!("Shadow opacity: {:.0}%", preset.shadow_opacity * 100.0))
+                        .size(14),
+                )
+                .push(
+                    slider(
+                        0.0..=1.0,
+                        preset.shadow_opacity,
+                        Self::edit_preset(options, |preset, opacity| {
+                            preset.shadow_opacity = opacity
+                        }),
+                    )
+                    .step(0.05),
+                );
+        }
+
+        let mut delete_button = button(text("Delete preset"))
+            .style(Button::Custom(Box::new(ButtonStyle::new(Color::TRANSPARENT))))
+            .width(Length::Fill);
+        //L'ultimo preset rimasto non può essere eliminato
+        if options.presets.len() > 1 {
+            delete_button = delete_button.on_press(Message::DeletePreset);
+        }
+        controls
+            .push(
+                text_input("Preset name", &self.preset_name)
+                    .on_input(Message::PresetNameChanged)
+                    .on_submit(Message::SavePreset),
+            )
+            .push(
+                button(text("Save preset"))
+                    .style(Button::Custom(Box::new(ButtonStyle::new(Color::WHITE))))
+                    .width(Length::Fill)
+                    .on_press(Message::SavePreset),
+            )
+            .push(delete_button)
+            .into()
+    }
+
+    /// Impostazioni della filigrana e del piè di pagina aggiunti all'esportazione
+    fn watermark_settings(&self) -> Element<Message> {
+        let options = &self.config.watermark;
+        let logo = match &options.logo {
+            Some(path) => path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            None => String::from("Choose logo"),
+        };
+        let mut controls = column![
+            text("Watermark:").width(Length::Fill),
+            checkbox("Enabled by default", options.enabled, {
+                let options = options.clone();
+                move |enabled| Message::WatermarkOptionsChanged(WatermarkOptions {
+                    enabled,
+                    ..options.clone()
+                })
+            })
+            .size(16),
+            text_input("Watermark text", &options.text).on_input({
+                let options = options.clone();
+                move |text| Message::WatermarkOptionsChanged(WatermarkOptions {
+                    text,
+                    ..options.clone()
+                })
+            }),
+            button(text(logo))
+                .style(Button::Custom(Box::new(ButtonStyle::new(Color::WHITE))))
+                .width(Length::Fill)
+                .on_press(Message::ChooseWatermarkLogo),
+        ]
+        .spacing(5);
+        if options.logo.is_some() {
+            controls = controls.push(
+                button(text("Use text instead"))
+                    .style(Button::Custom(Box::new(ButtonStyle::new(Color::TRANSPARENT))))
+                    .width(Length::Fill)
+                    .on_press(Message::WatermarkOptionsChanged(WatermarkOptions {
+                        logo: None,
+                        ..options.clone()
+                    })),
+            );
+        }
+        controls
+            .push(
+                pick_list(&WatermarkPosition::ALL[..], Some(options.position), {
+                    let options = options.clone();
+                    move |position| Message::WatermarkOptionsChanged(WatermarkOptions {
+                        position,
+                        ..options.clone()
+                    })
+                })
+                .width(Length::Fill),
+            )
+            .push(text(format!("Opacity: {:.0}%", options.opacity * 100.0)).size(14))
+            .push(
+                slider(0.05..=1.0, options.opacity, {
+                    let options = options.clone();
+                    move |opacity| Message::WatermarkOptionsChanged(WatermarkOptions {
+                        opacity,
+                        ..options.clone()
+                    })
+                })
+                .step(0.05),
+            )
+            .push(text(format!("Size: {:.0}% of height", options.scale * 100.0)).size(14))
+            .push(
+                slider(0.02..=0.5, options.scale, {
+                    let options = options.clone();
+                    move |scale| Message::WatermarkOptionsChanged(WatermarkOptions {
+                        scale,
+                        ..options.clone()
+                    })
+                })
+                .step(0.01),
+            )
+            .push(
+                checkbox("Footer with time and host", options.footer, {
+                    let options = options.clone();
+                    move |footer| Message::WatermarkOptionsChanged(WatermarkOptions {
+                        footer,
+                        ..options.clone()
+                    })
+                })
+                .size(16),
+            )
+            .into()
+    }
+
+    /// Funzione che genera la barra di stato con l'avanzamento o l'esito
+    /// dell'ultima esportazione
+    fn status_bar(&self) -> Element<Message> {
+        let Some(toast) = &self.toast else {
+            return row![].into();
+        };
+
+        let (content, color) = match toast {
+            Toast::Working(s) => (s, Color::from_rgb(0.3, 0.3, 0.3)),
+            Toast::Success(s) => (s, Color::from_rgb(0.0, 0.5, 0.2)),
+            Toast::Error(s) => (s, Color::from_rgb(0.8, 0.0, 0.0)),
+        };
+
+        let mut bar = row![text(content).style(color).width(Length::Fill)]
+            .spacing(10)
+            .align_items(Alignment::Center);
+        if !matches!(toast, Toast::Working(_)) {
+            bar = bar.push(
+                button("Dismiss")
+                    .style(theme::Button::Text)
+                    .on_press(Message::DismissToast(self.toast_id)),
+            );
+        }
+
+        container(bar)
+            .width(Length::Fill)
+            .style(theme::Container::Box)
+            .padding(5)
+            .into()
+    }
+
+    /// Funzione che genera la pagina per cambiare le hotkey globali e le
+    /// scorciatoie dell'editor premendo la nuova combinazione di tasti
+    fn shortcuts_page(&self) -> Element<Message> {
+        let binding_row = |binding: Binding| -> Element<Message> {
+            let keys = if self.recording == Some(binding) {
+                String::from("Press the new keys…")
+            } else {
+                let current = match binding {
+                    Binding::Global(a) => self.config.hotkeys.get(&a),
+                    Binding::Editor(a) => self.config.shortcuts.get(&a),
+                };
+                current.cloned().unwrap_or_else(|| String::from("Not set"))
+            };
+
+            row![
+                text(binding.to_string()).width(Length::Fill),
+                button(text(keys))
+                    .style(if self.recording == Some(binding) {
+                        theme::Button::Primary
+                    } else {
+                        theme::Button::Secondary
+                    })
+                    .width(200)
+                    .on_press(Message::RecordBinding(binding)),
+            ]
+            .spacing(10)
+            .align_items(Alignment::Center)
+            .into()
+        };
+
+        let mut page = column![text("Keyboard shortcuts").size(24), text("Global hotkeys")]
+            .spacing(10)
+            .width(450);
+        for a in HotkeyAction::ALL {
+            page = page.push(binding_row(Binding::Global(a)));
+        }
+        page = page.push(horizontal_rule(1.0)).push(text("Editor shortcuts"));
+        for a in EditorAction::ALL {
+            page = page.push(binding_row(Binding::Editor(a)));
+        }
+
+        if self.hotkey_manager.is_none() {
+            page = page.push(
+                text("Global hotkeys are unavailable on this system")
+                    .style(Color::from_rgb(0.8, 0.0, 0.0)),
+            );
+        }
+        if let Some(e) = &self.binding_error {
+            page = page.push(text(e).style(Color::from_rgb(0.8, 0.0, 0.0)));
+        } else if self.recording.is_some() {
+            page = page.push(text("Press Escape to cancel"));
+        }
+
+        page = page.push(
+            row![
+                horizontal_space(Length::Fill),
+                button("Restore defaults")
+                    .style(theme::Button::Text)
+                    .on_press(Message::ResetBindings),
+                button("Close").on_press(Message::ShowShortcuts(false)),
+            ]
+            .spacing(10),
+        );
+
+        //Con molte azioni la pagina può essere più alta della finestra
+        container(scrollable(page))
+            .style(theme::Container::Box)
+            .padding(20)
+            .max_height(600.0)
+            .into()
+    }
+
+    /// Funzione che genera il riepilogo di tutte le scorciatoie configurate
+    fn cheat_sheet(&self) -> Element<Message> {
+        let entry = |action: String, keys: &str| -> Element<Message> {
+            row![
+                text(action).width(Length::Fill),
+                text(keys.to_string()).style(Color::from_rgb(0.3, 0.3, 0.3)),
+            ]
+            .spacing(10)
+            .into()
+        };
+
+        let mut sheet = column![text("Keyboard shortcuts").size(24), text("Editor")]
+            .spacing(5)
+            .width(350);
+        for (action, keys) in &self.config.shortcuts {
+            sheet = sheet.push(entry(action.to_string(), keys));
+        }
+        sheet = sheet
+            .push(entry(String::from("Confirm crop"), "Enter"))
+            .push(entry(String::from("Cancel crop"), "Escape"))
+            .push(horizontal_rule(1.0))
+            .push(text("Global hotkeys"));
+        for (action, keys) in &self.config.hotkeys {
+            sheet = sheet.push(entry(action.to_string(), keys));
+        }
+        sheet = sheet.push(
+            row![
+                horizontal_space(Length::Fill),
+                button("Close").on_press(Message::ShowCheatSheet(false)),
+            ]
+            .padding([10, 0, 0, 0]),
+        );
+
+        container(scrollable(sheet))
+            .style(theme::Container::Box)
+            .padding(20)
+            .max_height(600.0)
+            .into()
+    }
+
+    /// Finestra di ridimensionamento della parte visibile dello screenshot,
+    /// grande width x height pixel
+    fn resize_dialog(dialog: &ResizeDialog, width: u32, height: u32) -> Element<Message> {
+        let changed = |edit: fn(&mut ResizeDialog, String)| {
+            let dialog = dialog.clone();
+            move |value| {
+                let mut dialog = dialog.clone();
+                edit(&mut dialog, value);
+                Message::ResizeDialogChanged(dialog)
+            }
+        };
+        //Con le proporzioni bloccate un lato viene ricalcolato dall'altro
+        let ratio = height as f32 / width as f32;
+        let fields: Element<Message> = match dialog.kind {
+            ResizeKind::Percent => row![
+                text_input("50", &dialog.percent)
+                    .on_input(changed(|d, v| d.percent = v))
+                    .width(80),
+                text("%"),
+            ]
+            .spacing(5)
+            .align_items(Alignment::Center)
+            .into(),
+            ResizeKind::Exact => column![
+                row![
+                    text_input("Width", &dialog.width)
+                        .on_input({
+                            let dialog = dialog.clone();
+                            move |v| {
+                                let mut dialog = dialog.clone();
+                                if let (true, Ok(w)) = (dialog.aspect_lock, v.trim().parse::<u32>()) {
+                                    dialog.height = ((w as f32 * ratio).round() as u32).max(1).to_string();
+                                }
+                                dialog.width = v;
+                                Message::ResizeDialogChanged(dialog)
                             }
+                        })
+                        .width(80),
+                    text("x"),
+                    text_input("Height", &dialog.height)
+                        .on_input({
+                            let dialog = dialog.clone();
+                            move |v| {
+                                let mut dialog = dialog.clone();
+                                if let (true, Ok(h)) = (dialog.aspect_lock, v.trim().parse::<u32>()) {
+                                    dialog.width = ((h as f32 / ratio).round() as u32).max(1).to_string();
+                                }
+                                dialog.height = v;
+                                Message::ResizeDialogChanged(dialog)
+                            }
+                        })
+                        .width(80),
+                ]
+                .spacing(5)
+                .align_items(Alignment::Center),
+                checkbox("Keep aspect ratio", dialog.aspect_lock, {
+                    let dialog = dialog.clone();
+                    move |aspect_lock| {
+                        let mut dialog = dialog.clone();
+                        dialog.aspect_lock = aspect_lock;
+                        if let (true, Ok(w)) = (aspect_lock, dialog.width.trim().parse::<u32>()) {
+                            dialog.height = ((w as f32 * ratio).round() as u32).max(1).to_string();
                         }
+                        Message::ResizeDialogChanged(dialog)
                     }
+                })
+                .size(16),
+            ]
+            .spacing(5)
+            .into(),
+            ResizeKind::MaxWidth => row![
+                text_input("1200", &dialog.max_width)
+                    .on_input(changed(|d, v| d.max_width = v))
+                    .width(80),
+                text("px"),
+            ]
+            .spacing(5)
+            .align_items(Alignment::Center)
+            .into(),
+        };
+
+        let target = dialog.mode().and_then(|m| m.target(width, height));
+        let mut resize_button = button("Resize");
+        if target.is_some_and(|t| t != (width, height)) {
+            resize_button = resize_button.on_press(Message::ApplyResize);
+        }
+
+        container(
+            column![
+                text("Resize").size(24),
+                text(format!("Current size: {} x {}", width, height)),
+                pick_list(&ResizeKind::ALL[..], Some(dialog.kind), {
+                    let dialog = dialog.clone();
+                    move |kind| Message::ResizeDialogChanged(ResizeDialog {
+                        kind,
+                        ..dialog.clone()
+                    })
+                })
+                .width(Length::Fill),
+                fields,
+                text("Filter:"),
+                pick_list(&ResizeFilter::ALL[..], Some(dialog.filter), {
+                    let dialog = dialog.clone();
+                    move |filter| Message::ResizeDialogChanged(ResizeDialog {
+                        filter,
+                        ..dialog.clone()
+                    })
+                })
+                .width(Length::Fill),
+                match target {
+                    Some((w, h)) => text(format!("New size: {} x {}", w, h)),
+                    None => text("Invalid size").style(Color::from_rgb(1.0, 0.0, 0.0)),
+                },
+                row![
+                    horizontal_space(Length::Fill),
+                    button("Cancel").on_press(Message::ShowResize(false)),
+                    resize_button,
+                ]
+                .spacing(10)
+                .padding([10, 0, 0, 0]),
+            ]
+            .spacing(10)
+            .width(300),
+        )
+        .style(theme::Container::Box)
+        .padding(20)
+        .into()
+    }
+
+    /// Funzione che genera la barra contenente i bottoni per confermare o
+    /// annullare un'operazione di crop
+    fn crop_dialog<'a>() -> Element<'a, Message> {
+        container(
+            row![
+                horizontal_space(Length::Fill),
+                button("Ok").on_press(Message::EndCrop),
+                button("Cancel").on_press(Message::CancelCrop),
+                horizontal_space(Length::Fill),
+            ]
+            .spacing(10),
+        )
+        .style(theme::Container::Box)
+        .height(50)
+        .padding(10)
+        .into()
+    }
+
+    /// Funzione che genera la GUI per personalizzare lo strumento di
+    /// annotazione Text
+    fn text_dialog<'a>(text: &'a String, size: f32) -> Element<'a, Message> {
+        container(
+            row![
+                horizontal_space(Length::Fill),
+                text_input("Text...", &text)
+                    .on_input(move |s| Message::ToolSelected(PickListTools::Text { text: s, size }))
+                    .width(200),
+                slider(1.0..=25.0, size, |v| Message::ToolSelected(
+                    PickListTools::Text {
+                        text: text.clone(),
+                        size: v
+                    }
+                )),
+                horizontal_space(Length::Fill),
+            ]
+            .spacing(10)
+            .align_items(Alignment::Center),
+        )
+        .width(Length::Fill)
+        .style(theme::Container::Box)
+        .padding(20)
+        .into()
+    }
+
+    /// Funzione che genera la GUI per scegliere il timbro e regolarne
+    /// dimensione e opacità
+    fn stamp_dialog(&self, scale: f32, opacity: f32) -> Element<Message> {
+        let library: Element<Message> = if self.stamps.is_empty() {
+            let dir = self
+                .stamps_dir()
+                .map(|d| d.display().to_string())
+                .unwrap_or_else(|_| String::from("the stamp folder"));
+            text(format!("Add PNG or SVG files to {}", dir)).into()
+        } else {
+            pick_list(
+                &self.stamps[..],
+                self.stamp.as_ref().map(|(s, _)| s.clone()),
+                Message::StampSelected,
+            )
+            .placeholder("Choose a stamp")
+            .into()
+        };
+        container(
+            row![
+                horizontal_space(Length::Fill),
+                library,
+                text("Size"),
+                slider(0.1..=4.0, scale, move |v| Message::ToolSelected(
+                    PickListTools::Stamp { scale: v, opacity }
+                ))
+                .step(0.1)
+                .width(150),
+                text("Opacity"),
+                slider(0.1..=1.0, opacity, move |v| Message::ToolSelected(
+                    PickListTools::Stamp { scale, opacity: v }
+                ))
+                .step(0.05)
+                .width(150),
+                horizontal_space(Length::Fill),
+            ]
+            .spacing(10)
+            .align_items(Alignment::Center),
+        )
+        .width(Length::Fill)
+        .style(theme::Container::Box)
+        .padding(20)
+        .into()
+    }
+}
+
+impl Application for ScreenCapture {
+    type Executor = executor::Default;
+
+    type Message = Message;
+
+    type Theme = Theme;
+
+    type Flags = Flags;
+
+    fn new(flags: Flags) -> (ScreenCapture, Command<Message>) {
+        //Errori non fatali dell'avvio, mostrati all'utente una volta aperta la finestra
+        let mut errors: Vec<(&str, Error)> = Vec::new();
+
+        //Carica la configurazione, migrando il vecchio config.config se presente. Se non esiste
+        //si usano le impostazioni di default: cartella Pictures e Png
+        let mut config_path = match config::config_path() {
+            Ok(p) => Some(p),
+            Err(e) => {
+                errors.push(("Unable to locate the configuration folder", e));
+                None
+            }
+        };
+        let loaded = config_path
+            .as_ref()
+            .map(|p| Config::load_or_migrate(p, Path::new(config::LEGACY_CONFIG_FILE)));
+        let config = match loaded {
+            None => Config::default(),
+            Some(Ok(config)) => config,
+            //Il file illeggibile viene messo da parte, altrimenti il primo salvataggio lo
+            //sovrascriverebbe con le impostazioni di default. Se non si riesce a spostarlo
+            //la configurazione non viene più salvata per tutta la sessione.
+            Some(Err(e)) => {
+                match config_path.as_deref().filter(|p| p.exists()).map(config::set_aside) {
+                    Some(Ok(backup)) => {
+                        let message = match e {
+                            Error::Config(s) => s,
+                            e => e.to_string(),
+                        };
+                        errors.push((
+                            "Unable to read the configuration",
+                            Error::Config(format!("{} (moved to {})", message, backup.display())),
+                        ));
+                    }
+                    Some(Err(move_error)) => {
+                        log::error!("Unable to set the configuration aside: {}", move_error);
+                        errors.push((
+                            "Unable to read the configuration, settings will not be saved",
+                            e,
+                        ));
+                        config_path = None;
+                    }
+                    None => errors.push(("Unable to read the configuration", e)),
+                }
+                Config::default()
+            }
+        };
+
+        let path = config.save_path.clone().unwrap_or_else(|| {
+            platform::default_path::take_default_path().unwrap_or_else(|e| {
+                errors.push(("Unable to find the Pictures folder", e));
+                PathBuf::new()
+            })
+        });
+
+        let all_screens = Screen::all().unwrap_or_else(|e| {
+            errors.push(("Unable to list the screens", e));
+            Vec::new()
+        });
+        let selected_screen = config
+            .screen
+            .and_then(|id| all_screens.iter().find(|s| s.id() == id).copied())
+            .or_else(|| Screen::primary().unwrap_or_default())
+            .or_else(|| all_screens.first().copied());
+
+        //Registra le hotkey globali lette dalla configurazione. Una combinazione non valida
+        //o già usata da un'altra applicazione non impedisce di registrare le altre
+        let mut hotkey_bindings = HotkeyBindings::default();
+        let hotkey_manager = match GlobalHotKeyManager::new() {
+            Ok(m) => {
+                for e in hotkey_bindings.register(&m, &config.hotkeys) {
+                    errors.push(("Unable to register a global hotkey", e));
+                }
+                Some(m)
+            }
+            Err(e) => {
+                errors.push(("Unable to register the global hotkeys", e.into()));
+                None
+            }
+        };
+
+        let mut app = Self {
+            path_save: path,
+            original_screenshot: None,
+            edited_screenshot: None,
+            settings: false,
+            format: config.format,
+            set_delay: Some(config.delay),
+            annotations: Annotations::new(iced::Size::ZERO, Message::NewAnnotation),
+            history: Vec::new(),
+            selected_tool: None,
+            crop_tool: None,
+            tool_color: config.tools.color,
+            tools: config.tools.clone(),
+            color_picker: color_picker::ColorPicker {},
+            all_screens,
+            selected_screen,
+            resources: ResHandles {
+                crop_icon: svg::Handle::from_path("res/crop.svg"),
+                arrow_icon: svg::Handle::from_path("res/arrow.svg"),
+                highlighter_icon: svg::Handle::from_path("res/highlighter.svg"),
+                display_icon: svg::Handle::from_path("res/display.svg"),
+                pen_icon: svg::Handle::from_path("res/pen.svg"),
+                plus_icon: svg::Handle::from_path("res/plus.svg"),
+                square_icon: svg::Handle::from_path("res/square.svg"),
+                circle_icon: svg::Handle::from_path("res/circle.svg"),
+                stopwatch_icon: svg::Handle::from_path("res/stopwatch.svg"),
+                text_icon: svg::Handle::from_path("res/text.svg"),
+                undo_icon: svg::Handle::from_path("res/undo.svg"),
+            },
+            toast: None,
+            toast_id: 0,
+            config_save_id: 0,
+            config_unsaved: false,
+            exporting: false,
+            hotkey_manager,
+            hotkey_bindings,
+            last_capture: None,
+            captured_screen: None,
+            captured_at: None,
+            note: String::new(),
+            pdf_title: String::new(),
+            shortcuts_page: false,
+            recording: None,
+            binding_error: None,
+            cheat_sheet: false,
+            //Letto prima che config venga spostato nello stato
+            watermark: config.watermark.enabled,
+            beautify: config.beautify.enabled,
+            preview: false,
+            preview_image: None,
+            preview_id: 0,
+            preview_base: None,
+            color_inputs: Default::default(),
+            preset_name: String::new(),
+            resize_dialog: None,
+            document_id: 0,
+            config,
+            config_path,
+            control: flags.control.map(|r| Arc::new(Mutex::new(r))),
+            stamps: Vec::new(),
+            stamp: None,
+        };
+        app.sync_color_inputs();
+
+        let mut commands: Vec<_> = errors
+            .into_iter()
+            .map(|(context, e)| app.report_error(context, e))
+            .collect();
+        if let Some(path) = flags.open {
+            commands.push(app.update(Message::OpenFile(path)));
+        }
+
+        (app, Command::batch(commands))
+    }
+
+    fn title(&self) -> String {
+        String::from("Screenshot utility")
+    }
+
+    fn update(&mut self, message: Self::Message) -> Command<Message> {
+        match message {
+            //Salva l'immagine nella cartella di default dandogli come nome screenshot_{date}_{time}.{imageFormat}.
+            //Il rendering e la codifica avvengono in background
+            Message::Save => {
+                let target = ExportTarget::Folder(self.path_save.clone(), self.file_name());
+                self.start_export(target)
+            }
+            //Apre un FileDialog per scegliere dove salvare lo screenshot e lo salva in background
+            Message::SaveAs => {
+                if self.exporting {
+                    return Command::none();
+                }
+                if self.original_screenshot.is_none() {
+                    return self.show_toast(Toast::Error(String::from("No screenshot to save")));
+                }
+                let fd = FileDialog::new().clone().set_directory(&self.path_save);
+                let (width, height) = self
+                    .edited_screenshot
+                    .as_ref()
+                    .map(|s| s.dimensions())
+                    .unwrap_or_default();
+                let file_name = self.file_name().file_name(width, height);
+                if let Some(s) = save(fd, &file_name, self.format) {
+                    self.start_export(ExportTarget::File(s))
                 } else {
-                    pds_project::error_popup("Nessuna immagine", "Nessuno screenshot da salvare");
+                    Command::none()
                 }
-                Command::none()
             }
 
             //Riduce a icona l'applicazione, lancia un task asincrono per attendere il delay per poi inivare il messaggio TakeScreenshot
-            Message::InitScreenshot => {
+            Message::InitScreenshot(mode) => {
+                self.last_capture = Some(mode);
                 let commands = [
                     iced::window::change_mode(iced::window::Mode::Hidden),
                     match self.set_delay {
                         Some(Delays::Zero) => Command::perform(
                             tokio::time::sleep(std::time::Duration::from_millis(500)),
-                            |_| Message::TakeScreenshot,
+                            move |_| Message::TakeScreenshot(mode),
                         ),
                         Some(Delays::Three) => Command::perform(
                             tokio::time::sleep(std::time::Duration::from_secs(3)),
-                            |_| Message::TakeScreenshot,
+                            move |_| Message::TakeScreenshot(mode),
                         ),
                         Some(Delays::Five) => Command::perform(
                             tokio::time::sleep(std::time::Duration::from_secs(5)),
-                            |_| Message::TakeScreenshot,
+                            move |_| Message::TakeScreenshot(mode),
                         ),
                         Some(Delays::Ten) => Command::perform(
                             tokio::time::sleep(std::time::Duration::from_secs(10)),
-                            |_| Message::TakeScreenshot,
+                            move |_| Message::TakeScreenshot(mode),
                         ),
                         _ => Command::none(),
                     },
@@ -608,37 +2278,67 @@ impl Application for ScreenCapture {
                 Command::batch(commands)
             }
 
-            //Effettua uno screenshot sullo schermo selelzionato
-            Message::TakeScreenshot => {
-                //Se non ci sono scremi la unwrap chiama un panic e questo perché l'applicazione in assenza di schermi non può procedere
-                if let Some(tmp) = screenshot(self.selected_screen.unwrap()) {
-                    let size = iced::Size::new(tmp.width() as f32, tmp.height() as f32);
-                    self.original_screenshot = Some(tmp);
-                    self.edited_screenshot = self.original_screenshot.clone();
-
-                    self.annotations.clear_annotations();
-                    self.annotations.set_image_size(size);
-                    self.selected_tool = None;
-                    self.crop_tool = None;
-                    self.history.clear();
-
-                    'blk: {
-                        // Se una qualunque di queste istruzioni fallisce è accettabile andare
-                        // avanti. Verrà comunque fatto lo screenshot, ma non verrà riprodotto
-                        // alcun suono.
-                        let Ok((_stream, handle)) = rodio::OutputStream::try_default() else { break 'blk };
-                        let Ok(sink) = rodio::Sink::try_new(&handle) else { break 'blk };
-
-                        let Ok(file) = File::open("res/iphone-camera-capture-6448.mp3") else { break 'blk };
-                        let Ok(decoder) = rodio::Decoder::new(BufReader::new(file)) else { break 'blk };
-
-                        sink.append(decoder);
-                        sink.sleep_until_end();
-                    }
+            //Esegue l'azione associata alla hotkey globale premuta
+            Message::GlobalHotkey(id) => match self.hotkey_bindings.action(id) {
+                Some(HotkeyAction::CaptureScreen) => {
+                    self.update(Message::InitScreenshot(CaptureMode::Screen))
+                }
+                Some(HotkeyAction::CaptureRegion) => {
+                    self.update(Message::InitScreenshot(CaptureMode::Region))
+                }
+                Some(HotkeyAction::CaptureAllScreens) => {
+                    self.update(Message::InitScreenshot(CaptureMode::AllScreens))
+                }
+                Some(HotkeyAction::RepeatLastCapture) => {
+                    let mode = self.last_capture.unwrap_or(CaptureMode::Screen);
+                    self.update(Message::InitScreenshot(mode))
+                }
+                Some(HotkeyAction::CopyLastCapture) => self.update(Message::CopyToClipboard),
+                None => Command::none(),
+            },
 
-                    iced::window::change_mode(iced::window::Mode::Windowed)
-                } else {
-                    Command::none()
+            //Effettua uno screenshot sullo schermo selelzionato, o su tutti gli schermi
+            Message::TakeScreenshot(mode) => {
+                let result = match (mode, self.selected_screen) {
+                    (CaptureMode::AllScreens, _) => screenshot_all(),
+                    (_, Some(screen)) => screenshot(screen),
+                    (_, None) => Err(Error::Capture(String::from("no screen selected"))),
+                };
+                match result {
+                    Ok(tmp) => {
+                        self.set_document(tmp);
+                        self.captured_screen = match mode {
+                            CaptureMode::AllScreens => None,
+                            _ => self.selected_screen.map(|s| s.id()),
+                        };
+                        self.captured_at = Some(chrono::Local::now());
+
+                        'blk: {
+                            // Se una qualunque di queste istruzioni fallisce è accettabile andare
+                            // avanti. Verrà comunque fatto lo screenshot, ma non verrà riprodotto
+                            // alcun suono.
+                            let Ok((_stream, handle)) = rodio::OutputStream::try_default() else { break 'blk };
+                            let Ok(sink) = rodio::Sink::try_new(&handle) else { break 'blk };
+
+                            let Ok(file) = File::open("res/iphone-camera-capture-6448.mp3") else { break 'blk };
+                            let Ok(decoder) = rodio::Decoder::new(BufReader::new(file)) else { break 'blk };
+
+                            sink.append(decoder);
+                            sink.sleep_until_end();
+                        }
+
+                        let show = iced::window::change_mode(iced::window::Mode::Windowed);
+                        if mode == CaptureMode::Region {
+                            Command::batch([show, self.update(Message::BeginCrop)])
+                        } else {
+                            show
+                        }
+                    }
+                    //La finestra era stata nascosta, va comunque mostrata di nuovo
+                    Err(e) => Command::batch([
+                        iced::window::change_mode(iced::window::Mode::Windowed),
+                        self.report_error("Unable to take the screenshot", e),
+                    ]),
                 }
             }
             //Se true mostra i settings
@@ -650,29 +2350,41 @@ impl Application for ScreenCapture {
             //Cambia il formato scelto con cui salvare l'immaggine
             Message::ChangeFormat { format } => {
                 self.format = format;
-                if let Ok(mut f) = File::create(PathBuf::from("config.config")) {
-                    let mut s = String::from(self.path_save.to_str().unwrap());
-                    s += "\n";
-                    s += self.format.as_str();
-                    if let Err(_) = f.write(s.as_bytes()) {
-                        pds_project::error_popup(
-                            "Errore",
-                            "Formato cambiato ma l'opzione non è stata resa permanente",
-                        );
-                    }
-                } else {
-                    pds_project::error_popup(
-                        "Errore",
-                        "Formato cambiato ma l'opzione non è stata resa permanente",
-                    )
+                if let Err(e) = self.write_config() {
+                    return self.report_error("Format changed but not saved permanently", e);
                 }
                 Command::none()
             }
 
-            //Cambia il tool selezionato
+            //Cambia il tool selezionato. La libreria dei timbri viene riletta ogni volta
+            //che si passa allo strumento Stamp, così i file aggiunti compaiono subito
             Message::ToolSelected(tool) => {
+                let refresh = matches!(tool, PickListTools::Stamp { .. })
+                    && !matches!(self.selected_tool, Some(PickListTools::Stamp { .. }));
                 self.selected_tool = Some(tool);
                 self.update_annotations();
+                if refresh {
+                    match self.stamps_dir().and_then(|dir| stamps::list_stamps(&dir)) {
+                        Ok(list) => self.stamps = list,
+                        Err(e) => return self.report_error("Unable to read the stamp library", e),
+                    }
+                }
+                Command::none()
+            }
+
+            //Carica il timbro scelto in background, gli Svg vanno rasterizzati
+            Message::StampSelected(stamp) => Command::perform(
+                async move {
+                    tokio::task::spawn_blocking(move || stamp.load().map(|image| (stamp, image)))
+                        .await
+                        .unwrap_or_else(|e| Err(Error::Render(e.to_string())))
+                },
+                Message::StampLoaded,
+            ),
+            Message::StampLoaded(Err(e)) => self.report_error("Unable to load the stamp", e),
+            Message::StampLoaded(Ok((stamp, image))) => {
+                self.stamp = Some((stamp, Arc::new(image)));
+                self.update_annotations();
                 Command::none()
             }
 
@@ -680,38 +2392,635 @@ impl Application for ScreenCapture {
             Message::ToolColorSelected(c) => {
                 self.tool_color = c;
                 self.update_annotations();
+                if let Err(e) = self.write_config() {
+                    return self.report_error("Color changed but not saved permanently", e);
+                }
                 Command::none()
             }
 
-            Message::CopyToClipboard => {
-                if let Some(image) = self.render_screenshot() {
-                    let image = arboard::ImageData {
-                        width: image.width() as usize,
-                        height: image.height() as usize,
-                        bytes: std::borrow::Cow::Borrowed(image.as_raw()),
+            //Copia lo screenshot annotato negli appunti in background
+            Message::CopyToClipboard => self.start_export(ExportTarget::Clipboard),
+
+            //Legge l'immagine negli appunti in background
+            Message::Paste => Command::perform(
+                async {
+                    tokio::task::spawn_blocking(|| {
+                        let data = arboard::Clipboard::new()?.get_image()?;
+                        RgbaImage::from_raw(
+                            data.width as u32,
+                            data.height as u32,
+                            data.bytes.into_owned(),
+                        )
+                        .ok_or_else(|| Error::Render(String::from("invalid clipboard image")))
+                    })
+                    .await
+                    .unwrap_or_else(|e| Err(Error::Render(e.to_string())))
+                },
+                Message::Pasted,
+            ),
+            Message::Pasted(Err(e)) => self.report_error("Unable to paste the image", e),
+            //Senza uno screenshot l'immagine diventa il nuovo documento, altrimenti viene
+            //aggiunta sopra come annotazione da spostare e ridimensionare
+            Message::Pasted(Ok(image)) => {
+                if self.original_screenshot.is_none() {
+                    self.set_document(image);
+                    return Command::none();
+                }
+                //Senza strumento l'immagine selezionata si può trascinare subito
+                self.selected_tool = None;
+                self.update_annotations();
+                self.annotations.insert_image(image);
+                self.history.push(HistoryEntry::Annotate);
+                Command::none()
+            }
+
+            //Mostra l'esito di un'esportazione terminata
+            Message::ExportFinished(result) => {
+                self.exporting = false;
+                match result {
+                    Ok((ExportTarget::File(path) | ExportTarget::Folder(path, _), saved)) => {
+                        let mut message = format!("Saved to {}", path.display());
+                        if let Some(saved) = saved {
+                            message.push_str(&format!(" (optimized, {:.1} KB saved)", saved as f64 / 1024.0));
+                        }
+                        self.show_toast(Toast::Success(message))
+                    }
+                    Ok((ExportTarget::Clipboard, _)) => {
+                        self.show_toast(Toast::Success(String::from("Copied to clipboard")))
+                    }
+                    Err(e) => self.report_error("Export failed", e),
+                }
+            }
+
+            //Durante una registrazione il tasto premuto diventa la nuova combinazione,
+            //altrimenti viene eseguita l'eventuale azione dell'editor associata
+            Message::KeyPressed(key_code, modifiers) => {
+                let Some(accelerator) = Accelerator::from_iced(key_code, modifiers) else {
+                    return Command::none();
+                };
+
+                if let Some(binding) = self.recording {
+                    if key_code == KeyCode::Escape && modifiers.is_empty() {
+                        self.binding_error = None;
+                        return self.stop_recording();
+                    }
+                    self.binding_error = match self.config.set_binding(binding, &accelerator) {
+                        Ok(()) => self.write_config().err().map(|e| e.to_string()),
+                        Err(e) => Some(e.to_string()),
                     };
-                    if let Ok(mut clipboard) = arboard::Clipboard::new() {
-                        if let Err(_) = clipboard.set_image(image) {
-                            pds_project::error_popup(
-                                "Errore",
-                                "Non è possibile gestire la clipboard",
-                            );
+                    return self.stop_recording();
+                }
+                if self.shortcuts_page {
+                    return Command::none();
+                }
+                //Durante il crop Invio conferma ed Esc annulla, gli altri tasti sono ignorati
+                if self.crop_tool.is_some() {
+                    return match (key_code, modifiers.is_empty()) {
+                        (KeyCode::Enter | KeyCode::NumpadEnter, true) => {
+                            self.update(Message::EndCrop)
                         }
-                    } else {
-                        pds_project::error_popup("Errore", "Non è possibile gestire la clipboard");
+                        (KeyCode::Escape, true) => self.update(Message::CancelCrop),
+                        _ => Command::none(),
+                    };
+                }
+
+                let Some(action) = self.config.editor_action(&accelerator) else {
+                    return Command::none();
+                };
+                let text_tool = PickListTools::Text {
+                    text: String::new(),
+                    size: self.tools.text_size,
+                };
+                let message = match action {
+                    EditorAction::Copy => Message::CopyToClipboard,
+                    EditorAction::Paste => Message::Paste,
+                    EditorAction::Save => Message::Save,
+                    EditorAction::Open => Message::Open,
+                    EditorAction::CheatSheet => Message::ShowCheatSheet(!self.cheat_sheet),
+                    EditorAction::Deselect if self.cheat_sheet => Message::ShowCheatSheet(false),
+                    //Le altre azioni agiscono sullo screenshot, se non c'è non fanno nulla
+                    _ if self.edited_screenshot.is_none() => return Command::none(),
+                    EditorAction::Pen => Message::ToolSelected(PickListTools::Pen),
+                    EditorAction::Rectangle => Message::ToolSelected(PickListTools::Rectangle),
+                    EditorAction::Ellipse => Message::ToolSelected(PickListTools::Ellipse),
+                    EditorAction::Arrow => Message::ToolSelected(PickListTools::Arrow),
+                    EditorAction::Text => Message::ToolSelected(text_tool),
+                    EditorAction::Highlighter => Message::ToolSelected(PickListTools::Highlighter),
+                    EditorAction::Crop => Message::BeginCrop,
+                    EditorAction::Deselect => Message::Deselect,
+                    EditorAction::DeleteAnnotation => Message::DeleteAnnotation,
+                };
+                self.update(message)
+            }
+
+            //Aggiorna il template del nome dei file, salvato in modo permanente con Invio
+            Message::FilenameTemplateChanged(template) => {
+                self.config.filename_template = template;
+                Command::none()
+            }
+            Message::SaveFilenameTemplate => {
+                if self.config.filename_template.trim().is_empty() {
+                    self.config.filename_template = String::from(config::DEFAULT_FILENAME_TEMPLATE);
+                }
+                if let Err(e) = self.write_config() {
+                    return self.report_error("File name template not saved permanently", e);
+                }
+                self.show_toast(Toast::Success(String::from("File name template saved")))
+            }
+
+            //Aggiorna le opzioni del codificatore del formato selezionato
+            Message::EncoderOptionsChanged(options) => {
+                self.config.encoder = options;
+                self.schedule_config_save()
+            }
+            //Salva la configurazione, a meno che nel frattempo non sia cambiata di nuovo
+            Message::SaveConfig(id) => {
+                if id != self.config_save_id || !self.config_unsaved {
+                    return Command::none();
+                }
+                self.config_unsaved = false;
+                if let Err(e) = self.write_config() {
+                    return self.report_error("Settings not saved permanently", e);
+                }
+                Command::none()
+            }
+
+            //La filigrana si può attivare o disattivare per il singolo salvataggio
+            Message::WatermarkToggled(enabled) => {
+                self.watermark = enabled;
+                self.refresh_preview()
+            }
+            Message::WatermarkOptionsChanged(options) => {
+                //Cambiando il default cambia anche la scelta corrente
+                if options.enabled != self.config.watermark.enabled {
+                    self.watermark = options.enabled;
+                }
+                self.config.watermark = options;
+                //Il testo e gli slider cambiano a ogni tasto o passo
+                Command::batch([self.schedule_config_save(), self.refresh_preview()])
+            }
+            Message::ChooseWatermarkLogo => {
+                let mut dialog = FileDialog::new().add_filter("Logo", &stamps::STAMP_EXTENSIONS);
+                if let Some(dir) = self.config.watermark.logo.as_ref().and_then(|p| p.parent()) {
+                    dialog = dialog.set_directory(dir);
+                }
+                if let Some(path) = dialog.pick_file() {
+                    self.config.watermark.logo = Some(path);
+                    if let Err(e) = self.write_config() {
+                        return self.report_error("Watermark logo not saved permanently", e);
+                    }
+                }
+                self.refresh_preview()
+            }
+
+            //Anche la presentazione si può attivare o disattivare per il singolo salvataggio
+            Message::BeautifyToggled(enabled) => {
+                self.beautify = enabled;
+                Command::none()
+            }
+            Message::BeautifyOptionsChanged(options) => {
+                if options.enabled != self.config.beautify.enabled {
+                    self.beautify = options.enabled;
+                }
+                self.config.beautify = options;
+                self.sync_color_inputs();
+                //Gli slider del preset cambiano a ogni passo
+                Command::batch([self.schedule_config_save(), self.refresh_preview()])
+            }
+            //Il colore viene applicato solo quando il testo è un colore valido
+            Message::BeautifyColorChanged(index, input) => {
+                let color = input.parse::<beautify::Rgb>();
+                self.color_inputs[index] = input;
+                let (Ok(color), Some(preset)) = (color, self.config.beautify.selected_mut()) else {
+                    return Command::none();
+                };
+                match (&mut preset.background, index) {
+                    (Background::Solid { color: c }, 0)
+                    | (Background::Gradient { from: c, .. }, 0)
+                    | (Background::Gradient { to: c, .. }, _) => *c = color,
+                    _ => return Command::none(),
+                }
+                Command::batch([self.schedule_config_save(), self.refresh_preview()])
+            }
+            Message::PresetNameChanged(name) => {
+                self.preset_name = name;
+                Command::none()
+            }
+            //Salva il preset corrente con un nuovo nome, o sovrascrive quello omonimo
+            Message::SavePreset => {
+                let name = self.preset_name.trim().to_string();
+                if name.is_empty() {
+                    return self.show_toast(Toast::Error(String::from("Enter a name for the preset")));
+                }
+                let Some(current) = self.config.beautify.selected().cloned() else {
+                    return Command::none();
+                };
+                let preset = BeautifyPreset {
+                    name: name.clone(),
+                    ..current
+                };
+                let presets = &mut self.config.beautify.presets;
+                match presets.iter_mut().find(|p| p.name == name) {
+                    Some(p) => *p = preset,
+                    None => presets.push(preset),
+                }
+                self.config.beautify.preset = name.clone();
+                self.preset_name.clear();
+                if let Err(e) = self.write_config() {
+                    return self.report_error("Beautify preset not saved permanently", e);
+                }
+                self.show_toast(Toast::Success(format!("Preset \"{}\" saved", name)))
+            }
+            Message::DeletePreset => {
+                let options = &mut self.config.beautify;
+                if options.presets.len() > 1 {
+                    let name = options.selected().map(|p| p.name.clone()).unwrap_or_default();
+                    options.presets.retain(|p| p.name != name);
+                    options.preset = options.presets[0].name.clone();
+                }
+                self.sync_color_inputs();
+                if let Err(e) = self.write_config() {
+                    return self.report_error("Beautify presets not saved permanently", e);
+                }
+                self.refresh_preview()
+            }
+            Message::ShowPreview(preview) => {
+                self.preview = preview;
+                //Riaprendo l'anteprima lo screenshot potrebbe essere cambiato
+                self.preview_base = None;
+                if !preview {
+                    self.preview_image = None;
+                }
+                self.refresh_preview()
+            }
+            Message::RenderPreview(id) => {
+                if id != self.preview_id || !self.preview {
+                    return Command::none();
+                }
+                self.render_preview(id)
+            }
+            Message::PreviewRendered(id, result) => {
+                if id != self.preview_id || !self.preview {
+                    return Command::none();
+                }
+                match result {
+                    Ok((base, image)) => {
+                        self.preview_base = Some(base);
+                        self.preview_image = Some(widget::image::Handle::from_pixels(
+                            image.width(),
+                            image.height(),
+                            image.into_raw(),
+                        ));
+                        Command::none()
                     }
+                    Err(e) => self.report_error("Unable to render the preview", e),
                 }
+            }
 
+            Message::ShowResize(show) => {
+                self.resize_dialog = match (show, &self.edited_screenshot) {
+                    (true, Some(s)) => Some(ResizeDialog::new(s.width(), s.height())),
+                    _ => None,
+                };
+                Command::none()
+            }
+            Message::ResizeDialogChanged(dialog) => {
+                self.resize_dialog = Some(dialog);
                 Command::none()
             }
+            //La dimensione scelta è quella della parte visibile: l'originale
+            //viene scalato dello stesso fattore, in background
+            Message::ApplyResize => {
+                let Some(dialog) = self.resize_dialog.take() else {
+                    return Command::none();
+                };
+                let (Some(original), Some(visible)) =
+                    (self.original_screenshot.clone(), self.edited_screenshot.as_ref())
+                else {
+                    return Command::none();
+                };
+                let Some((width, height)) =
+                    dialog.mode().and_then(|m| m.target(visible.width(), visible.height()))
+                else {
+                    return self.show_toast(Toast::Error(String::from("Invalid size")));
+                };
+                let sx = width as f32 / visible.width() as f32;
+                let sy = height as f32 / visible.height() as f32;
+                let id = self.document_id;
+                let size = (
+                    ((original.width() as f32 * sx).round() as u32).clamp(1, resize::MAX_SIDE),
+                    ((original.height() as f32 * sy).round() as u32).clamp(1, resize::MAX_SIDE),
+                );
+                Command::batch([
+                    self.show_toast(Toast::Working(String::from("Resizing…"))),
+                    Command::perform(
+                        async move {
+                            tokio::task::spawn_blocking(move || {
+                                (resize::resize(&original, size.0, size.1, dialog.filter), sx, sy)
+                            })
+                            .await
+                            .map_err(|e| Error::Render(e.to_string()))
+                        },
+                        move |result| Message::Resized(id, result),
+                    ),
+                ])
+            }
+            Message::TransformDocument(transform) => {
+                if self.original_screenshot.is_some() {
+                    self.transform_document(transform);
+                    self.history.push(HistoryEntry::Transform(transform));
+                }
+                Command::none()
+            }
+            Message::Resized(_, Err(e)) => self.report_error("Unable to resize the screenshot", e),
+            //Se nel frattempo lo screenshot è stato sostituito, ad esempio da una nuova
+            //cattura o da un altro ridimensionamento, il risultato non vale più
+            Message::Resized(id, Ok(_)) if id != self.document_id => self.show_toast(Toast::Error(
+                String::from("The screenshot changed while it was being resized"),
+            )),
+            //Scala annotazioni e crop e registra lo stato precedente nella cronologia
+            Message::Resized(_, Ok((image, sx, sy))) => {
+                let Some(original) = self.original_screenshot.replace(image) else {
+                    return Command::none();
+                };
+                self.document_id += 1;
+                let (width, height) = self.original_screenshot.as_ref().unwrap().dimensions();
+                let entry = HistoryEntry::Resize {
+                    original,
+                    annotations: self.annotations.annotations(),
+                    deleted: self.annotations.deleted(),
+                    crops: self.history_crops().map(|r| *r).collect(),
+                };
+                self.map_history_crops(|c| resize::scale_crop(c, sx, sy, width, height));
+                self.annotations.resize(Size::new(width as f32, height as f32));
+                let crop_area = self.get_last_crop();
+                self.set_screenshot_crop(crop_area);
+                self.history.push(entry);
+                let (width, height) = self.edited_screenshot.as_ref().map_or((width, height), |s| s.dimensions());
+                self.show_toast(Toast::Success(format!("Resized to {} x {}", width, height)))
+            }
+
+            //Aggiorna la nota da salvare nei metadati
+            Message::NoteChanged(note) => {
+                self.note = note;
+                Command::none()
+            }
+
+            //Esporta in background lo screenshot con le annotazioni come elementi SVG
+            Message::ExportSvg => {
+                let options = self.config.encoder.svg.clone();
+                self.export_document("SVG", move |image, annotations, crop, path| {
+                    vector::save_svg(image, annotations, crop, &options, path)
+                })
+            }
+            Message::ExportPdf => {
+                let options = self.config.encoder.pdf.clone();
+                let title = Some(self.pdf_title.clone()).filter(|t| !t.trim().is_empty());
+                let captured_at = self.captured_at.map(Into::into);
+                self.export_document("PDF", move |image, annotations, crop, path| {
+                    pdf::save_pdf(image, annotations, crop, title.as_deref(), captured_at, &options, path)
+                })
+            }
+            Message::PdfTitleChanged(title) => {
+                self.pdf_title = title;
+                Command::none()
+            }
+
+            //Salva in background lo screenshot originale con annotazioni e cronologia
+            Message::SaveProject => {
+                let Some(original) = self.original_screenshot.clone() else {
+                    return self.show_toast(Toast::Error(String::from("No screenshot to save")));
+                };
+                let mut file_name = self.file_name().render(original.width(), original.height(), 1);
+                file_name.set_extension(project::PROJECT_EXTENSION);
+                let Some(path) = FileDialog::new()
+                    .set_directory(&self.path_save)
+                    .set_file_name(&file_name.file_name().unwrap_or_default().to_string_lossy())
+                    .add_filter("Project", &[project::PROJECT_EXTENSION])
+                    .save_file()
+                else {
+                    return Command::none();
+                };
+
+                let project = Project {
+                    original,
+                    annotations: self.annotations.annotations(),
+                    deleted: self.annotations.deleted(),
+                    history: self.history.iter().filter_map(HistoryEntry::step).collect(),
+                    tools: config::ToolDefaults {
+                        color: self.tool_color,
+                        ..self.tools.clone()
+                    },
+                };
+                self.exporting = true;
+                Command::batch([
+                    self.show_toast(Toast::Working(String::from("Saving project…"))),
+                    Command::perform(
+                        async move {
+                            tokio::task::spawn_blocking(move || {
+                                project.save(&path).map(|_| path)
+                            })
+                            .await
+                            .unwrap_or_else(|e| Err(Error::Render(e.to_string())))
+                        },
+                        Message::ProjectSaved,
+                    ),
+                ])
+            }
+            Message::ProjectSaved(result) => {
+                self.exporting = false;
+                match result {
+                    Ok(path) => self.show_toast(Toast::Success(format!(
+                        "Project saved to {}",
+                        path.display()
+                    ))),
+                    Err(e) => self.report_error("Project not saved", e),
+                }
+            }
+
+            //Apre un'immagine o un progetto salvato, che sostituisce lo screenshot corrente
+            Message::Open => {
+                let all: Vec<&str> = OPEN_EXTENSIONS
+                    .into_iter()
+                    .chain([project::PROJECT_EXTENSION])
+                    .collect();
+                let Some(path) = FileDialog::new()
+                    .set_directory(&self.path_save)
+                    .add_filter("Images and projects", &all)
+                    .add_filter("Images", &OPEN_EXTENSIONS)
+                    .add_filter("Project", &[project::PROJECT_EXTENSION])
+                    .pick_file()
+                else {
+                    return Command::none();
+                };
+                self.update(Message::OpenFile(path))
+            }
+            //Apre un progetto, riconosciuto dall'estensione, o un'immagine come nuovo screenshot.
+            //La lettura avviene in background
+            Message::OpenFile(path) => {
+                if path.extension().is_some_and(|e| e == project::PROJECT_EXTENSION) {
+                    return Command::perform(
+                        async move {
+                            tokio::task::spawn_blocking(move || Project::load(&path))
+                                .await
+                                .unwrap_or_else(|e| Err(Error::Render(e.to_string())))
+                        },
+                        Message::ProjectOpened,
+                    );
+                }
+                Command::perform(
+                    async move {
+                        tokio::task::spawn_blocking(move || {
+                            let image = image::open(&path).map_err(|e| match e {
+                                image::ImageError::IoError(e) => Error::io(&path, e),
+                                e => e.into(),
+                            })?;
+                            let metadata = read_metadata_from_file(&path).unwrap_or_default();
+                            Ok((image.to_rgba8(), metadata))
+                        })
+                        .await
+                        .unwrap_or_else(|e| Err(Error::Render(e.to_string())))
+                    },
+                    Message::ImageOpened,
+                )
+            }
+            Message::ImageOpened(Err(e)) => self.report_error("Unable to open the image", e),
+            //I metadati di una cattura salvata da questa applicazione vengono recuperati
+            Message::ImageOpened(Ok((image, metadata))) => {
+                self.set_document(image);
+                self.captured_screen = metadata.screen;
+                self.captured_at = metadata
+                    .captured_at
+                    .map(|t| t.with_timezone(&chrono::Local));
+                self.note = metadata.note.unwrap_or_default();
+                Command::none()
+            }
+
+            //Esegue un comando ricevuto sul socket di controllo
+            Message::Control(request) => {
+                let show = iced::window::change_mode(iced::window::Mode::Windowed);
+                match request {
+                    Request::Show => show,
+                    Request::Capture => self.update(Message::InitScreenshot(CaptureMode::Screen)),
+                    Request::CaptureRegion => {
+                        self.update(Message::InitScreenshot(CaptureMode::Region))
+                    }
+                    Request::CaptureAll => {
+                        self.update(Message::InitScreenshot(CaptureMode::AllScreens))
+                    }
+                    Request::Open(path) => {
+                        Command::batch([show, self.update(Message::OpenFile(path))])
+                    }
+                    Request::Save(None) => self.update(Message::Save),
+                    Request::Save(Some(path)) => self.start_export(ExportTarget::File(path)),
+                    Request::Copy => self.update(Message::CopyToClipboard),
+                    //Le modifiche in attesa di essere salvate andrebbero perse
+                    Request::Quit => {
+                        if self.config_unsaved {
+                            if let Err(e) = self.write_config() {
+                                log::error!("Settings not saved permanently: {}", e);
+                            }
+                        }
+                        iced::window::close()
+                    }
+                }
+            }
+            Message::ProjectOpened(Err(e)) => self.report_error("Unable to open the project", e),
+            Message::ProjectOpened(Ok(project)) => {
+                self.set_document(project.original);
+                self.annotations
+                    .set_annotations(project.annotations, project.deleted);
+                self.history = project.history.into_iter().map(Into::into).collect();
+                //Gli strumenti del progetto valgono solo per questo documento,
+                //le impostazioni globali restano invariate
+                self.tool_color = project.tools.color;
+                self.tools = project.tools;
+                self.set_screenshot_crop(self.get_last_crop());
+                self.update_annotations();
+
+                self.show_toast(Toast::Success(String::from("Project opened")))
+            }
+
+            //Mostra o nasconde il riepilogo delle scorciatoie
+            Message::ShowCheatSheet(show) => {
+                self.cheat_sheet = show;
+                Command::none()
+            }
+
+            //Deseleziona lo strumento corrente, così un click seleziona un'annotazione
+            Message::Deselect => {
+                self.selected_tool = None;
+                self.update_annotations();
+                Command::none()
+            }
+
+            //Elimina l'annotazione selezionata, l'operazione può essere annullata
+            Message::DeleteAnnotation => {
+                if self.annotations.delete_selected() {
+                    self.history.push(HistoryEntry::DeleteAnnotation);
+                }
+                Command::none()
+            }
+
+            //Mostra o nasconde la pagina delle scorciatoie
+            Message::ShowShortcuts(show) => {
+                self.shortcuts_page = show;
+                self.binding_error = None;
+                self.stop_recording()
+            }
+
+            //Inizia a registrare la nuova combinazione per l'azione indicata. Le hotkey
+            //globali vengono sospese, altrimenti il sistema le intercetterebbe
+            Message::RecordBinding(binding) => {
+                self.binding_error = None;
+                if self.recording.replace(binding).is_none() {
+                    if let Some(manager) = &self.hotkey_manager {
+                        for e in self.hotkey_bindings.unregister_all(manager) {
+                            log::warn!("Unable to unregister a global hotkey: {}", e);
+                        }
+                    }
+                }
+                Command::none()
+            }
+
+            //Ripristina le combinazioni di default
+            Message::ResetBindings => {
+                self.recording = None;
+                self.binding_error = None;
+                self.config.hotkeys = HotkeyAction::default_bindings();
+                self.config.shortcuts = EditorAction::default_bindings();
+                let reload = self.reload_hotkeys();
+                if let Err(e) = self.write_config() {
+                    return Command::batch([
+                        reload,
+                        self.report_error("Shortcuts restored but not saved permanently", e),
+                    ]);
+                }
+                reload
+            }
+
+            //Chiude la notifica, a meno che nel frattempo non ne sia arrivata una nuova
+            Message::DismissToast(id) => {
+                if id == self.toast_id {
+                    self.toast = None;
+                }
+                Command::none()
+            }
+
             Message::ScreenSelected(screen) => {
                 self.selected_screen = Some(screen);
+                if let Err(e) = self.write_config() {
+                    return self.report_error("Screen changed but not saved permanently", e);
+                }
                 Command::none()
             }
 
             //Imposta il delay
             Message::DelaySelected(delay) => {
                 self.set_delay = Some(delay);
+                if let Err(e) = self.write_config() {
+                    return self.report_error("Delay changed but not saved permanently", e);
+                }
                 Command::none()
             }
             //Annulla l'ultima modifica sullo screenshot
@@ -724,6 +3033,24 @@ impl Application for ScreenCapture {
                         let crop_area = self.get_last_crop();
                         self.set_screenshot_crop(crop_area);
                     }
+                    Some(HistoryEntry::DeleteAnnotation) => {
+                        self.annotations.restore_deleted();
+                    }
+                    Some(HistoryEntry::Resize { original, annotations, deleted, crops }) => {
+                        let size = Size::new(original.width() as f32, original.height() as f32);
+                        self.original_screenshot = Some(original);
+                        self.document_id += 1;
+                        self.annotations.set_image_size(size);
+                        self.annotations.set_annotations(annotations, deleted);
+                        for (crop, previous) in self.history_crops().zip(crops) {
+                            *crop = previous;
+                        }
+                        let crop_area = self.get_last_crop();
+                        self.set_screenshot_crop(crop_area);
+                    }
+                    Some(HistoryEntry::Transform(transform)) => {
+                        self.transform_document(transform.inverse());
+                    }
                     None => (),
                 };
                 Command::none()
@@ -736,25 +3063,38 @@ impl Application for ScreenCapture {
                     .pick_folder();
                 if let Some(path) = o {
                     self.path_save = path;
-                    if let Ok(mut f) = File::create(PathBuf::from("config.config")) {
-                        let mut s = String::from(self.path_save.to_str().unwrap());
-                        s += "\n";
-                        s += self.format.as_str();
-                        if let Err(_) = f.write(s.as_bytes()) {
-                            pds_project::error_popup("Errore", "Cartella di default cambiata ma non è stato possibile rendere permanente la cartella selazionata");
-                        }
-                    } else {
-                        pds_project::error_popup("Errore", "Cartella di default cambiata ma non è stato possibile rendere permanente la cartella selazionata");
+                    if let Err(e) = self.write_config() {
+                        return self.report_error("Save folder changed but not saved permanently", e);
                     }
                 }
                 Command::none()
             }
 
+            //La nuova cartella dei timbri viene letta subito, così il dialogo dello
+            //strumento Stamp mostra già i suoi file
+            Message::ChooseStampsFolder => {
+                let mut dialog = FileDialog::new();
+                if let Ok(dir) = self.stamps_dir() {
+                    dialog = dialog.set_directory(dir);
+                }
+                let Some(dir) = dialog.pick_folder() else {
+                    return Command::none();
+                };
+                self.stamps = stamps::list_stamps(&dir).unwrap_or_default();
+                self.config.stamps_dir = Some(dir);
+                if let Err(e) = self.write_config() {
+                    return self.report_error("Stamp folder changed but not saved permanently", e);
+                }
+                Command::none()
+            }
+
             //Prepara l'operazione di crop
             Message::BeginCrop => {
                 let Some(s) = self.render_screenshot() else {
-                    pds_project::error_popup("Errore durante il rendering", "Impossibile produrre lo screenshot annotato");
-                    return Command::none();
+                    return self.report_error(
+                        "Unable to start cropping",
+                        Error::Render(String::from("the annotated screenshot could not be produced")),
+                    );
                 };
 
                 self.edited_screenshot = Some(s);
@@ -837,6 +3177,8 @@ impl Application for ScreenCapture {
                 let canvas = Canvas::new(ct).width(Length::Fill).height(Length::Fill);
 
                 Modal::new(img, canvas).into()
+            } else if let Some(preview) = self.preview_image.as_ref().filter(|_| self.preview) {
+                widget::image(preview.clone()).into()
             } else {
                 let canvas = Canvas::new(self.annotations.clone())
                     .width(Length::Fill)
@@ -860,6 +3202,9 @@ impl Application for ScreenCapture {
                             Some(PickListTools::Text { text, size }) => {
                                 Self::text_dialog(text, *size)
                             }
+                            Some(PickListTools::Stamp { scale, opacity }) => {
+                                self.stamp_dialog(*scale, *opacity)
+                            }
                             _ => container(row![]).into(),
                         },
                         screenshot_canvas
@@ -869,57 +3214,90 @@ impl Application for ScreenCapture {
                     .align_x(Horizontal::Center)
                     .align_y(Vertical::Center),
                     self.right_bar()
-                ])
+                ]),
+                self.status_bar()
             ]
         } else {
-            let screenshot_text = text("Press SHIFT+D to take a screenshot")
+            let accelerator = self
+                .config
+                .hotkeys
+                .get(&HotkeyAction::CaptureScreen)
+                .and_then(|keys| keys.parse::<hotkey::Accelerator>().ok())
+                .filter(|_| self.hotkey_manager.is_some());
+            let screenshot_text = match accelerator {
+                Some(a) => text(format!("Press {} to take a screenshot", a)),
+                None => text("Press New to take a screenshot"),
+            }
                 .width(Length::Fill)
                 .height(Length::Fill)
                 .horizontal_alignment(Horizontal::Center)
                 .vertical_alignment(Vertical::Center);
 
-            column![row![
-                container(column![screenshot_text]).width(Length::Fill),
-                self.right_bar(),
-            ]]
+            column![
+                row![
+                    container(column![screenshot_text]).width(Length::Fill),
+                    self.right_bar(),
+                ],
+                self.status_bar()
+            ]
         };
-        container(content).into()
+        if self.shortcuts_page {
+            Modal::new(container(content), self.shortcuts_page())
+                .on_blur(Message::ShowShortcuts(false))
+                .into()
+        } else if self.cheat_sheet {
+            Modal::new(container(content), self.cheat_sheet())
+                .on_blur(Message::ShowCheatSheet(false))
+                .into()
+        } else if let (Some(dialog), Some(s)) = (&self.resize_dialog, &self.edited_screenshot) {
+            Modal::new(container(content), Self::resize_dialog(dialog, s.width(), s.height()))
+                .on_blur(Message::ShowResize(false))
+                .into()
+        } else {
+            container(content).into()
+        }
     }
 
     fn subscription(&self) -> Subscription<Message> {
         let subscriptions = [
             iced::subscription::unfold("hotkey", (), |_| hotkey::Hotkey {
-                msg: Message::InitScreenshot,
+                on_press: Message::GlobalHotkey,
             }),
+            //I tasti premuti vengono inoltrati a update, che li confronta con le scorciatoie
+            //configurate o li usa come nuova combinazione durante una registrazione
             iced::subscription::events_with(|event, status| {
                 if status == iced::event::Status::Captured {
                     return None;
                 }
 
-                let keyboard_event = if let iced::event::Event::Keyboard(e) = event {
-                    e
-                } else {
-                    return None;
-                };
-
-                if let iced::keyboard::Event::KeyPressed {
-                    key_code,
-                    modifiers,
-                } = keyboard_event
-                {
-                    let modifier = if cfg!(target_os = "macos") {
-                        iced::keyboard::Modifiers::LOGO
-                    } else {
-                        iced::keyboard::Modifiers::CTRL
-                    };
-
-                    if modifiers == modifier && key_code == iced::keyboard::KeyCode::C {
-                        return Some(Message::CopyToClipboard);
-                    } else if modifiers == modifier && key_code == iced::keyboard::KeyCode::S {
-                        return Some(Message::Save);
+                match event {
+                    iced::event::Event::Keyboard(iced::keyboard::Event::KeyPressed {
+                        key_code,
+                        modifiers,
+                    }) => Some(Message::KeyPressed(key_code, modifiers)),
+                    //Un file trascinato sulla finestra viene aperto come con Open
+                    iced::event::Event::Window(iced::window::Event::FileDropped(path)) => {
+                        Some(Message::OpenFile(path))
                     }
+                    _ => None,
+                }
+            }),
+            //I comandi del socket arrivano da un thread bloccante, letti uno alla volta
+            iced::subscription::unfold("control", self.control.clone(), |control| async move {
+                let request = match control.clone() {
+                    Some(receiver) => tokio::task::spawn_blocking(move || {
+                        receiver.lock().ok().and_then(|r| r.recv().ok())
+                    })
+                    .await
+                    .ok()
+                    .flatten(),
+                    None => None,
+                };
+                match request {
+                    Some(request) => (Message::Control(request), control),
+                    //Senza socket la subscription non produce messaggi
+                    None => std::future::pending().await,
                 }
-                None
             }),
         ];
 
@@ -0,0 +1,472 @@
+//! Metadati della cattura salvati nei file esportati.
+//!
+//! Nei Png vengono scritti come chunk tEXt, più un chunk iTXt per la nota
+//! che può contenere qualunque carattere. Nei Jpeg, WebP e Tiff vengono
+//! scritti come EXIF: schermo, crop e nota in UserComment, in Unicode se la
+//! nota non è ASCII, e la nota anche in ImageDescription se è ASCII, perché
+//! quel campo non ammette altri caratteri. Gif, Bmp, Ico e Pnm non hanno un
+//! posto standard per i metadati, quindi non li conservano.
+
+use crate::{Error, Result};
+use chrono::{DateTime, FixedOffset, Local};
+use exif::experimental::Writer;
+use exif::{Context, Field, In, Tag, Value};
+use image::error::{DecodingError, ImageFormatHint};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+use std::io::Cursor;
+use std::path::Path;
+use std::str::FromStr;
+
+const KEY_CREATION_TIME: &str = "Creation Time";
+const KEY_SOFTWARE: &str = "Software";
+const KEY_SCREEN: &str = "Screen";
+const KEY_CROP: &str = "Crop";
+const KEY_COMMENT: &str = "Comment";
+
+/// Codifica del testo di UserComment, seguita dal testo stesso
+const USER_COMMENT_ASCII: &[u8; 8] = b"ASCII\0\0\0";
+/// Come USER_COMMENT_ASCII, per un testo in UCS-2 nell'ordine dei byte del file
+const USER_COMMENT_UNICODE: &[u8; 8] = b"UNICODE\0";
+/// Chiave della nota in UserComment: è sempre l'ultima, così può contenere
+/// anche il separatore ;
+const COMMENT_NOTE: &str = "note=";
+const PNG_SIGNATURE: &[u8; 8] = b"\x89PNG\r\n\x1a\n";
+/// Campo ExtraSamples dei Tiff, che il crate exif non definisce perché non
+/// fa parte dello standard EXIF
+const TAG_EXTRA_SAMPLES: u16 = 338;
+
+/// Zona ritagliata dello screenshot, in pixel dell'immagine catturata
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    /// Se la zona non è vuota ed è interamente contenuta in un'immagine
+    /// delle dimensioni indicate
+    pub fn fits_in(&self, width: u32, height: u32) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self.x.checked_add(self.width).is_some_and(|r| r <= width)
+            && self.y.checked_add(self.height).is_some_and(|b| b <= height)
+    }
+}
+
+impl std::fmt::Display for Crop {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl FromStr for Crop {
+    type Err = ();
+
+    /// Legge una zona nel formato x,y,width,height
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>().map_err(|_| ()))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        match values[..] {
+            [x, y, width, height] => Ok(Crop {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Informazioni sulla cattura da salvare insieme all'immagine
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// Istante della cattura
+    pub captured_at: Option<DateTime<FixedOffset>>,
+    /// Id dello schermo catturato, None se sono stati catturati tutti
+    pub screen: Option<u32>,
+    /// Zona ritagliata, None se l'immagine non è stata ritagliata
+    pub crop: Option<Crop>,
+    /// Versione dell'applicazione che ha salvato il file
+    pub app_version: Option<String>,
+    /// Nota libera dell'utente
+    pub note: Option<String>,
+}
+
+impl Metadata {
+    /// Metadati di una cattura eseguita con la versione corrente
+    pub fn new(captured_at: DateTime<Local>) -> Self {
+        Self {
+            captured_at: Some(captured_at.into()),
+            app_version: Some(String::from(env!("CARGO_PKG_VERSION"))),
+            ..Self::default()
+        }
+    }
+
+    /// Chunk di testo da scrivere nei Png. Il terzo elemento indica se il
+    /// testo va in un chunk iTXt, che a differenza di tEXt accetta UTF-8.
+    pub(crate) fn png_texts(&self) -> Vec<(&'static str, String, bool)> {
+        let mut texts = Vec::new();
+        if let Some(time) = self.captured_at {
+            texts.push((KEY_CREATION_TIME, time.to_rfc3339(), false));
+        }
+        if let Some(version) = &self.app_version {
+            texts.push((KEY_SOFTWARE, software(version), false));
+        }
+        if let Some(screen) = self.screen {
+            texts.push((KEY_SCREEN, screen.to_string(), false));
+        }
+        if let Some(crop) = self.crop {
+            texts.push((KEY_CROP, crop.to_string(), false));
+        }
+        if let Some(note) = &self.note {
+            texts.push((KEY_COMMENT, note.clone(), true));
+        }
+        texts
+    }
+
+    /// Ricostruisce i metadati dai chunk di testo di un Png, ignorando
+    /// quelli sconosciuti o malformati
+    fn from_png_texts<'a>(texts: impl Iterator<Item = (&'a str, String)>) -> Self {
+        let mut metadata = Self::default();
+        for (keyword, text) in texts {
+            match keyword {
+                KEY_CREATION_TIME => {
+                    metadata.captured_at = DateTime::parse_from_rfc3339(&text).ok()
+                }
+                KEY_SOFTWARE => metadata.app_version = app_version(&text),
+                KEY_SCREEN => metadata.screen = text.parse().ok(),
+                KEY_CROP => metadata.crop = text.parse().ok(),
+                KEY_COMMENT => metadata.note = Some(text),
+                _ => {}
+            }
+        }
+        metadata
+    }
+
+    /// Codifica i metadati come EXIF, senza l'intestazione "Exif\0\0" del
+    /// segmento APP1 dei Jpeg
+    pub(crate) fn to_exif(&self) -> Result<Vec<u8>> {
+        write_exif(&self.exif_fields(), None)
+    }
+
+    /// Codifica l'immagine come Tiff non compresso. Un Tiff ha già la
+    /// struttura di un blocco EXIF, quindi i metadati vanno nella stessa
+    /// directory dei campi che descrivono l'immagine.
+    pub(crate) fn to_tiff(&self, image: &RgbaImage) -> Result<Vec<u8>> {
+        let short = |tag, value: Vec<u16>| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Short(value),
+        };
+        let long = |tag, value| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Long(vec![value]),
+        };
+
+        let mut fields = self.exif_fields();
+        fields.extend([
+            long(Tag::ImageWidth, image.width()),
+            long(Tag::ImageLength, image.height()),
+            short(Tag::BitsPerSample, vec![8; 4]),
+            //Nessuna compressione
+            short(Tag::Compression, vec![1]),
+            //RGB
+            short(Tag::PhotometricInterpretation, vec![2]),
+            short(Tag::SamplesPerPixel, vec![4]),
+            long(Tag::RowsPerStrip, image.height()),
+            //Campioni interlacciati, RGBARGBA...
+            short(Tag::PlanarConfiguration, vec![1]),
+            //Il quarto campione è un alpha non premoltiplicato
+            short(Tag(Context::Tiff, TAG_EXTRA_SAMPLES), vec![2]),
+        ]);
+        write_exif(&fields, Some(&[image.as_raw().as_slice()]))
+    }
+
+    /// Campi EXIF dei metadati, tutti nella directory principale o nella
+    /// sua sotto directory Exif
+    fn exif_fields(&self) -> Vec<Field> {
+        let ascii = |tag, text: &str| Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        };
+
+        let mut fields = Vec::new();
+        if let Some(time) = self.captured_at {
+            let datetime = time.format("%Y:%m:%d %H:%M:%S").to_string();
+            fields.push(ascii(Tag::DateTime, &datetime));
+            fields.push(ascii(Tag::DateTimeOriginal, &datetime));
+            fields.push(ascii(
+                Tag::OffsetTimeOriginal,
+                &time.format("%:z").to_string(),
+            ));
+        }
+        if let Some(version) = &self.app_version {
+            fields.push(ascii(Tag::Software, &software(version)));
+        }
+        if let Some(note) = self.note.as_ref().filter(|n| n.is_ascii()) {
+            fields.push(ascii(Tag::ImageDescription, note));
+        }
+        let mut comment = Vec::new();
+        if let Some(screen) = self.screen {
+            comment.push(format!("screen={}", screen));
+        }
+        if let Some(crop) = self.crop {
+            comment.push(format!("crop={}", crop));
+        }
+        if let Some(note) = &self.note {
+            comment.push(format!("{}{}", COMMENT_NOTE, note));
+        }
+        if !comment.is_empty() {
+            let comment = comment.join(";");
+            //Il file viene scritto in big endian
+            let value = if comment.is_ascii() {
+                let mut value = USER_COMMENT_ASCII.to_vec();
+                value.extend_from_slice(comment.as_bytes());
+                value
+            } else {
+                let mut value = USER_COMMENT_UNICODE.to_vec();
+                value.extend(comment.encode_utf16().flat_map(u16::to_be_bytes));
+                value
+            };
+            fields.push(Field {
+                tag: Tag::UserComment,
+                ifd_num: In::PRIMARY,
+                value: Value::Undefined(value, 0),
+            });
+        }
+        fields
+    }
+
+    fn from_exif(exif: &exif::Exif) -> Self {
+        let ascii = |tag| match exif.get_field(tag, In::PRIMARY).map(|f| &f.value) {
+            Some(Value::Ascii(v)) if !v.is_empty() => {
+                Some(String::from_utf8_lossy(&v[0]).into_owned())
+            }
+            _ => None,
+        };
+
+        let mut metadata = Self {
+            app_version: ascii(Tag::Software).and_then(|s| app_version(&s)),
+            note: ascii(Tag::ImageDescription),
+            ..Self::default()
+        };
+        if let Some(datetime) = ascii(Tag::DateTimeOriginal) {
+            let offset = ascii(Tag::OffsetTimeOriginal).unwrap_or_else(|| String::from("+00:00"));
+            metadata.captured_at = DateTime::parse_from_str(
+                &format!("{} {}", datetime, offset),
+                "%Y:%m:%d %H:%M:%S %:z",
+            )
+            .ok();
+        }
+        if let Some(Value::Undefined(v, _)) = exif
+            .get_field(Tag::UserComment, In::PRIMARY)
+            .map(|f| &f.value)
+        {
+            let comment = match v.strip_prefix(USER_COMMENT_UNICODE) {
+                Some(units) => {
+                    let units: Vec<u16> = units
+                        .chunks_exact(2)
+                        .map(|b| {
+                            if exif.little_endian() {
+                                u16::from_le_bytes([b[0], b[1]])
+                            } else {
+                                u16::from_be_bytes([b[0], b[1]])
+                            }
+                        })
+                        .collect();
+                    String::from_utf16_lossy(&units)
+                }
+                None => String::from_utf8_lossy(v.strip_prefix(USER_COMMENT_ASCII).unwrap_or(v))
+                    .into_owned(),
+            };
+            let (comment, note) = match comment.find(COMMENT_NOTE) {
+                Some(i) => (&comment[..i], Some(&comment[i + COMMENT_NOTE.len()..])),
+                None => (comment.as_str(), None),
+            };
+            if let Some(note) = note {
+                metadata.note = Some(String::from(note));
+            }
+            for entry in comment.split(';') {
+                match entry.split_once('=') {
+                    Some(("screen", screen)) => metadata.screen = screen.parse().ok(),
+                    Some(("crop", crop)) => metadata.crop = crop.parse().ok(),
+                    _ => {}
+                }
+            }
+        }
+        metadata
+    }
+}
+
+/// Legge i metadati da un'immagine codificata. Un'immagine senza metadati,
+/// o in un formato che non li supporta, restituisce metadati vuoti.
+pub fn read_metadata(data: &[u8]) -> Result<Metadata> {
+    if data.starts_with(PNG_SIGNATURE) {
+        let reader = png::Decoder::new(data).read_info().map_err(|e| {
+            Error::from(image::ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Exact(image::ImageFormat::Png),
+                e,
+            )))
+        })?;
+        let info = reader.info();
+        let texts = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|t| (t.keyword.as_str(), t.text.clone()))
+            .chain(
+                info.utf8_text
+                    .iter()
+                    .filter_map(|t| Some((t.keyword.as_str(), t.get_text().ok()?))),
+            );
+        return Ok(Metadata::from_png_texts(texts));
+    }
+
+    match exif::Reader::new().read_from_container(&mut Cursor::new(data)) {
+        Ok(exif) => Ok(Metadata::from_exif(&exif)),
+        Err(exif::Error::NotFound(_) | exif::Error::InvalidFormat(_)) => Ok(Metadata::default()),
+        Err(e) => Err(exif_error(e)),
+    }
+}
+
+/// Come [`read_metadata`], leggendo l'immagine da file
+pub fn read_metadata_from_file(path: &Path) -> Result<Metadata> {
+    let data = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+    read_metadata(&data)
+}
+
+/// Scrive i campi come blocco EXIF in big endian, insieme alle eventuali
+/// strisce di pixel dell'immagine
+fn write_exif(fields: &[Field], strips: Option<&[&[u8]]>) -> Result<Vec<u8>> {
+    let mut writer = Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    if let Some(strips) = strips {
+        writer.set_strips(strips, In::PRIMARY);
+    }
+    let mut buf = Cursor::new(Vec::new());
+    writer.write(&mut buf, false).map_err(exif_error)?;
+    Ok(buf.into_inner())
+}
+
+fn software(version: &str) -> String {
+    format!("{} {}", env!("CARGO_PKG_NAME"), version)
+}
+
+/// Estrae la versione dal campo Software, se il file è stato salvato da
+/// questa applicazione
+fn app_version(software: &str) -> Option<String> {
+    software
+        .strip_prefix(env!("CARGO_PKG_NAME"))
+        .and_then(|v| v.strip_prefix(' '))
+        .map(String::from)
+}
+
+fn exif_error(e: exif::Error) -> Error {
+    image::ImageError::Decoding(DecodingError::new(ImageFormatHint::Unknown, e)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{encode, EncoderOptions};
+    use crate::ImageFormat;
+    use chrono::TimeZone;
+
+    const METADATA_FORMATS: [ImageFormat; 4] = [
+        ImageFormat::Png,
+        ImageFormat::Jpeg,
+        ImageFormat::WebP,
+        ImageFormat::Tiff,
+    ];
+
+    fn metadata() -> Metadata {
+        Metadata {
+            screen: Some(2),
+            crop: Some(Crop {
+                x: 10,
+                y: 20,
+                width: 300,
+                height: 200,
+            }),
+            note: Some(String::from("Bug nel menù")),
+            ..Metadata::new(Local.with_ymd_and_hms(2023, 6, 30, 14, 5, 9).unwrap())
+        }
+    }
+
+    fn encoded(format: ImageFormat, metadata: &Metadata, options: &EncoderOptions) -> Vec<u8> {
+        let image = image::RgbaImage::from_pixel(32, 16, image::Rgba([40, 90, 200, 255]));
+        let mut buf = Cursor::new(Vec::new());
+        encode(&image, format, options, Some(metadata), &mut buf).unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let metadata = metadata();
+        for format in METADATA_FORMATS {
+            let data = encoded(format, &metadata, &EncoderOptions::default());
+            assert_eq!(read_metadata(&data).unwrap(), metadata, "{}", format);
+            //I metadati non devono impedire di leggere l'immagine
+            let decoded = image::load_from_memory_with_format(&data, format.into()).unwrap();
+            assert_eq!((decoded.width(), decoded.height()), (32, 16), "{}", format);
+        }
+
+        //Una nota ASCII va anche in ImageDescription, una con altri caratteri
+        //solo in UserComment, codificata in Unicode
+        let exif_field = |metadata: &Metadata, tag| {
+            let data = encoded(ImageFormat::Jpeg, metadata, &EncoderOptions::default());
+            let exif = exif::Reader::new()
+                .read_from_container(&mut Cursor::new(data))
+                .unwrap();
+            exif.get_field(tag, In::PRIMARY).map(|f| f.value.clone())
+        };
+        assert_eq!(exif_field(&metadata, Tag::ImageDescription), None);
+        assert!(matches!(
+            exif_field(&metadata, Tag::UserComment),
+            Some(Value::Undefined(v, _)) if v.starts_with(USER_COMMENT_UNICODE)
+        ));
+        let ascii = Metadata {
+            note: Some(String::from("screen=1;crop=x")),
+            ..metadata.clone()
+        };
+        assert!(matches!(
+            exif_field(&ascii, Tag::ImageDescription),
+            Some(Value::Ascii(v)) if v[0] == b"screen=1;crop=x"
+        ));
+        let data = encoded(ImageFormat::Jpeg, &ascii, &EncoderOptions::default());
+        assert_eq!(read_metadata(&data).unwrap(), ascii);
+
+        //I formati senza metadati restituiscono metadati vuoti
+        for format in [ImageFormat::Bmp, ImageFormat::Gif] {
+            let data = encoded(format, &metadata, &EncoderOptions::default());
+            assert_eq!(
+                read_metadata(&data).unwrap(),
+                Metadata::default(),
+                "{}",
+                format
+            );
+        }
+    }
+
+    #[test]
+    fn strip_metadata() {
+        let options = EncoderOptions {
+            strip_metadata: true,
+            ..EncoderOptions::default()
+        };
+        for format in METADATA_FORMATS {
+            let data = encoded(format, &metadata(), &options);
+            assert_eq!(read_metadata(&data).unwrap(), Metadata::default());
+        }
+    }
+}
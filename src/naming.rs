@@ -0,0 +1,208 @@
+//! Generazione dei nomi dei file salvati a partire da un template.
+//!
+//! Token supportati:
+//! - `{date}`, `{time}`, `{ms}`: data (2023-06-30), ora (14-05-09) e millisecondi (042)
+//! - `{year}`, `{month}`, `{day}`: parti della data, utili per le sottocartelle
+//! - `{screen}`: id dello schermo catturato, `all` se sono stati catturati tutti
+//! - `{counter}`: il primo numero progressivo (001, 002, ...) non ancora usato
+//! - `{width}`, `{height}`: dimensioni dell'immagine salvata
+//! - `{tool}`: modalità di cattura, ad esempio `screen` o `region`
+//! - `{name}`: nome senza estensione dell'immagine di partenza, usato
+//!   dall'elaborazione in batch
+//!
+//! Il carattere `/` separa le sottocartelle, ad esempio `{year}/{month}/{time}`.
+//! I file esistenti non vengono mai sovrascritti: se il nome è già usato viene
+//! aggiunto un suffisso `_1`, `_2`, ...
+
+use crate::{Error, ImageFormat, Result};
+use chrono::{DateTime, Local};
+use std::fs::{File, OpenOptions};
+use std::path::{Component, Path, PathBuf};
+
+/// Numero massimo di tentativi prima di rinunciare a trovare un nome libero
+const MAX_ATTEMPTS: u32 = 10_000;
+
+/// Template del nome di un file da salvare, con i valori dei token noti al
+/// momento della cattura
+#[derive(Debug, Clone)]
+pub struct FileName {
+    pub template: String,
+    pub format: ImageFormat,
+    /// Istante usato per i token di data e ora
+    pub time: DateTime<Local>,
+    /// Valore di {screen}
+    pub screen: Option<String>,
+    /// Valore di {tool}
+    pub tool: Option<String>,
+    /// Valore di {name}
+    pub name: Option<String>,
+}
+
+impl FileName {
+    pub fn new(template: &str, format: ImageFormat) -> Self {
+        Self {
+            template: template.to_string(),
+            format,
+            time: Local::now(),
+            screen: None,
+            tool: None,
+            name: None,
+        }
+    }
+
+    /// Sostituisce i token e aggiunge l'estensione. Il risultato è un path
+    /// relativo alla cartella di salvataggio: le componenti vuote, `.` e `..`
+    /// vengono scartate, così il template non può uscire dalla cartella.
+    pub fn render(&self, width: u32, height: u32, counter: u32) -> PathBuf {
+        let name = self
+            .template
+            .replace("{date}", &self.time.format("%Y-%m-%d").to_string())
+            .replace("{time}", &self.time.format("%H-%M-%S").to_string())
+            .replace("{ms}", &self.time.format("%3f").to_string())
+            .replace("{year}", &self.time.format("%Y").to_string())
+            .replace("{month}", &self.time.format("%m").to_string())
+            .replace("{day}", &self.time.format("%d").to_string())
+            .replace("{screen}", self.screen.as_deref().unwrap_or("unknown"))
+            .replace("{counter}", &format!("{:03}", counter))
+            .replace("{width}", &width.to_string())
+            .replace("{height}", &height.to_string())
+            .replace("{tool}", self.tool.as_deref().unwrap_or("screen"))
+            .replace("{name}", self.name.as_deref().unwrap_or("screenshot"));
+
+        let mut path: PathBuf = Path::new(&name)
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        if path.file_name().is_none() {
+            path.push("screenshot");
+        }
+
+        let extension = self.format.extensions()[0];
+        let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".");
+        file_name.push(extension);
+        path.set_file_name(file_name);
+        path
+    }
+
+    /// Restituisce il solo nome del file, senza sottocartelle, da proporre
+    /// nella finestra di salvataggio
+    pub fn file_name(&self, width: u32, height: u32) -> String {
+        let path = self.render(width, height, 1);
+        path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// Crea in dir un nuovo file con il nome generato, creando anche le
+    /// sottocartelle. Il file viene creato in modo esclusivo, quindi due
+    /// salvataggi contemporanei non possono mai scrivere sullo stesso file.
+    pub fn create(&self, dir: &Path, width: u32, height: u32) -> Result<(PathBuf, File)> {
+        let has_counter = self.template.contains("{counter}");
+        let base = dir.join(self.render(width, height, 1));
+
+        for attempt in 0..MAX_ATTEMPTS {
+            let path = if has_counter {
+                dir.join(self.render(width, height, attempt + 1))
+            } else {
+                with_suffix(&base, attempt)
+            };
+            //Il contatore può comparire anche nelle sottocartelle, quindi
+            //ogni nome provato può avere una cartella diversa
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+            }
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(f) => return Ok((path, f)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(Error::io(path, e)),
+            }
+        }
+
+        Err(Error::io(
+            base,
+            std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "no free file name is available",
+            ),
+        ))
+    }
+}
+
+/// Aggiunge _n prima dell'estensione, per n maggiore di zero
+fn with_suffix(path: &Path, n: u32) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}_{}", stem, n);
+    if let Some(ext) = path.extension() {
+        name.push('.');
+        name.push_str(&ext.to_string_lossy());
+    }
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+    use chrono::TimeZone;
+
+    fn file_name(template: &str) -> FileName {
+        FileName {
+            time: Local.with_ymd_and_hms(2023, 6, 30, 14, 5, 9).unwrap(),
+            screen: Some(String::from("2")),
+            tool: Some(String::from("region")),
+            name: Some(String::from("release")),
+            ..FileName::new(template, ImageFormat::Png)
+        }
+    }
+
+    #[test]
+    fn render_tokens() {
+        let name = file_name("{date}_{time}_{ms}_{screen}_{counter}_{width}x{height}_{tool}");
+        assert_eq!(
+            name.render(1920, 1080, 7),
+            PathBuf::from("2023-06-30_14-05-09_000_2_007_1920x1080_region.png")
+        );
+
+        let name = file_name("{name}-{width}");
+        assert_eq!(name.render(800, 600, 1), PathBuf::from("release-800.png"));
+
+        let name = file_name("{year}/{month}/{day}/shot");
+        assert_eq!(name.render(1, 1, 1), PathBuf::from("2023/06/30/shot.png"));
+        assert_eq!(name.file_name(1, 1), "shot.png");
+
+        //Il template non può uscire dalla cartella di salvataggio
+        let name = file_name("/../../{date}/");
+        assert_eq!(name.render(1, 1, 1), PathBuf::from("2023-06-30.png"));
+    }
+
+    #[test]
+    fn create_never_overwrites() {
+        let dir = temp_dir("naming_suffix");
+
+        let name = file_name("{date}/shot");
+        let (first, _) = name.create(&dir, 10, 10).unwrap();
+        let (second, _) = name.create(&dir, 10, 10).unwrap();
+        let (third, _) = name.create(&dir, 10, 10).unwrap();
+        assert_eq!(first, dir.join("2023-06-30/shot.png"));
+        assert_eq!(second, dir.join("2023-06-30/shot_1.png"));
+        assert_eq!(third, dir.join("2023-06-30/shot_2.png"));
+
+        let name = file_name("shot_{counter}");
+        std::fs::write(dir.join("shot_001.png"), []).unwrap();
+        let (path, _) = name.create(&dir, 10, 10).unwrap();
+        assert_eq!(path, dir.join("shot_002.png"));
+
+        let name = file_name("{counter}/shot");
+        std::fs::create_dir_all(dir.join("001")).unwrap();
+        std::fs::write(dir.join("001/shot.png"), []).unwrap();
+        let (path, _) = name.create(&dir, 10, 10).unwrap();
+        assert_eq!(path, dir.join("002/shot.png"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
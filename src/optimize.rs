@@ -0,0 +1,490 @@
+//! Ottimizzazione senza perdita dei Png già codificati.
+//!
+//! L'immagine viene decodificata e ricodificata provando le rappresentazioni
+//! più compatte che ne conservano esattamente i pixel: palette se usa al
+//! massimo 256 colori, scala di grigi, profondità ridotta a 1, 2 o 4 bit e
+//! nessun canale alfa se è completamente opaca. Per ognuna vengono provati
+//! tutti i filtri con la compressione veloce, e solo la combinazione più
+//! piccola viene ricodificata con la compressione migliore. I chunk di testo,
+//! il profilo colore, la risoluzione e l'Exif vengono conservati.
+
+use crate::{Error, Result};
+use image::error::{DecodingError, EncodingError, ImageFormatHint};
+use png::{AdaptiveFilterType, BitDepth, ColorType, Compression, FilterType, Transformations};
+use std::collections::{HashMap, HashSet};
+
+/// Filtri provati su ogni rappresentazione; None indica il filtro adattivo
+const FILTERS: [Option<FilterType>; 6] = [
+    Some(FilterType::NoFilter),
+    Some(FilterType::Sub),
+    Some(FilterType::Up),
+    Some(FilterType::Avg),
+    Some(FilterType::Paeth),
+    None,
+];
+
+/// Chunk copiati così come sono dal Png originale: profilo e spazio colore,
+/// risoluzione di stampa ed Exif
+const KEPT_CHUNKS: [&[u8; 4]; 6] = [b"iCCP", b"sRGB", b"gAMA", b"cHRM", b"pHYs", b"eXIf"];
+
+/// Risultato dell'ottimizzazione
+#[derive(Debug, Clone)]
+pub struct Optimized {
+    /// Png ottimizzato, o quello originale se non è stato possibile ridurlo
+    pub data: Vec<u8>,
+    /// Dimensione del Png originale in byte
+    pub original_size: usize,
+}
+
+impl Optimized {
+    /// Byte risparmiati rispetto al Png originale
+    pub fn saved(&self) -> usize {
+        self.original_size - self.data.len()
+    }
+}
+
+/// Una possibile codifica dell'immagine, con le righe già impacchettate
+struct Candidate {
+    color: ColorType,
+    depth: BitDepth,
+    palette: Option<Vec<u8>>,
+    trns: Option<Vec<u8>>,
+    data: Vec<u8>,
+}
+
+/// Chunk di testo del Png originale da copiare in quello ottimizzato
+#[derive(Default)]
+struct Texts {
+    latin1: Vec<(String, String)>,
+    compressed: Vec<(String, String)>,
+    utf8: Vec<(String, String)>,
+}
+
+/// Ottimizza il Png contenuto in data. I Png a 16 bit e quelli animati
+/// vengono restituiti invariati, come quelli che non si riescono a ridurre.
+pub fn optimize_png(data: &[u8]) -> Result<Optimized> {
+    let unchanged = || Optimized {
+        data: data.to_vec(),
+        original_size: data.len(),
+    };
+
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(decoding_error)?;
+    let info = reader.info();
+    if info.bit_depth == BitDepth::Sixteen || info.animation_control().is_some() {
+        return Ok(unchanged());
+    }
+    let (width, height) = (info.width, info.height);
+    let texts = read_texts(info)?;
+
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let output = reader.next_frame(&mut buf).map_err(decoding_error)?;
+    let pixels = to_rgba(&buf, output.color_type, output.line_size, width, height);
+
+    //La compressione migliore è lenta, quindi la dimensione ottenuta con
+    //quella veloce basta a scegliere rappresentazione e filtro
+    let candidates = candidates(&pixels, width as usize);
+    let mut best: Option<(usize, &Candidate, Option<FilterType>)> = None;
+    for candidate in &candidates {
+        for filter in FILTERS {
+            let size = encode(candidate, width, height, filter, Compression::Fast, &texts)?.len();
+            if best.is_none_or(|(b, _, _)| size < b) {
+                best = Some((size, candidate, filter));
+            }
+        }
+    }
+    let best = match best {
+        Some((_, candidate, filter)) => {
+            let encoded = encode(candidate, width, height, filter, Compression::Best, &texts)?;
+            Some(with_chunks(encoded, &kept_chunks(data)))
+        }
+        None => None,
+    };
+
+    match best {
+        Some(best) if best.len() < data.len() => Ok(Optimized {
+            data: best,
+            original_size: data.len(),
+        }),
+        _ => Ok(unchanged()),
+    }
+}
+
+/// Chunk di KEPT_CHUNKS presenti in data, completi di lunghezza e crc
+fn kept_chunks(data: &[u8]) -> Vec<&[u8]> {
+    let mut chunks = Vec::new();
+    //Firma del Png
+    let mut pos = 8;
+    while pos + 12 <= data.len() {
+        let length = u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]);
+        let end = pos + 12 + length as usize;
+        if end > data.len() {
+            break;
+        }
+        let kind = &data[pos + 4..pos + 8];
+        if KEPT_CHUNKS.iter().any(|k| &k[..] == kind) {
+            chunks.push(&data[pos..end]);
+        }
+        pos = end;
+    }
+    chunks
+}
+
+/// Inserisce i chunk subito dopo l'IHDR, che è sempre il primo: così
+/// precedono PLTE e IDAT come richiesto dalla specifica
+fn with_chunks(png: Vec<u8>, chunks: &[&[u8]]) -> Vec<u8> {
+    //Firma di 8 byte e IHDR con 13 byte di dati
+    const IHDR_END: usize = 8 + 12 + 13;
+    if chunks.is_empty() || png.len() < IHDR_END {
+        return png;
+    }
+    let mut out = Vec::with_capacity(png.len() + chunks.iter().map(|c| c.len()).sum::<usize>());
+    out.extend_from_slice(&png[..IHDR_END]);
+    for chunk in chunks {
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&png[IHDR_END..]);
+    out
+}
+
+fn read_texts(info: &png::Info) -> Result<Texts> {
+    let mut texts = Texts::default();
+    for t in &info.uncompressed_latin1_text {
+        texts.latin1.push((t.keyword.clone(), t.text.clone()));
+    }
+    for t in &info.compressed_latin1_text {
+        let mut t = t.clone();
+        t.decompress_text().map_err(decoding_error)?;
+        let text = t.get_text().map_err(decoding_error)?;
+        texts.compressed.push((t.keyword, text));
+    }
+    for t in &info.utf8_text {
+        let mut t = t.clone();
+        t.decompress_text().map_err(decoding_error)?;
+        let text = t.get_text().map_err(decoding_error)?;
+        texts.utf8.push((t.keyword, text));
+    }
+    Ok(texts)
+}
+
+/// Converte le righe decodificate, già espanse a 8 bit, in pixel Rgba
+fn to_rgba(
+    buf: &[u8],
+    color: ColorType,
+    line_size: usize,
+    width: u32,
+    height: u32,
+) -> Vec<[u8; 4]> {
+    let samples = color.samples();
+    let mut pixels = Vec::with_capacity(width as usize * height as usize);
+    for line in buf.chunks(line_size).take(height as usize) {
+        for p in line[..width as usize * samples].chunks(samples) {
+            pixels.push(match color {
+                ColorType::Grayscale => [p[0], p[0], p[0], 255],
+                ColorType::GrayscaleAlpha => [p[0], p[0], p[0], p[1]],
+                ColorType::Rgb => [p[0], p[1], p[2], 255],
+                _ => [p[0], p[1], p[2], p[3]],
+            });
+        }
+    }
+    pixels
+}
+
+/// Elenca le rappresentazioni che conservano esattamente i pixel
+fn candidates(pixels: &[[u8; 4]], width: usize) -> Vec<Candidate> {
+    let opaque = pixels.iter().all(|p| p[3] == 255);
+    let gray = pixels.iter().all(|p| p[0] == p[1] && p[1] == p[2]);
+    let mut candidates = Vec::new();
+
+    if let Some(candidate) = indexed(pixels, width) {
+        candidates.push(candidate);
+    }
+
+    let (color, samples): (ColorType, &[usize]) = match (gray, opaque) {
+        (true, true) => (ColorType::Grayscale, &[0]),
+        (true, false) => (ColorType::GrayscaleAlpha, &[0, 3]),
+        (false, true) => (ColorType::Rgb, &[0, 1, 2]),
+        (false, false) => (ColorType::Rgba, &[0, 1, 2, 3]),
+    };
+    if color == ColorType::Grayscale {
+        //Valori come 0 e 255 si possono rappresentare con meno bit
+        let depth = [BitDepth::One, BitDepth::Two, BitDepth::Four]
+            .into_iter()
+            .find(|&d| {
+                let step = 255 / ((1u16 << d as u8) - 1) as u8;
+                pixels.iter().all(|p| p[0] % step == 0)
+            })
+            .unwrap_or(BitDepth::Eight);
+        let step = 255 / ((1u16 << depth as u8) - 1) as u8;
+        let values: Vec<u8> = pixels.iter().map(|p| p[0] / step).collect();
+        candidates.push(Candidate {
+            color,
+            depth,
+            palette: None,
+            trns: None,
+            data: pack(&values, width, depth),
+        });
+    } else {
+        let data = pixels
+            .iter()
+            .flat_map(|p| samples.iter().map(move |&i| p[i]))
+            .collect();
+        candidates.push(Candidate {
+            color,
+            depth: BitDepth::Eight,
+            palette: None,
+            trns: None,
+            data,
+        });
+    }
+    candidates
+}
+
+/// Rappresentazione con palette, se l'immagine ha al massimo 256 colori.
+/// I colori trasparenti vanno in testa alla palette, così il chunk tRNS
+/// contiene solo quelli.
+fn indexed(pixels: &[[u8; 4]], width: usize) -> Option<Candidate> {
+    let mut seen = HashSet::new();
+    for p in pixels {
+        if seen.insert(*p) && seen.len() > 256 {
+            return None;
+        }
+    }
+    let mut colors: Vec<[u8; 4]> = seen.into_iter().collect();
+    colors.sort_by_key(|c| (c[3] == 255, *c));
+
+    let index: HashMap<[u8; 4], u8> = colors
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (*c, i as u8))
+        .collect();
+    let depth = match colors.len() {
+        0..=2 => BitDepth::One,
+        3..=4 => BitDepth::Two,
+        5..=16 => BitDepth::Four,
+        _ => BitDepth::Eight,
+    };
+    let values: Vec<u8> = pixels.iter().map(|p| index[p]).collect();
+
+    let palette = colors.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+    let trns: Vec<u8> = colors
+        .iter()
+        .take_while(|c| c[3] != 255)
+        .map(|c| c[3])
+        .collect();
+    Some(Candidate {
+        color: ColorType::Indexed,
+        depth,
+        palette: Some(palette),
+        trns: (!trns.is_empty()).then_some(trns),
+        data: pack(&values, width, depth),
+    })
+}
+
+/// Impacchetta valori da un campione per pixel nella profondità indicata.
+/// Ogni riga inizia su un nuovo byte.
+fn pack(values: &[u8], width: usize, depth: BitDepth) -> Vec<u8> {
+    let bits = depth as usize;
+    if bits == 8 {
+        return values.to_vec();
+    }
+    let per_byte = 8 / bits;
+    let mut data = Vec::with_capacity(values.len() / per_byte + values.len() / width + 1);
+    for row in values.chunks(width) {
+        for chunk in row.chunks(per_byte) {
+            let mut byte = 0u8;
+            for (i, v) in chunk.iter().enumerate() {
+                byte |= v << (8 - bits * (i + 1));
+            }
+            data.push(byte);
+        }
+    }
+    data
+}
+
+fn encode(
+    candidate: &Candidate,
+    width: u32,
+    height: u32,
+    filter: Option<FilterType>,
+    compression: Compression,
+    texts: &Texts,
+) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, width, height);
+    encoder.set_color(candidate.color);
+    encoder.set_depth(candidate.depth);
+    encoder.set_compression(compression);
+    match filter {
+        Some(filter) => encoder.set_filter(filter),
+        None => encoder.set_adaptive_filter(AdaptiveFilterType::Adaptive),
+    }
+    if let Some(palette) = &candidate.palette {
+        encoder.set_palette(palette.as_slice());
+    }
+    if let Some(trns) = &candidate.trns {
+        encoder.set_trns(trns.as_slice());
+    }
+    for (keyword, text) in &texts.latin1 {
+        encoder
+            .add_text_chunk(keyword.clone(), text.clone())
+            .map_err(encoding_error)?;
+    }
+    for (keyword, text) in &texts.compressed {
+        encoder
+            .add_ztxt_chunk(keyword.clone(), text.clone())
+            .map_err(encoding_error)?;
+    }
+    for (keyword, text) in &texts.utf8 {
+        encoder
+            .add_itxt_chunk(keyword.clone(), text.clone())
+            .map_err(encoding_error)?;
+    }
+
+    let mut writer = encoder.write_header().map_err(encoding_error)?;
+    writer
+        .write_image_data(&candidate.data)
+        .map_err(encoding_error)?;
+    writer.finish().map_err(encoding_error)?;
+    Ok(out)
+}
+
+fn decoding_error(e: png::DecodingError) -> Error {
+    image::ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Exact(image::ImageFormat::Png),
+        e,
+    ))
+    .into()
+}
+
+fn encoding_error(e: png::EncodingError) -> Error {
+    image::ImageError::Encoding(EncodingError::new(
+        ImageFormatHint::Exact(image::ImageFormat::Png),
+        e,
+    ))
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    fn encode_rgba(image: &RgbaImage) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        image.write_to(&mut buf, image::ImageFormat::Png).unwrap();
+        buf.into_inner()
+    }
+
+    /// L'immagine ottimizzata deve avere esattamente gli stessi pixel
+    fn assert_lossless(original: &[u8], optimized: &Optimized) {
+        let before = image::load_from_memory(original).unwrap().to_rgba8();
+        let after = image::load_from_memory(&optimized.data).unwrap().to_rgba8();
+        assert_eq!(before, after);
+        assert_eq!(optimized.original_size, original.len());
+        assert_eq!(optimized.saved(), original.len() - optimized.data.len());
+    }
+
+    #[test]
+    fn optimize_fixtures() {
+        for fixture in [
+            "tests/screenshot.png",
+            "tests/annotated_screenshot_rectangle.png",
+        ] {
+            let image = image::open(fixture).unwrap().to_rgba8();
+            let original = encode_rgba(&image);
+            let optimized = optimize_png(&original).unwrap();
+            assert_lossless(&original, &optimized);
+            assert!(optimized.data.len() <= original.len());
+        }
+    }
+
+    #[test]
+    fn optimize_reduces_color_type() {
+        //Pochi colori con un pixel semitrasparente: palette a 2 bit con tRNS
+        let mut image = RgbaImage::from_pixel(64, 64, Rgba([255, 0, 0, 255]));
+        image.put_pixel(3, 3, Rgba([0, 0, 255, 128]));
+        image.put_pixel(4, 4, Rgba([0, 255, 0, 255]));
+        let original = encode_rgba(&image);
+        let optimized = optimize_png(&original).unwrap();
+        assert_lossless(&original, &optimized);
+        assert!(optimized.saved() > 0);
+        let info = png::Decoder::new(optimized.data.as_slice())
+            .read_info()
+            .unwrap();
+        assert_eq!(info.info().color_type, ColorType::Indexed);
+        assert_eq!(info.info().bit_depth, BitDepth::Two);
+
+        //Una scacchiera in bianco e nero si riduce a un bit per pixel
+        let image = RgbaImage::from_fn(33, 7, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let original = encode_rgba(&image);
+        let optimized = optimize_png(&original).unwrap();
+        assert_lossless(&original, &optimized);
+        let info = png::Decoder::new(optimized.data.as_slice())
+            .read_info()
+            .unwrap();
+        assert_eq!(info.info().bit_depth, BitDepth::One);
+    }
+
+    #[test]
+    fn optimize_keeps_text_chunks() {
+        let image = RgbaImage::from_pixel(8, 8, Rgba([1, 2, 3, 255]));
+        let mut original = Vec::new();
+        let mut encoder = png::Encoder::new(&mut original, 8, 8);
+        encoder.set_color(ColorType::Rgba);
+        encoder
+            .add_itxt_chunk(String::from("Comment"), String::from("così"))
+            .unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(image.as_raw()).unwrap();
+        writer.finish().unwrap();
+
+        let optimized = optimize_png(&original).unwrap();
+        let reader = png::Decoder::new(optimized.data.as_slice())
+            .read_info()
+            .unwrap();
+        let text = &reader.info().utf8_text[0];
+        assert_eq!(text.keyword, "Comment");
+        assert_eq!(text.get_text().unwrap(), "così");
+    }
+
+    #[test]
+    fn optimize_keeps_metadata_chunks() {
+        let image = RgbaImage::from_pixel(8, 8, Rgba([1, 2, 3, 255]));
+        //3780 pixel per metro, circa 96 dpi, ed Exif di prova
+        let mut phys = Vec::new();
+        phys.extend_from_slice(&3780u32.to_be_bytes());
+        phys.extend_from_slice(&3780u32.to_be_bytes());
+        phys.push(1);
+        let exif = b"MM\0*\0\0\0\x08\0\0";
+        let mut original = Vec::new();
+        let mut encoder = png::Encoder::new(&mut original, 8, 8);
+        encoder.set_color(ColorType::Rgba);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_chunk(png::chunk::pHYs, &phys).unwrap();
+        writer.write_chunk(png::chunk::ChunkType(*b"eXIf"), exif).unwrap();
+        writer.write_image_data(image.as_raw()).unwrap();
+        writer.finish().unwrap();
+
+        let optimized = optimize_png(&original).unwrap();
+        assert_lossless(&original, &optimized);
+        let chunks = kept_chunks(&optimized.data);
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(&chunks[0][8..chunks[0].len() - 4], phys.as_slice());
+        assert_eq!(&chunks[1][8..chunks[1].len() - 4], exif);
+
+        let reader = png::Decoder::new(optimized.data.as_slice())
+            .read_info()
+            .unwrap();
+        let dims = reader.info().pixel_dims.unwrap();
+        assert_eq!((dims.xppu, dims.yppu), (3780, 3780));
+    }
+}
@@ -0,0 +1,680 @@
+//! Esportazione in PDF, senza programmi esterni.
+//!
+//! Lo screenshot ritagliato viene incluso alla sua risoluzione come immagine
+//! compressa senza perdita, e le annotazioni vengono disegnate sopra come
+//! tracciati vettoriali. I testi usano Helvetica, uno dei font standard che
+//! ogni lettore PDF possiede, quindi nel file non viene incluso alcun font:
+//! il font scelto nell'app viene ignorato e per i caratteri fuori da
+//! WinAnsi viene scritto un punto interrogativo.
+
+use crate::annotations::{Annotation, FillStyle, TextFont};
+use crate::metadata::Crop;
+use crate::svg::{arrow_head_length, arrow_wings, SIZE_SCALE};
+use crate::{Error, Result};
+use chrono::{DateTime, Datelike, FixedOffset, Timelike};
+use image::{imageops, RgbaImage};
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use pdf_writer::types::{LineCapStyle, LineJoinStyle};
+use pdf_writer::{Content, Date, Filter, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+use std::path::Path;
+
+/// Margine intorno all'immagine sui fogli A4 e Letter, in punti
+const MARGIN: f32 = 36.0;
+/// Margine intorno all'intestazione sulle pagine grandi quanto l'immagine
+const HEADER_PADDING: f32 = 12.0;
+const TITLE_SIZE: f32 = 16.0;
+const TIMESTAMP_SIZE: f32 = 10.0;
+/// Distanza tra le righe di testo rispetto alla dimensione del font
+const LINE_HEIGHT: f32 = 1.2;
+/// Altezza delle maiuscole di Helvetica, per centrare i testi in verticale
+const CAP_HEIGHT: f32 = 0.718;
+/// Distanza dei punti di controllo delle curve di Bézier che approssimano
+/// un quarto di ellisse
+const KAPPA: f32 = 0.552_284_8;
+
+/// Larghezze di Helvetica e Helvetica-Bold per i caratteri da ' ' a '~', in
+/// millesimi della dimensione del font
+#[rustfmt::skip]
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556,
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556,
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556,
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584,
+];
+#[rustfmt::skip]
+const HELVETICA_BOLD_WIDTHS: [u16; 95] = [
+    278, 333, 474, 556, 556, 889, 722, 238, 333, 333, 389, 584, 278, 333, 278, 278,
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 333, 333, 584, 584, 584, 611,
+    975, 722, 722, 722, 722, 667, 611, 778, 722, 278, 556, 722, 611, 833, 722, 778,
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 333, 278, 333, 584, 556,
+    333, 556, 611, 556, 611, 556, 333, 611, 611, 278, 278, 556, 278, 889, 611, 611,
+    611, 611, 389, 556, 333, 611, 556, 778, 556, 556, 500, 389, 280, 389, 584,
+];
+/// Larghezza usata per i caratteri fuori dalle tabelle
+const DEFAULT_WIDTH: u16 = 556;
+
+/// Dimensione delle pagine del PDF
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    /// Pagina grande quanto lo screenshot, un pixel per punto
+    #[default]
+    Image,
+    A4,
+    Letter,
+}
+
+impl PageSize {
+    pub const ALL: [PageSize; 3] = [PageSize::Image, PageSize::A4, PageSize::Letter];
+
+    /// Dimensione del foglio verticale in punti, None se dipende dall'immagine
+    fn paper(self) -> Option<(f32, f32)> {
+        match self {
+            PageSize::Image => None,
+            PageSize::A4 => Some((595.28, 841.89)),
+            PageSize::Letter => Some((612.0, 792.0)),
+        }
+    }
+}
+
+impl std::fmt::Display for PageSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            PageSize::Image => "Image size",
+            PageSize::A4 => "A4",
+            PageSize::Letter => "Letter",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Impostazioni dell'esportazione in PDF
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PdfOptions {
+    pub page_size: PageSize,
+    /// Se scrivere sopra lo screenshot l'istante della cattura
+    pub timestamp: bool,
+}
+
+impl Default for PdfOptions {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::default(),
+            timestamp: true,
+        }
+    }
+}
+
+/// Genera il PDF dello screenshot con le sue annotazioni, con un'eventuale
+/// intestazione. image è lo screenshot originale, senza crop.
+pub fn to_pdf(
+    image: &RgbaImage,
+    annotations: &[Annotation],
+    crop: Option<Crop>,
+    title: Option<&str>,
+    captured_at: Option<DateTime<FixedOffset>>,
+    options: &PdfOptions,
+) -> Result<Vec<u8>> {
+    let crop = crop.unwrap_or(Crop {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    });
+    if crop.width == 0 || crop.height == 0 {
+        return Err(Error::Render(String::from("the screenshot is empty")));
+    }
+    let mut original = image.clone();
+    let visible = imageops::crop(&mut original, crop.x, crop.y, crop.width, crop.height).to_image();
+
+    //Intestazione, dall'alto verso il basso
+    let mut header = Vec::new();
+    if let Some(title) = title.map(str::trim).filter(|t| !t.is_empty()) {
+        header.push((title.to_string(), TITLE_SIZE, true));
+    }
+    if let Some(time) = captured_at.filter(|_| options.timestamp) {
+        let time = time.format("Captured %Y-%m-%d %H:%M:%S %:z").to_string();
+        header.push((time, TIMESTAMP_SIZE, false));
+    }
+    let header_height: f32 = header.iter().map(|(_, size, _)| size * LINE_HEIGHT).sum();
+
+    let (width, height) = (visible.width() as f32, visible.height() as f32);
+    let (page_width, page_height, scale, left, top, header_left) = match options.page_size.paper() {
+        None => {
+            let padding = if header.is_empty() {
+                0.0
+            } else {
+                HEADER_PADDING
+            };
+            let top = header_height + 2.0 * padding;
+            (width, height + top, 1.0, 0.0, top, padding)
+        }
+        Some((short, long)) => {
+            //Gli screenshot più larghi che alti vanno su un foglio orizzontale
+            let (page_width, page_height) = if width > height {
+                (long, short)
+            } else {
+                (short, long)
+            };
+            let spacing = if header.is_empty() { 0.0 } else { MARGIN / 2.0 };
+            let available_width = page_width - 2.0 * MARGIN;
+            let available_height = page_height - 2.0 * MARGIN - header_height - spacing;
+            //Le immagini piccole non vengono ingrandite per non perdere nitidezza
+            let scale = (available_width / width)
+                .min(available_height / height)
+                .min(1.0);
+            let left = (page_width - width * scale) / 2.0;
+            let top = MARGIN + header_height + spacing;
+            (page_width, page_height, scale, left, top, MARGIN)
+        }
+    };
+
+    let catalog_id = Ref::new(1);
+    let pages_id = Ref::new(2);
+    let page_id = Ref::new(3);
+    let content_id = Ref::new(4);
+    let image_id = Ref::new(5);
+    let mask_id = Ref::new(6);
+    let font_id = Ref::new(7);
+    let bold_font_id = Ref::new(8);
+    let info_id = Ref::new(9);
+    let mut next_id = Ref::new(10);
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(pages_id);
+    pdf.pages(pages_id).kids([page_id]).count(1);
+
+    let mut content = Content::new();
+    let mut y = page_height
+        - if options.page_size == PageSize::Image {
+            HEADER_PADDING
+        } else {
+            MARGIN
+        };
+    for (text, size, bold) in &header {
+        y -= size * LINE_HEIGHT;
+        content.begin_text();
+        content.set_fill_gray(0.0);
+        content.set_font(font_name(*bold), *size);
+        content.set_text_matrix([
+            1.0,
+            0.0,
+            0.0,
+            1.0,
+            header_left,
+            y + size * (LINE_HEIGHT - 1.0),
+        ]);
+        content.show(Str(&win_ansi(text)));
+        content.end_text();
+    }
+
+    //Da qui in poi le coordinate sono quelle dell'immagine, con l'asse y verso il basso
+    content.save_state();
+    content.transform([scale, 0.0, 0.0, -scale, left, page_height - top]);
+    content.rect(0.0, 0.0, width, height);
+    content.clip_nonzero();
+    content.end_path();
+    content.save_state();
+    content.transform([width, 0.0, 0.0, -height, 0.0, height]);
+    content.x_object(Name(b"Im0"));
+    content.restore_state();
+
+    content.transform([1.0, 0.0, 0.0, 1.0, -(crop.x as f32), -(crop.y as f32)]);
+    content.set_line_cap(LineCapStyle::RoundCap);
+    content.set_line_join(LineJoinStyle::RoundJoin);
+    let mut alphas: Vec<f32> = Vec::new();
+    let mut images: Vec<&RgbaImage> = Vec::new();
+    for annotation in annotations {
+        draw_annotation(
+            &mut content,
+            annotation,
+            arrow_head_length(image.width() as f32, image.height() as f32),
+            &mut alphas,
+            &mut images,
+        );
+    }
+    content.restore_state();
+    let content = content.finish();
+
+    let mut page = pdf.page(page_id);
+    page.parent(pages_id)
+        .media_box(Rect::new(0.0, 0.0, page_width, page_height))
+        .contents(content_id);
+    let mut resources = page.resources();
+    //Ogni immagine incollata ha il suo XObject e la sua eventuale maschera
+    let image_ids: Vec<(Ref, Ref)> = images
+        .iter()
+        .map(|_| (next_id.bump(), next_id.bump()))
+        .collect();
+    let mut x_objects = resources.x_objects();
+    x_objects.pair(Name(b"Im0"), image_id);
+    for (i, (id, _)) in image_ids.iter().enumerate() {
+        x_objects.pair(Name(format!("Im{}", i + 1).as_bytes()), *id);
+    }
+    x_objects.finish();
+    resources
+        .fonts()
+        .pair(Name(b"F0"), font_id)
+        .pair(Name(b"F1"), bold_font_id);
+    let mut states = resources.ext_g_states();
+    let state_ids: Vec<Ref> = alphas.iter().map(|_| next_id.bump()).collect();
+    for (i, id) in state_ids.iter().enumerate() {
+        states.pair(Name(format!("GS{}", i).as_bytes()), *id);
+    }
+    states.finish();
+    resources.finish();
+    page.finish();
+
+    pdf.stream(content_id, &content);
+    for (alpha, id) in alphas.iter().zip(state_ids) {
+        pdf.ext_graphics(id)
+            .stroking_alpha(*alpha)
+            .non_stroking_alpha(*alpha);
+    }
+
+    write_image(&mut pdf, image_id, mask_id, &visible);
+    for (image, (id, mask_id)) in images.iter().zip(image_ids) {
+        write_image(&mut pdf, id, mask_id, image);
+    }
+
+    for (id, base) in [(font_id, "Helvetica"), (bold_font_id, "Helvetica-Bold")] {
+        pdf.type1_font(id)
+            .base_font(Name(base.as_bytes()))
+            .encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    let producer = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let mut info = pdf.document_info(info_id);
+    info.producer(TextStr(&producer));
+    if let Some((title, _, _)) = header.iter().find(|(_, _, bold)| *bold) {
+        info.title(TextStr(title));
+    }
+    if let Some(time) = captured_at {
+        info.creation_date(pdf_date(time));
+    }
+    info.finish();
+
+    Ok(pdf.finish())
+}
+
+/// Come [`to_pdf`], scrivendo il documento in path
+pub fn save_pdf(
+    image: &RgbaImage,
+    annotations: &[Annotation],
+    crop: Option<Crop>,
+    title: Option<&str>,
+    captured_at: Option<DateTime<FixedOffset>>,
+    options: &PdfOptions,
+    path: &Path,
+) -> Result<()> {
+    let pdf = to_pdf(image, annotations, crop, title, captured_at, options)?;
+    std::fs::write(path, pdf).map_err(|e| Error::io(path, e))
+}
+
+/// Scrive image come XObject. I pixel vengono compressi senza perdita,
+/// l'alpha va in una maschera separata con id mask_id se non è tutto opaco.
+fn write_image(pdf: &mut Pdf, id: Ref, mask_id: Ref, image: &RgbaImage) {
+    let rgb: Vec<u8> = image.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect();
+    let rgb = compress_to_vec_zlib(&rgb, 6);
+    let opaque = image.pixels().all(|p| p[3] == 255);
+    let mut xobject = pdf.image_xobject(id, &rgb);
+    xobject.filter(Filter::FlateDecode);
+    xobject
+        .width(image.width() as i32)
+        .height(image.height() as i32)
+        .color_space_name(Name(b"DeviceRGB"))
+        .bits_per_component(8);
+    if !opaque {
+        xobject.s_mask(mask_id);
+    }
+    xobject.finish();
+    if !opaque {
+        let alpha: Vec<u8> = image.pixels().map(|p| p[3]).collect();
+        let alpha = compress_to_vec_zlib(&alpha, 6);
+        let mut mask = pdf.image_xobject(mask_id, &alpha);
+        mask.filter(Filter::FlateDecode);
+        mask.width(image.width() as i32)
+            .height(image.height() as i32)
+            .color_space_name(Name(b"DeviceGray"))
+            .bits_per_component(8);
+    }
+}
+
+/// Disegna l'annotazione. Le immagini incollate vengono aggiunte a images e
+/// disegnate come XObject Im1, Im2, ... nello stesso ordine.
+fn draw_annotation<'a>(
+    content: &mut Content,
+    annotation: &'a Annotation,
+    arrow_head: f32,
+    alphas: &mut Vec<f32>,
+    images: &mut Vec<&'a RgbaImage>,
+) {
+    content.save_state();
+    match annotation {
+        Annotation::Rectangle {
+            color,
+            fill_style,
+            x,
+            y,
+            width,
+            height,
+        } => {
+            set_paint(content, color, fill_style, alphas);
+            content.rect(*x, *y, *width, *height);
+            finish_shape(content, fill_style);
+        }
+        Annotation::Ellipse {
+            color,
+            fill_style,
+            x,
+            y,
+            width,
+            height,
+        } => {
+            set_paint(content, color, fill_style, alphas);
+            let (rx, ry) = (width.abs() / 2.0, height.abs() / 2.0);
+            let (cx, cy) = (x + width / 2.0, y + height / 2.0);
+            let (ox, oy) = (rx * KAPPA, ry * KAPPA);
+            content.move_to(cx + rx, cy);
+            content.cubic_to(cx + rx, cy + oy, cx + ox, cy + ry, cx, cy + ry);
+            content.cubic_to(cx - ox, cy + ry, cx - rx, cy + oy, cx - rx, cy);
+            content.cubic_to(cx - rx, cy - oy, cx - ox, cy - ry, cx, cy - ry);
+            content.cubic_to(cx + ox, cy - ry, cx + rx, cy - oy, cx + rx, cy);
+            content.close_path();
+            finish_shape(content, fill_style);
+        }
+        Annotation::Arrow {
+            color,
+            stroke_width,
+            begin,
+            end,
+        } => {
+            set_paint(content, color, &FillStyle::Stroke(*stroke_width), alphas);
+            content.move_to(begin[0], begin[1]);
+            content.line_to(end[0], end[1]);
+            if let Some([upper, lower]) = arrow_wings(*begin, *end, arrow_head) {
+                content.line_to(upper[0], upper[1]);
+                content.move_to(end[0], end[1]);
+                content.line_to(lower[0], lower[1]);
+            }
+            content.stroke();
+        }
+        Annotation::Text {
+            color,
+            content: text,
+            size,
+            x,
+            y,
+            font,
+        } => {
+            set_paint(content, color, &FillStyle::Fill, alphas);
+            draw_text(content, text, size * SIZE_SCALE, *x, *y, font);
+        }
+        Annotation::FreeHand {
+            color,
+            stroke_width,
+            points,
+        } => {
+            if let Some((first, rest)) = points.split_first() {
+                set_paint(content, color, &FillStyle::Stroke(*stroke_width), alphas);
+                content.move_to(first[0], first[1]);
+                for p in rest {
+                    content.line_to(p[0], p[1]);
+                }
+                content.stroke();
+            }
+        }
+        Annotation::Image {
+            x,
+            y,
+            width,
+            height,
+            image,
+            opacity,
+        } => {
+            set_alpha(content, opacity.clamp(0.0, 1.0), alphas);
+            images.push(image);
+            //Come per lo screenshot, la matrice raddrizza l'immagine
+            content.transform([*width, 0.0, 0.0, -height, *x, y + height]);
+            content.x_object(Name(format!("Im{}", images.len()).as_bytes()));
+        }
+    }
+    content.restore_state();
+}
+
+/// Scrive un testo centrato in (x, y), con una riga per ogni a capo
+fn draw_text(content: &mut Content, text: &str, size: f32, x: f32, y: f32, font: &TextFont) {
+    let bold = font.weight.css_weight() >= 600;
+    let lines: Vec<&str> = text.lines().collect();
+    let first = y - (lines.len().max(1) - 1) as f32 * size * LINE_HEIGHT / 2.0;
+    content.begin_text();
+    content.set_font(font_name(bold), size);
+    for (i, line) in lines.iter().enumerate() {
+        let line = win_ansi(line);
+        let baseline = first + i as f32 * size * LINE_HEIGHT + size * CAP_HEIGHT / 2.0;
+        //La y della pagina è rovesciata, la matrice raddrizza i caratteri
+        content.set_text_matrix([
+            1.0,
+            0.0,
+            0.0,
+            -1.0,
+            x - text_width(&line, size, bold) / 2.0,
+            baseline,
+        ]);
+        content.show(Str(&line));
+    }
+    content.end_text();
+}
+
+/// Imposta colore, spessore e trasparenza della forma che sta per essere
+/// disegnata
+fn set_paint(
+    content: &mut Content,
+    color: &[f32; 4],
+    fill_style: &FillStyle,
+    alphas: &mut Vec<f32>,
+) {
+    let [r, g, b, a] = color.map(|c| c.clamp(0.0, 1.0));
+    match fill_style {
+        FillStyle::Fill => {
+            content.set_fill_rgb(r, g, b);
+        }
+        FillStyle::Stroke(width) => {
+            content.set_stroke_rgb(r, g, b);
+            content.set_line_width(width * SIZE_SCALE);
+        }
+    }
+    set_alpha(content, a, alphas);
+}
+
+/// Imposta la trasparenza di ciò che sta per essere disegnato, immagini
+/// comprese, riusando gli stati grafici con lo stesso valore
+fn set_alpha(content: &mut Content, alpha: f32, alphas: &mut Vec<f32>) {
+    if alpha < 1.0 {
+        let index = alphas.iter().position(|v| *v == alpha).unwrap_or_else(|| {
+            alphas.push(alpha);
+            alphas.len() - 1
+        });
+        content.set_parameters(Name(format!("GS{}", index).as_bytes()));
+    }
+}
+
+fn finish_shape(content: &mut Content, fill_style: &FillStyle) {
+    match fill_style {
+        FillStyle::Fill => content.fill_nonzero(),
+        FillStyle::Stroke(_) => content.stroke(),
+    };
+}
+
+fn font_name(bold: bool) -> Name<'static> {
+    if bold {
+        Name(b"F1")
+    } else {
+        Name(b"F0")
+    }
+}
+
+/// Converte il testo nella codifica WinAnsi dei font standard. Coincide con
+/// Latin-1 tranne che tra 0x80 e 0x9F, dove viene conservato solo il simbolo
+/// dell'euro.
+fn win_ansi(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' | '\u{A0}'..='\u{FF}' => c as u8,
+            '€' => 0x80,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Larghezza in punti di un testo già codificato in WinAnsi
+fn text_width(text: &[u8], size: f32, bold: bool) -> f32 {
+    let widths = if bold {
+        &HELVETICA_BOLD_WIDTHS
+    } else {
+        &HELVETICA_WIDTHS
+    };
+    let total: u32 = text
+        .iter()
+        .map(|c| {
+            c.checked_sub(b' ')
+                .and_then(|i| widths.get(i as usize))
+                .copied()
+                .unwrap_or(DEFAULT_WIDTH) as u32
+        })
+        .sum();
+    total as f32 * size / 1000.0
+}
+
+fn pdf_date(time: DateTime<FixedOffset>) -> Date {
+    let offset = time.offset().local_minus_utc() / 60;
+    Date::new(time.year() as u16)
+        .month(time.month() as u8)
+        .day(time.day() as u8)
+        .hour(time.hour() as u8)
+        .minute(time.minute() as u8)
+        .second(time.second() as u8)
+        .utc_offset_hour((offset / 60) as i8)
+        .utc_offset_minute((offset % 60).unsigned_abs() as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::FontWeight;
+    use chrono::TimeZone;
+
+    fn annotations() -> Vec<Annotation> {
+        vec![
+            Annotation::Rectangle {
+                color: [1.0, 0.0, 0.0, 0.5],
+                fill_style: FillStyle::Stroke(0.1),
+                x: 30.0,
+                y: 20.0,
+                width: -10.0,
+                height: 5.0,
+            },
+            Annotation::Arrow {
+                color: [0.0, 1.0, 0.0, 1.0],
+                stroke_width: 0.04,
+                begin: [0.0, 0.0],
+                end: [40.0, 0.0],
+            },
+            Annotation::Text {
+                color: [0.0, 0.0, 1.0, 1.0],
+                content: String::from("Bug (qui)"),
+                size: 0.5,
+                x: 32.0,
+                y: 16.0,
+                font: TextFont {
+                    family: None,
+                    weight: FontWeight::Bold,
+                },
+            },
+        ]
+    }
+
+    fn contains(pdf: &[u8], text: &str) -> bool {
+        pdf.windows(text.len()).any(|w| w == text.as_bytes())
+    }
+
+    #[test]
+    fn pdf_native_size() {
+        let image = RgbaImage::from_pixel(64, 32, image::Rgba([40, 90, 200, 255]));
+        let mut annotations = annotations();
+        annotations.push(Annotation::Image {
+            x: 4.0,
+            y: 6.0,
+            width: 8.0,
+            height: 2.0,
+            image: std::sync::Arc::new(RgbaImage::from_pixel(4, 1, image::Rgba([0, 0, 0, 255]))),
+            opacity: 1.0,
+        });
+        let pdf = to_pdf(
+            &image,
+            &annotations,
+            None,
+            None,
+            None,
+            &PdfOptions::default(),
+        )
+        .unwrap();
+
+        assert!(pdf.starts_with(b"%PDF-"));
+        assert!(contains(&pdf, "/MediaBox [0 0 64 32]"));
+        assert!(contains(&pdf, "/Helvetica-Bold"));
+        assert!(contains(&pdf, "(Bug (qui)) Tj"));
+        assert!(contains(&pdf, "/Im1 Do"));
+        //Una sola trasparenza, quella del rettangolo, e nessuna maschera alpha
+        assert!(contains(&pdf, "/GS0 gs"));
+        assert!(!contains(&pdf, "/GS1"));
+        assert!(!contains(&pdf, "/SMask"));
+    }
+
+    #[test]
+    fn pdf_fitted_with_header() {
+        let image = RgbaImage::from_pixel(2000, 1000, image::Rgba([40, 90, 200, 128]));
+        let captured_at = FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2023, 6, 30, 14, 5, 9)
+            .unwrap();
+        let options = PdfOptions {
+            page_size: PageSize::A4,
+            timestamp: true,
+        };
+        let crop = Crop {
+            x: 0,
+            y: 0,
+            width: 500,
+            height: 1000,
+        };
+        let pdf = to_pdf(
+            &image,
+            &annotations(),
+            Some(crop),
+            Some("Login broken"),
+            Some(captured_at),
+            &options,
+        )
+        .unwrap();
+
+        //Il ritaglio è più alto che largo, quindi il foglio resta verticale
+        assert!(contains(&pdf, "/MediaBox [0 0 595.28 841.89]"));
+        assert!(contains(&pdf, "(Login broken) Tj"));
+        assert!(contains(&pdf, "(Captured 2023-06-30 14:05:09 +02:00) Tj"));
+        assert!(contains(&pdf, "/Title (Login broken)"));
+        assert!(contains(&pdf, "/CreationDate (D:20230630140509+02'00)"));
+        assert!(contains(&pdf, "/SMask"));
+    }
+
+    #[test]
+    fn text_encoding() {
+        assert_eq!(win_ansi("Però 5€ ✓"), b"Per\xf2 5\x80 ?");
+        assert_eq!(text_width(b"Hi", 10.0, false), (722.0 + 222.0) / 100.0);
+    }
+}
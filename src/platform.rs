@@ -1,11 +1,12 @@
 ///Gestisce tutte le funzionalità multipiattaforma
 
 pub mod default_path{
+    use pds_project::{Error, Result};
     use std::path::PathBuf;
 
     #[cfg(target_os = "windows")]
     ///Trovo la posizione della cartella Pictures leggendo il registro di sistema
-    pub fn take_default_path() -> PathBuf {
+    pub fn take_default_path() -> Result<PathBuf> {
         use winreg::enums::HKEY_CURRENT_USER;
         use winreg::RegKey;
 
@@ -14,19 +15,20 @@ pub mod default_path{
             .open_subkey(
                 "Software\\Microsoft\\Windows\\CurrentVersion\\Explorer\\User Shell Folders",
             )
-            .expect("Errore nella lettura del registro di sistema");
+            .map_err(|e| Error::Config(format!("cannot read the user shell folders: {}", e)))?;
         let mut pf: String = cur_ver
             .get_value("My Pictures")
-            .expect("Errore nella lettura del registro di sistema");
+            .map_err(|e| Error::Config(format!("cannot read the Pictures folder: {}", e)))?;
         if pf.contains("USERPROFILE") {
-            let user_profile = std::env::var("USERPROFILE").expect("Errore in USERPROFILE");
+            let user_profile = std::env::var("USERPROFILE")
+                .map_err(|e| Error::Config(format!("USERPROFILE: {}", e)))?;
             pf = pf.replacen("%USERPROFILE%", &user_profile, 1);
         }
-        PathBuf::from(pf)
+        Ok(PathBuf::from(pf))
     }
     #[cfg(target_os = "linux")]
     //Trovo la posizione della cartella Pictures leggendo il file user_dirs.dirs
-    pub fn take_default_path() -> PathBuf {
+    pub fn take_default_path() -> Result<PathBuf> {
         use std::fs::File;
         use std::io::{BufRead, BufReader};
         //Trovo la posizione della cartella .config leggendo le variabili d'ambienete
@@ -36,29 +38,33 @@ pub mod default_path{
         let mut p = if let Ok(p) = std::env::var("XDG_CONFIG_HOME") {
             p
         } else {
-            let mut p = home.clone().expect("Variabile d'ambiente HOME non esiste");
+            let mut p = home
+                .clone()
+                .map_err(|_| Error::Config(String::from("the HOME variable is not set")))?;
             p += "/.config";
             p
         };
         p += "/user_dirs.dirs";
 
-        let f = if let Ok(f) = File::open(p) {
+        let f = if let Ok(f) = File::open(&p) {
             let b = BufReader::new(f);
             let lines = b.lines();
             let mut ret = PathBuf::new();
             for l in lines {
-                let s = l.expect("user_dirs.dirs è mal configurato");
+                let s = l.map_err(|e| Error::io(&p, e))?;
                 if s.contains("XDG_PICTURES_DIR") {
                     //prendo solo il path della cartella
-                    let split = s
-                        .split_once("=")
-                        .expect("Variabile d'ambiente XDG_PICTURES_DIR mal configurata");
+                    let split = s.split_once("=").ok_or_else(|| {
+                        Error::Config(format!("{}: malformed XDG_PICTURES_DIR entry", p))
+                    })?;
                     ret = PathBuf::from(split.1);
                     break;
                 }
             }
             if ret == PathBuf::new(){
-                ret = PathBuf::from(home.clone().expect("Variabile d'ambiente HOME non esiste"));
+                ret = PathBuf::from(home.clone().map_err(|_| {
+                    Error::Config(String::from("the HOME variable is not set"))
+                })?);
             }
             ret
         } else if let Ok(f) = home {
@@ -66,13 +72,14 @@ pub mod default_path{
         } else {
             PathBuf::from("")
         };
-        f
+        Ok(f)
     }
     #[cfg(target_os = "macos")]
-    pub fn take_default_path() -> PathBuf {
+    pub fn take_default_path() -> Result<PathBuf> {
         let key = "HOME";
-        let p = std::env::var(key).expect("Errore nelle variabili d'ambiente");
-        PathBuf::from(p + "/pictures")
+        let p = std::env::var(key)
+            .map_err(|_| Error::Config(String::from("the HOME variable is not set")))?;
+        Ok(PathBuf::from(p + "/pictures"))
     }
 }
 
@@ -90,26 +97,26 @@ mod tests {
         key = "HOME";
         env::set_var(key, "tests");
         assert!(
-            PathBuf::from("tests") == platform::default_path::take_default_path(),
+            PathBuf::from("tests") == platform::default_path::take_default_path().unwrap(),
             "Cannot handle missing user_dirs.dirs"
         );
         //Caso in cui non trova XDG_CONFIG_HOME,trova il file user_dirs.dirs ma non trova la posizione di pictures
         env::set_var(key, "tests/platform/us_dir_nok");
         assert!(
-            PathBuf::from("tests/platform/us_dir_nok") == platform::default_path::take_default_path(),
+            PathBuf::from("tests/platform/us_dir_nok") == platform::default_path::take_default_path().unwrap(),
             "Cannot handle missing user_dirs.dirs"
         );
         //Caso in cui non trova XDG_CONFIG_HOME
         env::set_var(key, "tests/platform/us_dir_ok");
         assert!(
-            PathBuf::from("riuscito") == platform::default_path::take_default_path(),
+            PathBuf::from("riuscito") == platform::default_path::take_default_path().unwrap(),
             "Cannot handle missing XDG_CONFIG_HOME"
         );
         //Caso in cui trova XDG_CONFIG_HOME
         key = "XDG_CONFIG_HOME";
         env::set_var(key, "tests/platform/us_dir_ok/.config");
         assert!(
-            PathBuf::from("riuscito") == platform::default_path::take_default_path(),
+            PathBuf::from("riuscito") == platform::default_path::take_default_path().unwrap(),
             "Cannot handle XDG_CONFIG_HOME"
         );
     }
@@ -124,7 +131,7 @@ mod tests {
         let key = "HOME";
         env::set_var(key, "tests");
         assert!(
-            PathBuf::from("tests/pictures") == platform::default_path::take_default_path(),
+            PathBuf::from("tests/pictures") == platform::default_path::take_default_path().unwrap(),
             "Errore"
         );
     }
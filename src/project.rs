@@ -0,0 +1,256 @@
+//! File di progetto, per modificare di nuovo uno screenshot già annotato.
+//!
+//! Un progetto è un archivio zip che contiene lo screenshot originale, senza
+//! crop né annotazioni, in `original.png` e la descrizione delle modifiche in
+//! `project.json`: annotazioni, cronologia e impostazioni degli strumenti.
+//! Le annotazioni restano separate dai pixel, quindi si possono ancora
+//! annullare, eliminare e ritagliare diversamente.
+
+use crate::annotations::Annotation;
+use crate::config::ToolDefaults;
+use crate::metadata::Crop;
+use crate::{Error, Result};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Seek, Write};
+use std::path::Path;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Estensione dei file di progetto
+pub const PROJECT_EXTENSION: &str = "snipproj";
+/// Versione del formato scritta nei nuovi progetti
+pub const PROJECT_VERSION: u32 = 1;
+
+const ORIGINAL_ENTRY: &str = "original.png";
+const DESCRIPTION_ENTRY: &str = "project.json";
+
+/// Una modifica della cronologia, nell'ordine in cui può essere annullata
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HistoryStep {
+    /// È stata aggiunta un'annotazione
+    Annotate,
+    /// Lo screenshot è stato ritagliato nella zona indicata
+    Crop(Crop),
+    /// È stata eliminata un'annotazione, che si trova tra quelle eliminate
+    DeleteAnnotation,
+}
+
+/// Screenshot originale con tutte le modifiche che gli sono state applicate
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    pub original: RgbaImage,
+    /// Annotazioni disegnate, dalla più vecchia alla più recente
+    pub annotations: Vec<Annotation>,
+    /// Annotazioni eliminate che si possono ancora ripristinare, con la
+    /// posizione che avevano
+    pub deleted: Vec<(usize, Annotation)>,
+    pub history: Vec<HistoryStep>,
+    /// Impostazioni degli strumenti al momento del salvataggio
+    pub tools: ToolDefaults,
+}
+
+/// Contenuto di project.json
+#[derive(Serialize, Deserialize)]
+struct Description {
+    version: u32,
+    #[serde(default)]
+    annotations: Vec<Annotation>,
+    #[serde(default)]
+    deleted: Vec<(usize, Annotation)>,
+    #[serde(default)]
+    history: Vec<HistoryStep>,
+    #[serde(default)]
+    tools: ToolDefaults,
+}
+
+impl Project {
+    /// Zona dell'ultimo crop ancora attivo, None se lo screenshot non è ritagliato
+    pub fn crop(&self) -> Option<Crop> {
+        self.history.iter().rev().find_map(|h| match h {
+            HistoryStep::Crop(c) => Some(*c),
+            _ => None,
+        })
+    }
+
+    /// Scrive il progetto come archivio zip
+    pub fn write<W: Write + Seek>(&self, w: W) -> Result<()> {
+        let mut original = Cursor::new(Vec::new());
+        self.original
+            .write_to(&mut original, image::ImageFormat::Png)?;
+
+        let description = Description {
+            version: PROJECT_VERSION,
+            annotations: self.annotations.clone(),
+            deleted: self.deleted.clone(),
+            history: self.history.clone(),
+            tools: self.tools.clone(),
+        };
+
+        let mut zip = ZipWriter::new(w);
+        //Il png è già compresso, comprimerlo di nuovo farebbe solo perdere tempo
+        zip.start_file(
+            ORIGINAL_ENTRY,
+            FileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        zip.write_all(original.get_ref())?;
+        zip.start_file(DESCRIPTION_ENTRY, FileOptions::default())?;
+        serde_json::to_writer_pretty(&mut zip, &description)?;
+        zip.finish()?.flush()?;
+        Ok(())
+    }
+
+    /// Legge un progetto scritto da [`Project::write`], controllando che le
+    /// zone ritagliate siano dentro lo screenshot
+    pub fn read<R: Read + Seek>(r: R) -> Result<Self> {
+        let mut zip = ZipArchive::new(r)?;
+
+        let description: Description = serde_json::from_reader(zip.by_name(DESCRIPTION_ENTRY)?)?;
+        if description.version > PROJECT_VERSION {
+            return Err(Error::Project(format!(
+                "version {} was written by a newer release",
+                description.version
+            )));
+        }
+
+        let mut data = Vec::new();
+        zip.by_name(ORIGINAL_ENTRY)?.read_to_end(&mut data)?;
+        let original =
+            image::load_from_memory_with_format(&data, image::ImageFormat::Png)?.to_rgba8();
+
+        for step in &description.history {
+            if let HistoryStep::Crop(c) = step {
+                if !c.fits_in(original.width(), original.height()) {
+                    return Err(Error::Project(format!(
+                        "the crop {} is outside the {}x{} screenshot",
+                        c,
+                        original.width(),
+                        original.height()
+                    )));
+                }
+            }
+        }
+
+        Ok(Self {
+            original,
+            annotations: description.annotations,
+            deleted: description.deleted,
+            history: description.history,
+            tools: description.tools,
+        })
+    }
+
+    /// Salva il progetto in path. Se la scrittura fallisce il file parziale
+    /// viene eliminato.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).map_err(|e| Error::io(path, e))?;
+        let result = self.write(BufWriter::new(file));
+        if result.is_err() {
+            let _ = std::fs::remove_file(path);
+        }
+        result.map_err(|e| with_path(e, path))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).map_err(|e| Error::io(path, e))?;
+        Self::read(BufReader::new(file)).map_err(|e| with_path(e, path))
+    }
+}
+
+/// Aggiunge il path agli errori di IO che non lo indicano
+fn with_path(e: Error, path: &Path) -> Error {
+    match e {
+        Error::Io { path: None, source } => Error::Io {
+            path: Some(path.to_path_buf()),
+            source,
+        },
+        e => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::{FillStyle, FontWeight, TextFont};
+    use crate::color_picker::ToolColor;
+
+    fn project() -> Project {
+        let rectangle = Annotation::Rectangle {
+            color: [1.0, 0.0, 0.0, 1.0],
+            fill_style: FillStyle::Stroke(2.0),
+            x: 10.0,
+            y: 20.0,
+            width: -5.0,
+            height: 30.5,
+        };
+        let text = Annotation::Text {
+            color: [0.0, 0.0, 1.0, 0.5],
+            content: String::from("Modificabile"),
+            size: 15.0,
+            x: 40.0,
+            y: 12.0,
+            font: TextFont {
+                family: Some(String::from("Tektur")),
+                weight: FontWeight::Bold,
+            },
+        };
+        Project {
+            original: image::open("tests/screenshot.png").unwrap().to_rgba8(),
+            annotations: vec![rectangle],
+            deleted: vec![(1, text)],
+            history: vec![
+                HistoryStep::Annotate,
+                HistoryStep::Crop(Crop {
+                    x: 5,
+                    y: 5,
+                    width: 100,
+                    height: 80,
+                }),
+                HistoryStep::Annotate,
+                HistoryStep::DeleteAnnotation,
+            ],
+            tools: ToolDefaults {
+                color: ToolColor::Red,
+                ..ToolDefaults::default()
+            },
+        }
+    }
+
+    #[test]
+    fn project_round_trip() {
+        let project = project();
+        let mut buf = Cursor::new(Vec::new());
+        project.write(&mut buf).unwrap();
+        buf.set_position(0);
+
+        let read = Project::read(buf).unwrap();
+        assert_eq!(read, project);
+        assert_eq!(
+            read.crop(),
+            Some(Crop {
+                x: 5,
+                y: 5,
+                width: 100,
+                height: 80
+            })
+        );
+    }
+
+    #[test]
+    fn project_rejects_invalid_crop() {
+        let mut project = project();
+        project.history.push(HistoryStep::Crop(Crop {
+            x: project.original.width() - 10,
+            y: 0,
+            width: 20,
+            height: 10,
+        }));
+        let mut buf = Cursor::new(Vec::new());
+        project.write(&mut buf).unwrap();
+        buf.set_position(0);
+
+        assert!(matches!(Project::read(buf), Err(Error::Project(_))));
+    }
+}
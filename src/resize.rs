@@ -0,0 +1,166 @@
+//! Ridimensionamento dello screenshot con i filtri di `image::imageops`.
+//!
+//! La dimensione richiesta si riferisce alla parte visibile dello screenshot,
+//! cioè alla zona ritagliata se c'è un crop. L'originale viene scalato dello
+//! stesso fattore, così il crop resta non distruttivo e le annotazioni, che
+//! sono in coordinate dell'originale, possono essere scalate allo stesso modo.
+
+use crate::metadata::Crop;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+
+/// Lato massimo, in pixel, accettato per l'immagine ridimensionata
+pub const MAX_SIDE: u32 = 16384;
+
+/// Filtro usato per ricampionare l'immagine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResizeFilter {
+    /// Il più nitido, adatto a testi e interfacce
+    #[default]
+    Lanczos3,
+    CatmullRom,
+    /// Nessuna interpolazione, mantiene i pixel netti della pixel art
+    Nearest,
+}
+
+impl ResizeFilter {
+    pub const ALL: [ResizeFilter; 3] = [
+        ResizeFilter::Lanczos3,
+        ResizeFilter::CatmullRom,
+        ResizeFilter::Nearest,
+    ];
+}
+
+impl std::fmt::Display for ResizeFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ResizeFilter::Lanczos3 => "Lanczos3",
+            ResizeFilter::CatmullRom => "CatmullRom",
+            ResizeFilter::Nearest => "Nearest",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl From<ResizeFilter> for FilterType {
+    fn from(filter: ResizeFilter) -> Self {
+        match filter {
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+            ResizeFilter::CatmullRom => FilterType::CatmullRom,
+            ResizeFilter::Nearest => FilterType::Nearest,
+        }
+    }
+}
+
+/// Modo in cui viene indicata la nuova dimensione
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResizeMode {
+    /// Percentuale della dimensione attuale
+    Percent(f32),
+    /// Larghezza e altezza esatte
+    Exact { width: u32, height: u32 },
+    /// Larghezza massima: le immagini più strette non vengono ingrandite
+    MaxWidth(u32),
+}
+
+impl ResizeMode {
+    /// Nuova dimensione di un'immagine di width x height pixel, None se
+    /// non è valida o troppo grande
+    pub fn target(&self, width: u32, height: u32) -> Option<(u32, u32)> {
+        let scaled = |factor: f32| {
+            (
+                (width as f32 * factor).round() as u32,
+                (height as f32 * factor).round() as u32,
+            )
+        };
+        let (w, h) = match *self {
+            ResizeMode::Percent(percent) if percent > 0.0 => scaled(percent / 100.0),
+            ResizeMode::Percent(_) => return None,
+            ResizeMode::Exact { width, height } => (width, height),
+            ResizeMode::MaxWidth(max) if width > max => scaled(max as f32 / width as f32),
+            ResizeMode::MaxWidth(_) => (width, height),
+        };
+        (w > 0 && h > 0 && w <= MAX_SIDE && h <= MAX_SIDE).then_some((w, h))
+    }
+}
+
+/// Ricampiona l'immagine alla dimensione indicata
+pub fn resize(image: &RgbaImage, width: u32, height: u32, filter: ResizeFilter) -> RgbaImage {
+    imageops::resize(image, width, height, filter.into())
+}
+
+/// Scala la zona ritagliata di sx in orizzontale e sy in verticale,
+/// mantenendola dentro un'immagine di width x height pixel
+pub fn scale_crop(crop: Crop, sx: f32, sy: f32, width: u32, height: u32) -> Crop {
+    let w = ((crop.width as f32 * sx).round() as u32).clamp(1, width);
+    let h = ((crop.height as f32 * sy).round() as u32).clamp(1, height);
+    Crop {
+        x: ((crop.x as f32 * sx).round() as u32).min(width - w),
+        y: ((crop.y as f32 * sy).round() as u32).min(height - h),
+        width: w,
+        height: h,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn resize_targets() {
+        assert_eq!(
+            ResizeMode::Percent(50.0).target(2880, 1800),
+            Some((1440, 900))
+        );
+        assert_eq!(ResizeMode::Percent(0.0).target(2880, 1800), None);
+        assert_eq!(
+            ResizeMode::Exact {
+                width: 800,
+                height: 0
+            }
+            .target(2880, 1800),
+            None
+        );
+        assert_eq!(
+            ResizeMode::MaxWidth(960).target(2880, 1800),
+            Some((960, 600))
+        );
+        assert_eq!(ResizeMode::MaxWidth(960).target(640, 400), Some((640, 400)));
+        assert_eq!(ResizeMode::Percent(1000.0).target(2880, 1800), None);
+    }
+
+    #[test]
+    fn resize_image_and_crop() {
+        let mut image = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 255, 255]));
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        let result = resize(&image, 8, 4, ResizeFilter::Nearest);
+        assert_eq!(result.dimensions(), (8, 4));
+        assert_eq!(result.get_pixel(1, 1), &Rgba([255, 0, 0, 255]));
+        assert_eq!(result.get_pixel(2, 1), &Rgba([0, 0, 255, 255]));
+
+        let crop = Crop {
+            x: 100,
+            y: 50,
+            width: 300,
+            height: 201,
+        };
+        let scaled = scale_crop(crop, 0.5, 0.5, 250, 150);
+        assert_eq!(
+            scaled,
+            Crop {
+                x: 50,
+                y: 25,
+                width: 150,
+                height: 101
+            }
+        );
+        //Gli arrotondamenti non possono portare il crop fuori dall'immagine
+        let scaled = scale_crop(crop, 0.5, 0.5, 190, 120);
+        assert_eq!((scaled.x, scaled.width), (40, 150));
+        assert_eq!((scaled.y, scaled.height), (19, 101));
+    }
+}
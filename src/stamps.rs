@@ -0,0 +1,161 @@
+//! Libreria dei timbri: immagini Png e Svg (un logo, un'emoji, un "BUG")
+//! che lo strumento Image appoggia sullo screenshot.
+//!
+//! I timbri sono i file contenuti nella cartella `stamps` della cartella di
+//! configurazione, o in quella scelta nelle impostazioni. Gli Svg vengono
+//! rasterizzati al caricamento, così nell'editor e nell'esportazione sono
+//! immagini come le altre. I loro testi vanno convertiti in tracciati,
+//! perché non viene caricato alcun font.
+
+use crate::config::config_dir;
+use crate::{Error, Result};
+use image::RgbaImage;
+use resvg::usvg::{self, TreeParsing};
+use std::fmt::Formatter;
+use std::path::{Path, PathBuf};
+
+/// Nome della cartella dei timbri dentro la cartella di configurazione
+pub const STAMPS_DIR: &str = "stamps";
+/// Estensioni dei file riconosciuti come timbri
+pub const STAMP_EXTENSIONS: [&str; 2] = ["png", "svg"];
+/// Lato più lungo, in pixel, a cui vengono rasterizzati i timbri Svg
+pub const SVG_SIZE: u32 = 256;
+
+/// Cartella di default dei timbri
+pub fn stamps_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join(STAMPS_DIR))
+}
+
+/// Un timbro della libreria, non ancora caricato
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamp {
+    /// Nome del file senza estensione, mostrato nell'interfaccia
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl std::fmt::Display for Stamp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+impl Stamp {
+    /// Carica il timbro come immagine Rgba
+    pub fn load(&self) -> Result<RgbaImage> {
+        load_image(&self.path)
+    }
+}
+
+/// Carica un Png o un Svg come immagine Rgba; gli Svg vengono rasterizzati
+/// con il lato più lungo di [`SVG_SIZE`] pixel
+pub fn load_image(path: &Path) -> Result<RgbaImage> {
+    if is_svg(path) {
+        let data = std::fs::read(path).map_err(|e| Error::io(path, e))?;
+        render_svg(&data, SVG_SIZE).map_err(|e| Error::Render(format!("{}: {}", path.display(), e)))
+    } else {
+        Ok(image::open(path)?.to_rgba8())
+    }
+}
+
+/// Timbri contenuti nella cartella, in ordine alfabetico. Una cartella che
+/// non esiste è una libreria vuota.
+pub fn list_stamps(dir: &Path) -> Result<Vec<Stamp>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::io(dir, e)),
+    };
+    let mut stamps = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| Error::io(dir, e))?.path();
+        let supported = path.extension().is_some_and(|e| {
+            STAMP_EXTENSIONS
+                .iter()
+                .any(|s| e.to_string_lossy().eq_ignore_ascii_case(s))
+        });
+        if path.is_file() && supported {
+            let name = path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            stamps.push(Stamp { name, path });
+        }
+    }
+    stamps.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(stamps)
+}
+
+fn is_svg(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case("svg"))
+}
+
+/// Rasterizza un Svg in modo che il lato più lungo misuri size pixel
+fn render_svg(data: &[u8], size: u32) -> std::result::Result<RgbaImage, String> {
+    let tree = usvg::Tree::from_data(data, &usvg::Options::default()).map_err(|e| e.to_string())?;
+    let tree = resvg::Tree::from_usvg(&tree);
+    let (width, height) = (tree.size.width() as f32, tree.size.height() as f32);
+    if !(width > 0.0 && height > 0.0) {
+        return Err(String::from("the image is empty"));
+    }
+
+    let scale = size as f32 / width.max(height);
+    let mut pixmap = tiny_skia::Pixmap::new(
+        ((width * scale).round() as u32).max(1),
+        ((height * scale).round() as u32).max(1),
+    )
+    .ok_or_else(|| String::from("the image is too large"))?;
+    tree.render(
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    //tiny-skia lavora con l'alpha premoltiplicato
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+    RgbaImage::from_raw(pixmap.width(), pixmap.height(), pixels)
+        .ok_or_else(|| String::from("invalid image size"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+    use image::Rgba;
+
+    #[test]
+    fn stamp_library() {
+        let dir = temp_dir("stamps");
+        assert!(list_stamps(&dir).unwrap().is_empty());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        RgbaImage::from_pixel(3, 2, Rgba([0, 0, 255, 128]))
+            .save(dir.join("logo.png"))
+            .unwrap();
+        std::fs::write(
+            dir.join("bug.SVG"),
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10"><rect width="20" height="10" fill="#ff0000"/></svg>"##,
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a stamp").unwrap();
+
+        let stamps = list_stamps(&dir).unwrap();
+        let names: Vec<&str> = stamps.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["bug", "logo"]);
+
+        let bug = stamps[0].load().unwrap();
+        assert_eq!(bug.dimensions(), (SVG_SIZE, SVG_SIZE / 2));
+        assert_eq!(bug.get_pixel(10, 10), &Rgba([255, 0, 0, 255]));
+        let logo = stamps[1].load().unwrap();
+        assert_eq!(logo.get_pixel(0, 0), &Rgba([0, 0, 255, 128]));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
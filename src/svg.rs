@@ -0,0 +1,433 @@
+//! Esportazione in SVG, con le annotazioni come elementi vettoriali.
+//!
+//! Lo screenshot viene incluso come `<image>` Png codificato in base64 e le
+//! annotazioni diventano `<rect>`, `<ellipse>`, `<path>`, `<polyline>`,
+//! `<text>` e `<image>` in coordinate dell'immagine originale: un eventuale crop è
+//! espresso dal viewBox del documento. Le dimensioni riproducono quelle
+//! usate da [`crate::canvas_to_pixels::draw_on_buffer`].
+
+use crate::annotations::{Annotation, FillStyle, TextFont};
+use crate::metadata::Crop;
+use crate::{Error, Result};
+use base64::Engine;
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::io::Cursor;
+use std::path::Path;
+
+/// Fattore tra lo spessore e la dimensione del testo degli strumenti e la
+/// loro misura in pixel dell'immagine
+pub(crate) const SIZE_SCALE: f32 = 25.0;
+/// Lunghezza delle alette delle frecce rispetto al lato più corto dell'immagine
+const ARROW_HEAD: f32 = 0.05;
+/// Distanza tra le righe di un testo, rispetto alla dimensione del font
+const LINE_HEIGHT: f32 = 1.2;
+
+/// Impostazioni dell'esportazione in SVG
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SvgOptions {
+    /// Se scrivere solo le annotazioni, senza lo screenshot, per
+    /// sovrapporle all'immagine già pubblicata
+    pub annotations_only: bool,
+}
+
+/// Genera il documento SVG dello screenshot con le sue annotazioni.
+/// image è lo screenshot originale, senza crop.
+pub fn to_svg(
+    image: &RgbaImage,
+    annotations: &[Annotation],
+    crop: Option<Crop>,
+    options: &SvgOptions,
+) -> Result<String> {
+    let crop = crop.unwrap_or(Crop {
+        x: 0,
+        y: 0,
+        width: image.width(),
+        height: image.height(),
+    });
+    if crop.width == 0 || crop.height == 0 {
+        return Err(Error::Render(String::from("the screenshot is empty")));
+    }
+
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        crop.width, crop.height, crop.x, crop.y, crop.width, crop.height
+    );
+
+    //Viene incluso solo il ritaglio, per non pubblicare le zone scartate
+    if !options.annotations_only {
+        let mut original = image.clone();
+        let visible = imageops::crop(&mut original, crop.x, crop.y, crop.width, crop.height);
+        let _ = writeln!(
+            svg,
+            r#"<image x="{}" y="{}" width="{}" height="{}" xlink:href="data:image/png;base64,{}"/>"#,
+            crop.x,
+            crop.y,
+            crop.width,
+            crop.height,
+            png_base64(&visible.to_image())?
+        );
+    }
+
+    svg.push_str(r#"<g stroke-linecap="round" stroke-linejoin="round">"#);
+    svg.push('\n');
+    let arrow_head = arrow_head_length(image.width() as f32, image.height() as f32);
+    for annotation in annotations {
+        write_annotation(&mut svg, annotation, arrow_head)?;
+    }
+    svg.push_str("</g>\n</svg>\n");
+    Ok(svg)
+}
+
+/// Come [`to_svg`], scrivendo il documento in path
+pub fn save_svg(
+    image: &RgbaImage,
+    annotations: &[Annotation],
+    crop: Option<Crop>,
+    options: &SvgOptions,
+    path: &Path,
+) -> Result<()> {
+    let svg = to_svg(image, annotations, crop, options)?;
+    std::fs::write(path, svg).map_err(|e| Error::io(path, e))
+}
+
+/// Codifica l'immagine come Png in base64, da usare in un URI data:
+fn png_base64(image: &RgbaImage) -> Result<String> {
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, image::ImageFormat::Png)?;
+    Ok(base64::engine::general_purpose::STANDARD.encode(png.get_ref()))
+}
+
+fn write_annotation(svg: &mut String, annotation: &Annotation, arrow_head: f32) -> Result<()> {
+    let _ = match annotation {
+        Annotation::Rectangle {
+            color,
+            fill_style,
+            x,
+            y,
+            width,
+            height,
+        } => writeln!(
+            svg,
+            r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+            x.min(x + width),
+            y.min(y + height),
+            width.abs(),
+            height.abs(),
+            paint(color, fill_style)
+        ),
+        Annotation::Ellipse {
+            color,
+            fill_style,
+            x,
+            y,
+            width,
+            height,
+        } => writeln!(
+            svg,
+            r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" {}/>"#,
+            x + width / 2.0,
+            y + height / 2.0,
+            width.abs() / 2.0,
+            height.abs() / 2.0,
+            paint(color, fill_style)
+        ),
+        Annotation::Arrow {
+            color,
+            stroke_width,
+            begin: [bx, by],
+            end: [ex, ey],
+        } => {
+            let mut d = format!("M{} {} L{} {}", bx, by, ex, ey);
+            if let Some([[ux, uy], [lx, ly]]) = arrow_wings([*bx, *by], [*ex, *ey], arrow_head) {
+                let _ = write!(d, " L{} {} M{} {} L{} {}", ux, uy, ex, ey, lx, ly);
+            }
+            writeln!(
+                svg,
+                r#"<path d="{}" {}/>"#,
+                d,
+                paint(color, &FillStyle::Stroke(*stroke_width))
+            )
+        }
+        Annotation::Text {
+            color,
+            content,
+            size,
+            x,
+            y,
+            font,
+        } => write_text(svg, color, content, size * SIZE_SCALE, *x, *y, font),
+        Annotation::FreeHand {
+            color,
+            stroke_width,
+            points,
+        } => {
+            let points = points
+                .iter()
+                .map(|[x, y]| format!("{},{}", x, y))
+                .collect::<Vec<_>>()
+                .join(" ");
+            writeln!(
+                svg,
+                r#"<polyline points="{}" {}/>"#,
+                points,
+                paint(color, &FillStyle::Stroke(*stroke_width))
+            )
+        }
+        Annotation::Image {
+            x,
+            y,
+            width,
+            height,
+            image,
+            opacity,
+        } => {
+            let opacity = if *opacity < 1.0 {
+                format!(r#" opacity="{}""#, opacity.max(0.0))
+            } else {
+                String::new()
+            };
+            writeln!(
+                svg,
+                r#"<image x="{}" y="{}" width="{}" height="{}" preserveAspectRatio="none"{} xlink:href="data:image/png;base64,{}"/>"#,
+                x,
+                y,
+                width,
+                height,
+                opacity,
+                png_base64(image)?
+            )
+        }
+    };
+    Ok(())
+}
+
+/// Lunghezza in pixel delle alette delle frecce su un'immagine width x height.
+/// Dipende dal lato più corto, e non dall'altezza, perché ruotando l'immagine
+/// le frecce non devono cambiare: sulle immagini verticali le alette sono
+/// quindi più corte di quanto lo fossero prima delle rotazioni.
+pub(crate) fn arrow_head_length(width: f32, height: f32) -> f32 {
+    ARROW_HEAD * width.min(height)
+}
+
+/// Estremi delle due alette, lunghe length, della freccia da begin a end.
+/// None se la freccia ha lunghezza nulla.
+pub(crate) fn arrow_wings(
+    begin: [f32; 2],
+    end: [f32; 2],
+    length: f32,
+) -> Option<[[f32; 2]; 2]> {
+    let ([bx, by], [ex, ey]) = (begin, end);
+    if bx == ex && by == ey {
+        return None;
+    }
+    let angle = (ey - by).atan2(ex - bx);
+    let wing = std::f32::consts::FRAC_PI_2 + std::f32::consts::FRAC_PI_3;
+    Some([angle + wing, angle - wing].map(|a| [ex + a.cos() * length, ey + a.sin() * length]))
+}
+
+/// Scrive un testo centrato in (x, y), con una riga per ogni a capo
+fn write_text(
+    svg: &mut String,
+    color: &[f32; 4],
+    content: &str,
+    size: f32,
+    x: f32,
+    y: f32,
+    font: &TextFont,
+) -> std::fmt::Result {
+    let family = font
+        .family
+        .as_deref()
+        .map_or(String::from("sans-serif"), |f| {
+            format!("'{}', sans-serif", escape(f))
+        });
+    write!(
+        svg,
+        r#"<text x="{}" y="{}" font-size="{}" font-family="{}" font-weight="{}" text-anchor="middle" dominant-baseline="central" {}>"#,
+        x,
+        y,
+        size,
+        family,
+        font.weight.css_weight(),
+        paint(color, &FillStyle::Fill)
+    )?;
+    let lines: Vec<&str> = content.lines().collect();
+    if lines.len() <= 1 {
+        svg.push_str(&escape(content));
+    } else {
+        let first = -((lines.len() - 1) as f32) * LINE_HEIGHT / 2.0;
+        for (i, line) in lines.iter().enumerate() {
+            let dy = if i == 0 { first } else { LINE_HEIGHT };
+            write!(
+                svg,
+                r#"<tspan x="{}" dy="{}em">{}</tspan>"#,
+                x,
+                dy,
+                escape(line)
+            )?;
+        }
+    }
+    svg.push_str("</text>\n");
+    Ok(())
+}
+
+/// Attributi di riempimento o di contorno di una forma
+fn paint(color: &[f32; 4], fill_style: &FillStyle) -> String {
+    let [r, g, b, a] = color.map(|c| c.clamp(0.0, 1.0));
+    let hex = format!(
+        "#{:02x}{:02x}{:02x}",
+        (r * 255.0).round() as u8,
+        (g * 255.0).round() as u8,
+        (b * 255.0).round() as u8
+    );
+    let (mut attributes, opacity) = match fill_style {
+        FillStyle::Fill => (format!(r#"fill="{}""#, hex), "fill-opacity"),
+        FillStyle::Stroke(width) => (
+            format!(
+                r#"fill="none" stroke="{}" stroke-width="{}""#,
+                hex,
+                width * SIZE_SCALE
+            ),
+            "stroke-opacity",
+        ),
+    };
+    if a < 1.0 {
+        let _ = write!(attributes, r#" {}="{}""#, opacity, a);
+    }
+    attributes
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotations::FontWeight;
+
+    fn annotations() -> Vec<Annotation> {
+        vec![
+            Annotation::Rectangle {
+                color: [1.0, 0.0, 0.0, 1.0],
+                fill_style: FillStyle::Stroke(0.1),
+                x: 30.0,
+                y: 20.0,
+                width: -10.0,
+                height: 5.0,
+            },
+            Annotation::Ellipse {
+                color: [0.0, 0.0, 1.0, 0.5],
+                fill_style: FillStyle::Fill,
+                x: 10.0,
+                y: 10.0,
+                width: 20.0,
+                height: 8.0,
+            },
+            Annotation::Arrow {
+                color: [0.0, 1.0, 0.0, 1.0],
+                stroke_width: 0.04,
+                begin: [0.0, 0.0],
+                end: [40.0, 0.0],
+            },
+            Annotation::Text {
+                color: [1.0, 1.0, 0.0, 1.0],
+                content: String::from("<Bug> & co"),
+                size: 0.5,
+                x: 32.0,
+                y: 16.0,
+                font: TextFont {
+                    family: Some(String::from("Tektur")),
+                    weight: FontWeight::Bold,
+                },
+            },
+            Annotation::FreeHand {
+                color: [1.0, 0.5, 0.5, 0.4],
+                stroke_width: 0.12,
+                points: vec![[1.0, 2.0], [3.5, 4.0]],
+            },
+        ]
+    }
+
+    #[test]
+    fn svg_annotations() {
+        let image = RgbaImage::from_pixel(64, 32, image::Rgba([40, 90, 200, 255]));
+        let mut annotations = annotations();
+        annotations.push(Annotation::Image {
+            x: 4.0,
+            y: 6.0,
+            width: 8.0,
+            height: 2.0,
+            image: std::sync::Arc::new(RgbaImage::new(4, 1)),
+            opacity: 0.5,
+        });
+        let svg = to_svg(&image, &annotations, None, &SvgOptions::default()).unwrap();
+
+        assert!(svg.contains(r#"viewBox="0 0 64 32""#));
+        assert!(svg.contains(r#"xlink:href="data:image/png;base64,"#));
+        assert!(svg.contains(
+            r##"<rect x="20" y="20" width="10" height="5" fill="none" stroke="#ff0000" stroke-width="2.5"/>"##
+        ));
+        assert!(svg.contains(
+            r##"<ellipse cx="20" cy="14" rx="10" ry="4" fill="#0000ff" fill-opacity="0.5"/>"##
+        ));
+        assert!(svg.contains(r#"<path d="M0 0 L40 0 L"#));
+        assert!(svg.contains(r#"font-size="12.5""#));
+        assert!(svg.contains(r#"font-weight="700""#));
+        assert!(svg.contains("&lt;Bug&gt; &amp; co</text>"));
+        assert!(svg.contains(r#"<polyline points="1,2 3.5,4""#));
+        assert!(svg.contains(
+            r#"<image x="4" y="6" width="8" height="2" preserveAspectRatio="none" opacity="0.5""#
+        ));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn svg_annotations_only_cropped() {
+        let image = RgbaImage::from_pixel(64, 32, image::Rgba([40, 90, 200, 255]));
+        let crop = Crop {
+            x: 8,
+            y: 4,
+            width: 40,
+            height: 20,
+        };
+        let options = SvgOptions {
+            annotations_only: true,
+        };
+        let svg = to_svg(&image, &annotations(), Some(crop), &options).unwrap();
+
+        assert!(svg.contains(r#"width="40" height="20" viewBox="8 4 40 20""#));
+        assert!(!svg.contains("<image"));
+        assert!(svg.contains("<rect"));
+    }
+
+    #[test]
+    fn arrow_head_on_portrait_images() {
+        //Su un'immagine verticale le alette dipendono dalla larghezza, così
+        //ruotandola di 90° restano uguali
+        assert!((arrow_head_length(1000.0, 600.0) - 30.0).abs() < 1e-3);
+        assert_eq!(arrow_head_length(600.0, 1000.0), arrow_head_length(1000.0, 600.0));
+
+        let [upper, lower] = arrow_wings([0.0, 0.0], [100.0, 0.0], 30.0).unwrap();
+        for [x, y] in [upper, lower] {
+            assert!(((x - 100.0).hypot(y) - 30.0).abs() < 1e-3);
+        }
+        assert_eq!(arrow_wings([5.0, 5.0], [5.0, 5.0], 30.0), None);
+    }
+}
@@ -0,0 +1,12 @@
+//! Funzioni di supporto condivise dai test dei moduli.
+
+use crate::config::APP_NAME;
+use std::path::PathBuf;
+
+/// Restituisce una cartella temporanea vuota, diversa per ogni test e per
+/// ogni processo. La cartella non viene creata.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{}_{}_{}", APP_NAME, name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
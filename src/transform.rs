@@ -0,0 +1,165 @@
+//! Rotazioni di 90°, 180° e 270° e ribaltamenti dell'intero documento.
+//!
+//! Le stesse trasformazioni vengono applicate ai pixel dello screenshot, alla
+//! geometria delle annotazioni e alle zone ritagliate, così restano allineati.
+//! Ogni trasformazione ha un'inversa esatta, usata per annullarla.
+
+use crate::metadata::Crop;
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+
+/// Trasformazione dell'intero documento
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transform {
+    /// Rotazione di 90° in senso orario
+    Rotate90,
+    Rotate180,
+    /// Rotazione di 90° in senso antiorario
+    Rotate270,
+    /// Ribaltamento da sinistra a destra
+    FlipHorizontal,
+    /// Ribaltamento dall'alto in basso
+    FlipVertical,
+}
+
+impl Transform {
+    pub const ALL: [Transform; 5] = [
+        Transform::Rotate90,
+        Transform::Rotate180,
+        Transform::Rotate270,
+        Transform::FlipHorizontal,
+        Transform::FlipVertical,
+    ];
+
+    /// Trasformazione che annulla questa
+    pub fn inverse(self) -> Transform {
+        match self {
+            Transform::Rotate90 => Transform::Rotate270,
+            Transform::Rotate270 => Transform::Rotate90,
+            t => t,
+        }
+    }
+
+    /// Se larghezza e altezza vengono scambiate
+    pub fn swaps_axes(self) -> bool {
+        matches!(self, Transform::Rotate90 | Transform::Rotate270)
+    }
+
+    /// Trasforma i pixel dell'immagine
+    pub fn apply(self, image: &RgbaImage) -> RgbaImage {
+        match self {
+            Transform::Rotate90 => imageops::rotate90(image),
+            Transform::Rotate180 => imageops::rotate180(image),
+            Transform::Rotate270 => imageops::rotate270(image),
+            Transform::FlipHorizontal => imageops::flip_horizontal(image),
+            Transform::FlipVertical => imageops::flip_vertical(image),
+        }
+    }
+
+    /// Trasforma un punto di un'immagine di width x height
+    pub fn point(self, [x, y]: [f32; 2], width: f32, height: f32) -> [f32; 2] {
+        match self {
+            Transform::Rotate90 => [height - y, x],
+            Transform::Rotate180 => [width - x, height - y],
+            Transform::Rotate270 => [y, width - x],
+            Transform::FlipHorizontal => [width - x, y],
+            Transform::FlipVertical => [x, height - y],
+        }
+    }
+
+    /// Trasforma il rettangolo (x, y, larghezza, altezza) di un'immagine di
+    /// width x height, restituendolo con larghezza e altezza positive
+    pub fn rect(self, rect: [f32; 4], width: f32, height: f32) -> [f32; 4] {
+        let [x, y, w, h] = rect;
+        let [x0, y0] = self.point([x, y], width, height);
+        let [x1, y1] = self.point([x + w, y + h], width, height);
+        [x0.min(x1), y0.min(y1), (x1 - x0).abs(), (y1 - y0).abs()]
+    }
+
+    /// Trasforma la zona ritagliata di un'immagine di width x height pixel
+    pub fn crop(self, crop: Crop, width: u32, height: u32) -> Crop {
+        let Crop {
+            x,
+            y,
+            width: w,
+            height: h,
+        } = crop;
+        let (x, y) = match self {
+            Transform::Rotate90 => (height - (y + h), x),
+            Transform::Rotate180 => (width - (x + w), height - (y + h)),
+            Transform::Rotate270 => (y, width - (x + w)),
+            Transform::FlipHorizontal => (width - (x + w), y),
+            Transform::FlipVertical => (x, height - (y + h)),
+        };
+        let (w, h) = if self.swaps_axes() { (h, w) } else { (w, h) };
+        Crop {
+            x,
+            y,
+            width: w,
+            height: h,
+        }
+    }
+}
+
+impl std::fmt::Display for Transform {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Transform::Rotate90 => "Rotate 90° right",
+            Transform::Rotate180 => "Rotate 180°",
+            Transform::Rotate270 => "Rotate 90° left",
+            Transform::FlipHorizontal => "Flip horizontal",
+            Transform::FlipVertical => "Flip vertical",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn transform_consistency() {
+        let (width, height) = (6, 4);
+        let crop = Crop {
+            x: 1,
+            y: 0,
+            width: 2,
+            height: 3,
+        };
+        let mut image = RgbaImage::new(width, height);
+        image.put_pixel(1, 0, Rgba([255, 0, 0, 255]));
+
+        for t in Transform::ALL {
+            let transformed = t.apply(&image);
+            let (w, h) = transformed.dimensions();
+            assert_eq!(t.swaps_axes(), (w, h) == (height, width), "{}", t);
+
+            //Il pixel rosso finisce dove finisce il centro del pixel originale
+            let [x, y] = t.point([1.5, 0.5], width as f32, height as f32);
+            assert_eq!(
+                transformed.get_pixel(x as u32, y as u32),
+                &Rgba([255, 0, 0, 255]),
+                "{}",
+                t
+            );
+
+            //Il crop segue la stessa geometria del rettangolo continuo
+            let c = t.crop(crop, width, height);
+            let r = t.rect([1.0, 0.0, 2.0, 3.0], width as f32, height as f32);
+            assert_eq!(
+                [c.x as f32, c.y as f32, c.width as f32, c.height as f32],
+                r,
+                "{}",
+                t
+            );
+            assert!(c.fits_in(w, h), "{}", t);
+
+            assert_eq!(t.inverse().apply(&transformed), image, "{}", t);
+            assert_eq!(t.inverse().crop(c, w, h), crop, "{}", t);
+        }
+    }
+}
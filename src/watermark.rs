@@ -0,0 +1,375 @@
+//! Filigrana e piè di pagina applicati in fase di esportazione.
+//!
+//! La filigrana è un testo o un logo (Png o Svg) disegnato sopra lo screenshot
+//! già annotato, in un angolo, al centro o ripetuto su tutta l'immagine. Il
+//! piè di pagina è una barra aggiunta sotto lo screenshot con l'istante della
+//! cattura e il nome della macchina. Entrambi vengono rasterizzati con
+//! [`draw_on_buffer`] dopo le annotazioni, quindi non modificano il documento.
+
+use crate::canvas_to_pixels::draw_on_buffer;
+use crate::stamps::load_image;
+use crate::{Error, Result};
+use chrono::{DateTime, FixedOffset};
+use iced::alignment::{Horizontal, Vertical};
+use iced::widget::canvas::{Cursor, Frame, Geometry, Program, Text};
+use iced::widget::image::Handle;
+use iced::{Color, Point, Rectangle, Renderer, Size, Theme};
+use iced_graphics::Primitive;
+use image::{Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fmt::Formatter;
+use std::path::PathBuf;
+
+/// Colore della barra del piè di pagina
+const FOOTER_BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+/// Altezza minima della barra del piè di pagina, in pixel
+const MIN_FOOTER_HEIGHT: u32 = 24;
+/// Larghezza media di un carattere rispetto alla dimensione del testo, usata
+/// per distanziare le ripetizioni della filigrana
+const CHAR_WIDTH: f32 = 0.6;
+
+/// Posizione della filigrana sull'immagine
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+    /// Ripetuta a scacchiera su tutta l'immagine
+    Tiled,
+}
+
+impl WatermarkPosition {
+    pub const ALL: [WatermarkPosition; 6] = [
+        WatermarkPosition::TopLeft,
+        WatermarkPosition::TopRight,
+        WatermarkPosition::BottomLeft,
+        WatermarkPosition::BottomRight,
+        WatermarkPosition::Center,
+        WatermarkPosition::Tiled,
+    ];
+}
+
+impl std::fmt::Display for WatermarkPosition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            WatermarkPosition::TopLeft => "Top left",
+            WatermarkPosition::TopRight => "Top right",
+            WatermarkPosition::BottomLeft => "Bottom left",
+            WatermarkPosition::BottomRight => "Bottom right",
+            WatermarkPosition::Center => "Center",
+            WatermarkPosition::Tiled => "Tiled",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Impostazioni della filigrana e del piè di pagina
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatermarkOptions {
+    /// Se la filigrana è attiva per default; si può cambiare a ogni salvataggio
+    pub enabled: bool,
+    /// Testo della filigrana, ignorato se è impostato un logo
+    pub text: String,
+    /// Immagine Png o Svg usata al posto del testo
+    pub logo: Option<PathBuf>,
+    pub position: WatermarkPosition,
+    /// Opacità, tra 0 e 1
+    pub opacity: f32,
+    /// Altezza della filigrana rispetto a quella dell'immagine
+    pub scale: f32,
+    /// Se aggiungere la barra con istante della cattura e nome della macchina
+    pub footer: bool,
+}
+
+impl Default for WatermarkOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            text: String::from("CONFIDENTIAL"),
+            logo: None,
+            position: WatermarkPosition::default(),
+            opacity: 0.5,
+            scale: 0.08,
+            footer: false,
+        }
+    }
+}
+
+/// Filigrana pronta da applicare, con il logo già caricato
+#[derive(Debug, Clone)]
+pub struct Watermark {
+    options: WatermarkOptions,
+    /// Logo con l'opacità già applicata e la sua dimensione
+    logo: Option<(Handle, Size)>,
+    /// Testi a sinistra e a destra del piè di pagina
+    footer: Option<(String, String)>,
+}
+
+impl Watermark {
+    /// Prepara la filigrana caricando il logo; captured_at è l'istante
+    /// mostrato nel piè di pagina
+    pub fn new(options: &WatermarkOptions, captured_at: DateTime<FixedOffset>) -> Result<Self> {
+        let logo = match &options.logo {
+            Some(path) => {
+                let mut image = load_image(path)?;
+                let opacity = options.opacity.clamp(0.0, 1.0);
+                for pixel in image.pixels_mut() {
+                    pixel[3] = (pixel[3] as f32 * opacity).round() as u8;
+                }
+                let size = Size::new(image.width() as f32, image.height() as f32);
+                Some((
+                    Handle::from_pixels(image.width(), image.height(), image.into_raw()),
+                    size,
+                ))
+            }
+            None => None,
+        };
+        let footer = options.footer.then(|| {
+            (
+                captured_at.format("%Y-%m-%d %H:%M:%S %:z").to_string(),
+                hostname().unwrap_or_default(),
+            )
+        });
+        Ok(Self {
+            options: options.clone(),
+            logo,
+            footer,
+        })
+    }
+
+    /// Aggiunge il piè di pagina sotto l'immagine e vi disegna sopra la filigrana
+    pub fn apply(&self, image: &RgbaImage) -> Result<RgbaImage> {
+        let area = Size::new(image.width() as f32, image.height() as f32);
+        let image = match self.footer {
+            Some(_) => {
+                let height = footer_height(image.height());
+                let mut extended = RgbaImage::from_pixel(
+                    image.width(),
+                    image.height() + height,
+                    FOOTER_BACKGROUND,
+                );
+                image::imageops::replace(&mut extended, image, 0, 0);
+                extended
+            }
+            None => image.clone(),
+        };
+        let stage = Stage {
+            watermark: self,
+            area,
+        };
+        draw_on_buffer::<Stage, ()>(&stage, &image, None)
+            .ok_or_else(|| Error::Render(String::from("the watermarked image has an invalid size")))
+    }
+}
+
+/// Altezza della barra del piè di pagina per un'immagine alta height pixel
+fn footer_height(height: u32) -> u32 {
+    (height / 25).max(MIN_FOOTER_HEIGHT)
+}
+
+/// Nome della macchina, None se non è possibile determinarlo
+pub fn hostname() -> Option<String> {
+    #[cfg(windows)]
+    let name = std::env::var("COMPUTERNAME").ok();
+    #[cfg(not(windows))]
+    let name = ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| std::fs::read_to_string(path).ok())
+        .or_else(|| std::env::var("HOSTNAME").ok());
+    name.map(|n| n.trim().to_string()).filter(|n| !n.is_empty())
+}
+
+/// Canvas che disegna filigrana e testi del piè di pagina. area è la zona
+/// occupata dallo screenshot, sopra l'eventuale barra.
+struct Stage<'a> {
+    watermark: &'a Watermark,
+    area: Size,
+}
+
+impl Stage<'_> {
+    /// Punti in cui disegnare una filigrana grande size, con l'allineamento
+    /// orizzontale a cui si riferiscono; il punto è sempre centrato in verticale
+    fn anchors(&self, size: Size, margin: f32) -> Vec<(Point, Horizontal)> {
+        let Size { width, height } = self.area;
+        let top = margin + size.height / 2.0;
+        let bottom = height - margin - size.height / 2.0;
+        match self.watermark.options.position {
+            WatermarkPosition::TopLeft => vec![(Point::new(margin, top), Horizontal::Left)],
+            WatermarkPosition::TopRight => {
+                vec![(Point::new(width - margin, top), Horizontal::Right)]
+            }
+            WatermarkPosition::BottomLeft => vec![(Point::new(margin, bottom), Horizontal::Left)],
+            WatermarkPosition::BottomRight => {
+                vec![(Point::new(width - margin, bottom), Horizontal::Right)]
+            }
+            WatermarkPosition::Center => {
+                vec![(Point::new(width / 2.0, height / 2.0), Horizontal::Center)]
+            }
+            //Righe sfalsate di mezzo passo, come una scacchiera
+            WatermarkPosition::Tiled => {
+                let step_x = size.width + size.height * 2.0;
+                let step_y = size.height * 3.0;
+                let mut anchors = Vec::new();
+                let mut y = size.height / 2.0;
+                let mut row = 0;
+                while y - size.height / 2.0 < height {
+                    let mut x = if row % 2 == 0 { 0.0 } else { -step_x / 2.0 };
+                    while x < width {
+                        anchors.push((Point::new(x, y), Horizontal::Left));
+                        x += step_x;
+                    }
+                    y += step_y;
+                    row += 1;
+                }
+                anchors
+            }
+        }
+    }
+
+    fn draw_watermark(&self, frame: &mut Frame, geometries: &mut Vec<Geometry>) {
+        let options = &self.watermark.options;
+        let unit = (self.area.height * options.scale).max(1.0);
+        let margin = unit / 2.0;
+
+        if let Some((handle, logo)) = &self.watermark.logo {
+            let size = Size::new(logo.width * unit / logo.height, unit);
+            for (point, align) in self.anchors(size, margin) {
+                let x = match align {
+                    Horizontal::Left => point.x,
+                    Horizontal::Center => point.x - size.width / 2.0,
+                    Horizontal::Right => point.x - size.width,
+                };
+                geometries.push(Geometry::from_primitive(Primitive::Clip {
+                    bounds: Rectangle::with_size(self.area),
+                    content: Box::new(Primitive::Image {
+                        handle: handle.clone(),
+                        bounds: Rectangle::new(Point::new(x, point.y - size.height / 2.0), size),
+                    }),
+                }));
+            }
+        } else if !options.text.trim().is_empty() {
+            let width = options.text.chars().count() as f32 * unit * CHAR_WIDTH;
+            let opacity = options.opacity.clamp(0.0, 1.0);
+            //Un'ombra sfalsata rende leggibile il testo anche su sfondi chiari
+            let shadow = unit / 20.0;
+            for (point, align) in self.anchors(Size::new(width, unit), margin) {
+                for (offset, color) in [
+                    (shadow, Color::from_rgba(0.0, 0.0, 0.0, opacity / 2.0)),
+                    (0.0, Color::from_rgba(1.0, 1.0, 1.0, opacity)),
+                ] {
+                    frame.fill_text(Text {
+                        content: options.text.clone(),
+                        position: Point::new(point.x + offset, point.y + offset),
+                        color,
+                        size: unit,
+                        horizontal_alignment: align,
+                        vertical_alignment: Vertical::Center,
+                        ..Text::default()
+                    });
+                }
+            }
+        }
+    }
+
+    fn draw_footer(&self, frame: &mut Frame) {
+        let Some((left, right)) = &self.watermark.footer else {
+            return;
+        };
+        let height = frame.height() - self.area.height;
+        let size = height * 0.55;
+        let y = self.area.height + height / 2.0;
+        let padding = height / 2.0;
+        for (content, x, align) in [
+            (left, padding, Horizontal::Left),
+            (right, frame.width() - padding, Horizontal::Right),
+        ] {
+            frame.fill_text(Text {
+                content: content.clone(),
+                position: Point::new(x, y),
+                color: Color::WHITE,
+                size,
+                horizontal_alignment: align,
+                vertical_alignment: Vertical::Center,
+                ..Text::default()
+            });
+        }
+    }
+}
+
+impl Program<(), Renderer> for Stage<'_> {
+    type State = ();
+
+    fn draw(
+        &self,
+        _state: &Self::State,
+        renderer: &Renderer,
+        _theme: &Theme,
+        bounds: Rectangle,
+        _cursor: Cursor,
+    ) -> Vec<Geometry> {
+        let mut frame = Frame::new(renderer, bounds.size());
+        let mut geometries = Vec::new();
+        self.draw_watermark(&mut frame, &mut geometries);
+        self.draw_footer(&mut frame);
+        geometries.insert(0, frame.into_geometry());
+        geometries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::temp_dir;
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn watermark_logo_and_footer() {
+        let dir = temp_dir("watermark");
+        std::fs::create_dir_all(&dir).unwrap();
+        let logo = dir.join("logo.png");
+        RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]))
+            .save(&logo)
+            .unwrap();
+        let captured_at = Local
+            .with_ymd_and_hms(2023, 9, 1, 10, 30, 0)
+            .unwrap()
+            .into();
+        let screenshot = RgbaImage::from_pixel(200, 100, Rgba([255, 255, 255, 255]));
+
+        let options = WatermarkOptions {
+            logo: Some(logo),
+            opacity: 1.0,
+            scale: 0.2,
+            ..WatermarkOptions::default()
+        };
+        let result = Watermark::new(&options, captured_at)
+            .unwrap()
+            .apply(&screenshot)
+            .unwrap();
+        assert_eq!(result.dimensions(), (200, 100));
+        //Logo di 20x20 pixel a 10 pixel dall'angolo in basso a destra
+        assert_eq!(result.get_pixel(180, 80), &Rgba([255, 0, 0, 255]));
+        assert_eq!(result.get_pixel(20, 20), &Rgba([255, 255, 255, 255]));
+
+        let options = WatermarkOptions {
+            position: WatermarkPosition::TopLeft,
+            footer: true,
+            ..options
+        };
+        let result = Watermark::new(&options, captured_at)
+            .unwrap()
+            .apply(&screenshot)
+            .unwrap();
+        assert_eq!(result.dimensions(), (200, 100 + MIN_FOOTER_HEIGHT));
+        assert_eq!(result.get_pixel(20, 20), &Rgba([255, 0, 0, 255]));
+        assert_eq!(result.get_pixel(180, 80), &Rgba([255, 255, 255, 255]));
+        assert_eq!(result.get_pixel(100, 100), &FOOTER_BACKGROUND);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}